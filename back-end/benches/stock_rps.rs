@@ -0,0 +1,62 @@
+//! 全市场 RPS 计算的性能基准
+//! 运行：cargo bench --bench stock_rps
+//! 构造 5000 只股票、121 个交易日的价格数据，统计 rank_stock_rps 的耗时
+use back_end::db::stock_info::StockPriceInfo;
+use back_end::stock_lib::get_stock_rps_list::rank_stock_rps;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::time::Instant;
+
+const STOCK_COUNT: usize = 5000;
+const TRADE_DAYS: usize = 121;
+const ROUNDS: usize = 20;
+
+fn mock_stock_map() -> HashMap<String, Vec<StockPriceInfo>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..STOCK_COUNT)
+        .map(|code| {
+            let ts_code = format!("{:06}.SZ", code);
+            let mut close = rng.gen_range(5.0..50.0);
+            let stock_list = (0..TRADE_DAYS)
+                .map(|day| {
+                    let pre_close = close;
+                    close *= 1.0 + rng.gen_range(-0.05..0.05);
+                    StockPriceInfo {
+                        ts_code: ts_code.clone(),
+                        trade_date: Some(format!("2024{:04}", day)),
+                        open: Some(pre_close),
+                        close: Some(close),
+                        high: Some(close.max(pre_close)),
+                        low: Some(close.min(pre_close)),
+                        pre_close: Some(pre_close),
+                        vol: Some(10000.0),
+                        change: Some(close - pre_close),
+                        pct_chg: Some((close - pre_close) / pre_close * 100.0),
+                        amount: Some(close * 10000.0),
+                    }
+                })
+                .collect();
+            (ts_code, stock_list)
+        })
+        .collect()
+}
+
+fn main() {
+    let stock_map = mock_stock_map();
+    // 预热，避免把 rayon 线程池的初始化算进去
+    rank_stock_rps(&stock_map, "20240120", 300);
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let stock_rps = rank_stock_rps(&stock_map, "20240120", 300);
+        assert_eq!(stock_rps.len(), 300);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "rank_stock_rps: {} stocks x {} days, {:?}/iter ({} rounds)",
+        STOCK_COUNT,
+        TRADE_DAYS,
+        elapsed / ROUNDS as u32,
+        ROUNDS
+    );
+}
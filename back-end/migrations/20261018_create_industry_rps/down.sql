@@ -0,0 +1 @@
+DROP TABLE industry_rps_values;
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE industry_rps_values (
+    industry VARCHAR(100),             -- 所属行业
+    trade_date CHAR(8),                -- 交易日期
+    rps DOUBLE,                        -- 行业 RPS 值
+    increase DOUBLE,                   -- 行业指定时间涨幅（成分股等权或成交额加权）
+    stock_count INT,                   -- 参与计算的成分股数量
+    PRIMARY KEY (industry, trade_date) -- 复合主键
+);
@@ -0,0 +1 @@
+DROP TABLE index_daily;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE index_daily (
+    ts_code CHAR(20),             -- 指数代码，如 000300.SH
+    trade_date CHAR(8),           -- 交易日期
+    open DOUBLE,                  -- 开盘点位，可以为空
+    close DOUBLE,                 -- 收盘点位，可以为空
+    high DOUBLE,                  -- 最高点位，可以为空
+    low DOUBLE,                   -- 最低点位，可以为空
+    pre_close DOUBLE NULL,        -- 昨日收盘点位，可以为空
+    vol DOUBLE,                   -- 成交量（手），可以为空
+    `change` DOUBLE NULL,         -- 涨跌点，可以为空
+    pct_chg DOUBLE NULL,          -- 涨跌幅，可以为空
+    amount DOUBLE NULL,           -- 成交额（千元），可以为空
+    PRIMARY KEY (ts_code, trade_date) -- 复合主键
+);
@@ -0,0 +1,3 @@
+DROP TABLE backtest_trade;
+DROP TABLE backtest_equity;
+DROP TABLE backtest_run;
\ No newline at end of file
@@ -0,0 +1,45 @@
+CREATE TABLE backtest_run (
+    id BIGINT AUTO_INCREMENT PRIMARY KEY,
+    codes TEXT NOT NULL,              -- 股票代码，多个代码用逗号分隔
+    strategy VARCHAR(64) NOT NULL,    -- 策略名称
+    seed BIGINT UNSIGNED NOT NULL,    -- 参数搜索的随机数种子
+    iterations BIGINT UNSIGNED NOT NULL, -- 参数搜索的次数
+    init_cash DOUBLE NOT NULL,        -- 初始资金
+    start_date CHAR(8) NULL,          -- 回测开始日期
+    end_date CHAR(8) NULL,            -- 回测结束日期
+    benchmark CHAR(20) NULL,          -- 基准指数代码
+    request TEXT NOT NULL,            -- 回测请求参数（JSON）
+    best_param TEXT NOT NULL,         -- 最优参数（JSON）
+    metrics TEXT NOT NULL,            -- 绩效指标（JSON）
+    created_at CHAR(19) NOT NULL      -- 创建时间，YYYY-MM-DD HH:MM:SS
+);
+CREATE TABLE backtest_equity (
+    run_id BIGINT NOT NULL,           -- 回测记录 id
+    trade_date CHAR(8) NOT NULL,      -- 交易日期
+    open DOUBLE,                      -- 开盘价，可以为空
+    close DOUBLE,                     -- 收盘价，可以为空
+    high DOUBLE,                      -- 最高价，可以为空
+    low DOUBLE,                       -- 最低价，可以为空
+    volume DOUBLE,                    -- 成交量，可以为空
+    `signal` INTEGER NULL,            -- 交易信号，可以为空
+    hold BIGINT UNSIGNED NOT NULL,    -- 收盘后持股数
+    total_assets DOUBLE NOT NULL,     -- 总资产
+    benchmark DOUBLE NULL,            -- 基准净值，可以为空
+    PRIMARY KEY (run_id, trade_date)  -- 复合主键
+);
+CREATE TABLE backtest_trade (
+    id BIGINT AUTO_INCREMENT PRIMARY KEY,
+    run_id BIGINT NOT NULL,           -- 回测记录 id
+    rejected BOOLEAN NOT NULL,        -- 是否为未能成交的订单
+    code CHAR(20) NOT NULL,           -- 股票代码
+    order_type CHAR(4) NOT NULL,      -- Buy 或 Sell
+    hold BIGINT UNSIGNED NOT NULL,    -- 下单前持股数
+    assets DOUBLE NOT NULL,           -- 下单时总资产
+    operate_num BIGINT UNSIGNED NOT NULL, -- 成交股数
+    close DOUBLE NOT NULL,            -- 收盘价
+    operate_date CHAR(8) NULL,        -- 交易日期
+    price DOUBLE NOT NULL,            -- 成交价，包含滑点
+    fee DOUBLE NOT NULL,              -- 交易费用
+    reason TEXT NULL,                 -- 订单被调整或未能成交的原因
+    INDEX idx_run_id (run_id)
+);
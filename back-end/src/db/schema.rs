@@ -38,3 +38,81 @@ diesel::table! {
         amount -> Nullable<Double>,             // 成交额 （千元）
     }
 }
+
+diesel::table! {
+    industry_rps_values (industry, trade_date) {
+        industry -> Varchar,                    // 所属行业
+        trade_date -> Nullable<Text>,           // 日期
+        rps -> Nullable<Double>,                // 行业 rps 值
+        increase -> Nullable<Double>,           // 行业指定时间涨幅
+        stock_count -> Nullable<Integer>,       // 参与计算的成分股数量
+    }
+}
+
+diesel::table! {
+    index_daily (ts_code, trade_date) {
+        ts_code -> Varchar,                     // 指数代码
+        trade_date -> Nullable<Text>,           // 日期
+        open -> Nullable<Double>,               // 开盘点位
+        close -> Nullable<Double>,              // 收盘点位
+        high -> Nullable<Double>,               // 最高点位
+        low -> Nullable<Double>,                // 最低点位
+        pre_close -> Nullable<Double>,          // 昨日收盘点位
+        vol -> Nullable<Double>,                // 成交量 （手）
+        change -> Nullable<Double>,             // 涨跌点
+        pct_chg -> Nullable<Double>,            // 涨跌幅
+        amount -> Nullable<Double>,             // 成交额 （千元）
+    }
+}
+
+diesel::table! {
+    backtest_run (id) {
+        id -> BigInt,                           // 主键
+        codes -> Text,                          // 股票代码，逗号分隔
+        strategy -> Varchar,                    // 策略名称
+        seed -> Unsigned<BigInt>,               // 随机数种子
+        iterations -> Unsigned<BigInt>,         // 参数搜索的次数
+        init_cash -> Double,                    // 初始资金
+        start_date -> Nullable<Text>,           // 回测开始日期
+        end_date -> Nullable<Text>,             // 回测结束日期
+        benchmark -> Nullable<Text>,            // 基准指数代码
+        request -> Text,                        // 回测请求参数（JSON）
+        best_param -> Text,                     // 最优参数（JSON）
+        metrics -> Text,                        // 绩效指标（JSON）
+        created_at -> Text,                     // 创建时间
+    }
+}
+
+diesel::table! {
+    backtest_equity (run_id, trade_date) {
+        run_id -> BigInt,                       // 回测记录 id
+        trade_date -> Text,                     // 日期
+        open -> Nullable<Double>,               // 开盘价
+        close -> Nullable<Double>,              // 收盘价
+        high -> Nullable<Double>,               // 最高价
+        low -> Nullable<Double>,                // 最低价
+        volume -> Nullable<Double>,             // 成交量
+        signal -> Nullable<Integer>,            // 交易信号
+        hold -> Unsigned<BigInt>,               // 收盘后持股数
+        total_assets -> Double,                 // 总资产
+        benchmark -> Nullable<Double>,          // 基准净值
+    }
+}
+
+diesel::table! {
+    backtest_trade (id) {
+        id -> BigInt,                           // 主键
+        run_id -> BigInt,                       // 回测记录 id
+        rejected -> Bool,                       // 是否为未能成交的订单
+        code -> Varchar,                        // 股票代码
+        order_type -> Varchar,                  // Buy 或 Sell
+        hold -> Unsigned<BigInt>,               // 下单前持股数
+        assets -> Double,                       // 下单时总资产
+        operate_num -> Unsigned<BigInt>,        // 成交股数
+        close -> Double,                        // 收盘价
+        operate_date -> Nullable<Text>,         // 交易日期
+        price -> Double,                        // 成交价
+        fee -> Double,                          // 交易费用
+        reason -> Nullable<Text>,               // 订单被调整或未能成交的原因
+    }
+}
@@ -1,4 +1,7 @@
-use crate::db::schema::{rps_values, stock_daily_info, stock_info_list};
+use crate::db::schema::{
+    backtest_equity, backtest_run, backtest_trade, index_daily, industry_rps_values, rps_values,
+    stock_daily_info, stock_info_list,
+};
 use rocket::serde::{Deserialize, Serialize};
 use std::convert::From;
 
@@ -132,3 +135,135 @@ pub struct StockRps {
     pub rps: Option<f64>,           // 股价强度指数
     pub increase: Option<f64>,      // 指定时间涨幅
 }
+
+#[derive(Debug, Clone, Queryable, Insertable, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name=industry_rps_values)] // 指定 Diesel 中表的名称为 industry_rps_values
+pub struct IndustryRps {
+    pub industry: String,           // 所属行业
+    pub trade_date: Option<String>, // 交易日期
+    pub rps: Option<f64>,           // 行业强度指数
+    pub increase: Option<f64>,      // 行业指定时间涨幅
+    pub stock_count: Option<i32>,   // 参与计算的成分股数量
+}
+
+#[derive(Debug, Clone, Queryable, Insertable, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name=index_daily)] // 指定 Diesel 中表的名称为 index_daily
+pub struct IndexDailyInfo {
+    pub ts_code: String,            // 指数代码
+    pub trade_date: Option<String>, // 交易日期
+    pub open: Option<f64>,          // 开盘点位
+    pub close: Option<f64>,         // 收盘点位
+    pub high: Option<f64>,          // 最高点位
+    pub low: Option<f64>,           // 最低点位
+    pub pre_close: Option<f64>,     // 昨日收盘点位
+    pub vol: Option<f64>,           // 成交量 （手）
+    pub change: Option<f64>,        // 涨跌点
+    pub pct_chg: Option<f64>,       // 涨跌幅
+    pub amount: Option<f64>,        // 成交额 （千元）
+}
+// 指数日线与股票日线的字段相同，接口返回的数据先解析为 StockPriceInfo 再转换
+impl From<StockPriceInfo> for IndexDailyInfo {
+    fn from(value: StockPriceInfo) -> Self {
+        IndexDailyInfo {
+            ts_code: value.ts_code,
+            trade_date: value.trade_date,
+            open: value.open,
+            close: value.close,
+            high: value.high,
+            low: value.low,
+            pre_close: value.pre_close,
+            vol: value.vol,
+            change: value.change,
+            pct_chg: value.pct_chg,
+            amount: value.amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BacktestRun {
+    pub id: i64,                    // 主键
+    pub codes: String,              // 股票代码，逗号分隔
+    pub strategy: String,           // 策略名称
+    pub seed: u64,                  // 随机数种子
+    pub iterations: u64,            // 参数搜索的次数
+    pub init_cash: f64,             // 初始资金
+    pub start_date: Option<String>, // 回测开始日期
+    pub end_date: Option<String>,   // 回测结束日期
+    pub benchmark: Option<String>,  // 基准指数代码
+    pub request: String,            // 回测请求参数（JSON）
+    pub best_param: String,         // 最优参数（JSON）
+    pub metrics: String,            // 绩效指标（JSON）
+    pub created_at: String,         // 创建时间
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name=backtest_run)] // 指定 Diesel 中表的名称为 backtest_run
+pub struct NewBacktestRun {
+    pub codes: String,              // 股票代码，逗号分隔
+    pub strategy: String,           // 策略名称
+    pub seed: u64,                  // 随机数种子
+    pub iterations: u64,            // 参数搜索的次数
+    pub init_cash: f64,             // 初始资金
+    pub start_date: Option<String>, // 回测开始日期
+    pub end_date: Option<String>,   // 回测结束日期
+    pub benchmark: Option<String>,  // 基准指数代码
+    pub request: String,            // 回测请求参数（JSON）
+    pub best_param: String,         // 最优参数（JSON）
+    pub metrics: String,            // 绩效指标（JSON）
+    pub created_at: String,         // 创建时间
+}
+
+#[derive(Debug, Clone, Queryable, Insertable, Serialize)]
+#[serde(crate = "rocket::serde")]
+#[diesel(table_name=backtest_equity)] // 指定 Diesel 中表的名称为 backtest_equity
+pub struct BacktestEquity {
+    pub run_id: i64,            // 回测记录 id
+    pub trade_date: String,     // 交易日期
+    pub open: Option<f64>,      // 开盘价
+    pub close: Option<f64>,     // 收盘价
+    pub high: Option<f64>,      // 最高价
+    pub low: Option<f64>,       // 最低价
+    pub volume: Option<f64>,    // 成交量
+    pub signal: Option<i32>,    // 交易信号
+    pub hold: u64,              // 收盘后持股数
+    pub total_assets: f64,      // 总资产
+    pub benchmark: Option<f64>, // 基准净值
+}
+
+#[derive(Debug, Clone, Queryable)]
+pub struct BacktestTrade {
+    pub id: i64,                      // 主键
+    pub run_id: i64,                  // 回测记录 id
+    pub rejected: bool,               // 是否为未能成交的订单
+    pub code: String,                 // 股票代码
+    pub order_type: String,           // Buy 或 Sell
+    pub hold: u64,                    // 下单前持股数
+    pub assets: f64,                  // 下单时总资产
+    pub operate_num: u64,             // 成交股数
+    pub close: f64,                   // 收盘价
+    pub operate_date: Option<String>, // 交易日期
+    pub price: f64,                   // 成交价
+    pub fee: f64,                     // 交易费用
+    pub reason: Option<String>,       // 订单被调整或未能成交的原因
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name=backtest_trade)] // 指定 Diesel 中表的名称为 backtest_trade
+pub struct NewBacktestTrade {
+    pub run_id: i64,                  // 回测记录 id
+    pub rejected: bool,               // 是否为未能成交的订单
+    pub code: String,                 // 股票代码
+    pub order_type: String,           // Buy 或 Sell
+    pub hold: u64,                    // 下单前持股数
+    pub assets: f64,                  // 下单时总资产
+    pub operate_num: u64,             // 成交股数
+    pub close: f64,                   // 收盘价
+    pub operate_date: Option<String>, // 交易日期
+    pub price: f64,                   // 成交价
+    pub fee: f64,                     // 交易费用
+    pub reason: Option<String>,       // 订单被调整或未能成交的原因
+}
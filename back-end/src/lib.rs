@@ -18,7 +18,11 @@ pub enum AppErrorEnum {
     DieselError(diesel::result::Error),
     IoError(std::io::Error),
     ThreadErr(Box<dyn Any + Send + 'static>),
-    JoinErr(JoinError)
+    JoinErr(JoinError),
+    InvalidParam(String), // 请求参数错误
+    ReqwestError(reqwest::Error), // 调用数据接口出错
+    InsufficientHistory { code: String, bars: usize, required: usize }, // 历史 K 线不足以回测
+    CorruptRecord(String), // 保存的数据无法解析
     // 可以扩展其他错误类型
 }
 
@@ -29,6 +33,14 @@ impl fmt::Display for AppErrorEnum {
             AppErrorEnum::IoError(err) => write!(f, "IO error: {}", err),
             AppErrorEnum::ThreadErr(err) => write!(f, "Thread execute error: {:?}", err),
             AppErrorEnum::JoinErr(err) => write!(f, "Thread execute error: {:?}", err),
+            AppErrorEnum::InvalidParam(msg) => write!(f, "Invalid param: {}", msg),
+            AppErrorEnum::ReqwestError(err) => write!(f, "Request error: {}", err),
+            AppErrorEnum::InsufficientHistory { code, bars, required } => write!(
+                f,
+                "Insufficient history: {} has {} bars, at least {} required",
+                code, bars, required
+            ),
+            AppErrorEnum::CorruptRecord(msg) => write!(f, "Corrupt record: {}", msg),
             // 可以扩展其他错误类型的显示方式
         }
     }
@@ -58,3 +70,9 @@ impl From<JoinError> for AppErrorEnum {
         AppErrorEnum::JoinErr(error)
     }
 }
+
+impl From<reqwest::Error> for AppErrorEnum {
+    fn from(error: reqwest::Error) -> Self {
+        AppErrorEnum::ReqwestError(error)
+    }
+}
@@ -1,20 +1,43 @@
-use crate::db::schema::{rps_values, stock_info_list};
-use crate::db::{connection::Db, stock_info::StockInfo};
+use crate::db::schema::{industry_rps_values, rps_values, stock_daily_info, stock_info_list};
+use crate::db::{
+    connection::Db,
+    stock_info::{IndustryRps, NewBacktestRun, StockInfo},
+};
 use crate::stock_lib::{
-    get_all_stock_list, get_stock_rps_list,
-    stock_trade::{simulate_stock_trade, OperateRecord, TradeResult},
+    backtest_store::{self, BacktestCompare, BacktestRunDetail, BacktestRunSummary},
+    benchmark::{self, BenchmarkReport, DEFAULT_BENCHMARK},
+    cost_model::CostModel,
+    engine::{ExecutionConfig, OrderEvent},
+    get_all_stock_list,
+    get_industry_rps_list::{self, IndustryStockRps, IndustryWeight},
+    get_stock_rps_list,
+    metrics::{BacktestMetrics, Objective},
+    missing_bar::MissingBarPolicy,
+    optimizer::{OptimizeConfig, OptimizerKind, ParamScore},
+    portfolio::{simulate_portfolio_trade, PortfolioResult},
+    rebalance::{simulate_rebalance_trade, RebalanceConfig, RebalanceResult},
+    risk::RiskConfig,
+    rps_screen::{self, RpsScreenItem},
+    sensitivity::RobustnessReport,
+    sizer::SizerConfig,
+    stock_trade::{
+        simulate_stock_trade, OperateRecord, OptimizeStats, RunConfig, ST_Account, TradeResult,
+        TradeRules, DEFAULT_COMMISSION_COEFFICIENT, DEFAULT_TAX_COEFFICIENT,
+    },
+    strategy::{create_strategy, ParamSpec, StrategyData, DEFAULT_STRATEGY, STRATEGY_NAMES},
+    walk_forward::{simulate_walk_forward, WalkForwardConfig, WalkForwardResult},
 };
-use chrono::NaiveDate;
-use diesel::{ExpressionMethods, QueryDsl};
+use crate::AppErrorEnum;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
 use rocket::fairing::AdHoc;
 use rocket::response::Debug; // 导入 Rocket 的 Debug 类型，用于调试错误响应。
-use rocket::serde::json::Json;
+use rocket::serde::json::{self, Json};
 use rocket::serde::{Deserialize, Serialize};
 use rocket::tokio;
-use rocket::State;
 use rocket_db_pools::diesel::AsyncConnection; // 导入 AsyncConnection 用于与 MySQL 数据库异步交互。
 use rocket_db_pools::diesel::RunQueryDsl;
 use rocket_db_pools::Connection;
+use std::collections::HashMap;
 
 // 定义一个通用的 Result 类型，默认错误类型为 Debug<diesel::result::Error>，用于处理数据库操作中的错误。
 type Result<T, E = Debug<diesel::result::Error>> = std::result::Result<T, E>;
@@ -46,15 +69,19 @@ async fn get_basic_info(mut db: Connection<Db>) -> Result<()> {
 struct ReqFetchStockRps {
     date: Option<String>,
     range: Option<usize>,
+    force: Option<bool>, // 当天已有数据时是否删除并重新计算
 }
 
 #[post("/fetch_stock_rps_list", data = "<req>")]
-async fn get_stock_rps(
-    db: Connection<Db>,
-    db_state: &State<Db>,
-    req: Json<ReqFetchStockRps>,
-) -> Result<()> {
-    match get_stock_rps_list::col_stock_rps(db, db_state, req.date.clone(), req.range).await {
+async fn get_stock_rps(db: Connection<Db>, req: Json<ReqFetchStockRps>) -> Result<()> {
+    match get_stock_rps_list::col_stock_rps(
+        db,
+        req.date.clone(),
+        req.range,
+        req.force.unwrap_or(false),
+    )
+    .await
+    {
         Ok(()) => {}
         Err(e) => {
             println!("{:?}", e)
@@ -119,6 +146,7 @@ async fn get_stock_daily_range(
 #[serde(crate = "rocket::serde")]
 struct RpsRequest {
     date: Option<String>,
+    horizon: Option<usize>, // 排名变化的对比周期（交易日），如 1、5、20，默认 1
 }
 #[derive(Queryable)]
 struct CurDateRpsResponse {
@@ -143,18 +171,50 @@ struct RpsResponse {
     name: Option<String>,
     rps: Option<f64>,
     increase: Option<f64>,
-    rank_change: StockRankChange,
+    rank: usize,               // 当天排名，从 1 开始
+    prev_date: Option<String>, // 对比日期
+    prev_rank: Option<usize>,  // 对比日期的排名
+    prev_rps: Option<f64>,     // 对比日期的 rps 值
+    // 没有对比日期时为 null
+    rank_change: Option<StockRankChange>,
 }
+
+/// 获取排名变化的对比日期：date 之前第 horizon 个交易日当天或之前、最近一个有 RPS 数据的日期
+/// 之前的交易日不足 horizon 个时返回 None
+async fn get_prev_rps_date(
+    db: &mut Connection<Db>,
+    date: &str,
+    horizon: usize,
+) -> Result<Option<String>> {
+    // 以价格数据的交易日历计算往前第 horizon 个交易日
+    let horizon_date = stock_daily_info::table
+        .select(stock_daily_info::trade_date)
+        .filter(stock_daily_info::trade_date.lt(date.to_string()))
+        .distinct()
+        .order(stock_daily_info::trade_date.desc())
+        .offset(horizon.max(1) as i64 - 1)
+        .first::<Option<String>>(db)
+        .await
+        .optional()?
+        .flatten();
+    // 不能用更近的日期代替，否则会把更短周期的变化当作 horizon 的变化
+    let horizon_date = match horizon_date {
+        Some(horizon_date) => horizon_date,
+        None => return Ok(None),
+    };
+    Ok(rps_values::table
+        .select(diesel::dsl::max(rps_values::trade_date))
+        .filter(rps_values::trade_date.le(horizon_date))
+        .first::<Option<String>>(db)
+        .await?)
+}
+
 #[post("/rps-top", data = "<search>")]
 async fn get_stock_rps_top(
     mut db: Connection<Db>,
     search: Json<RpsRequest>,
 ) -> Result<Json<Vec<RpsResponse>>> {
     if let Some(date) = &search.date {
-        // 定义格式化字符串
-        let format = "%Y%m%d";
-        let cur_date = NaiveDate::parse_from_str(date, format).unwrap();
-        let prev_date = cur_date.pred_opt().unwrap().format(format).to_string();
         // 查询当天的股票RPS排名
         let result: Vec<CurDateRpsResponse> = stock_info_list::table
             .inner_join(rps_values::table)
@@ -168,54 +228,47 @@ async fn get_stock_rps_top(
             .order(rps_values::rps.desc())
             .load(&mut db)
             .await?;
-        // 查询前一天的股票RPS排名
-        let prev_rank: Vec<String> = rps_values::table
-            .filter(rps_values::trade_date.eq(prev_date))
-            .select(rps_values::ts_code)
-            .order(rps_values::rps.desc())
-            .load(&mut db)
-            .await?;
+        // 查询对比日期的股票RPS排名，股票代码 -> (排名, rps)
+        let prev_date = get_prev_rps_date(&mut db, date, search.horizon.unwrap_or(1)).await?;
+        let prev_rank: HashMap<String, (usize, Option<f64>)> = match &prev_date {
+            Some(prev_date) => rps_values::table
+                .filter(rps_values::trade_date.eq(prev_date.to_string()))
+                .select((rps_values::ts_code, rps_values::rps))
+                .order(rps_values::rps.desc())
+                .load::<(String, Option<f64>)>(&mut db)
+                .await?
+                .into_iter()
+                .enumerate()
+                .map(|(index, (ts_code, rps))| (ts_code, (index + 1, rps)))
+                .collect(),
+            None => HashMap::new(),
+        };
         // 计算股票排名变化
         let rank_change: Vec<RpsResponse> = result
             .into_iter()
             .enumerate()
             .map(|(cur_index, r)| {
-                let index = prev_rank.iter().position(|x| x == &r.ts_code);
-                match index {
-                    Some(i) => {
-                        if cur_index == i {
-                            RpsResponse {
-                                ts_code: r.ts_code,
-                                name: r.name,
-                                rps: r.rps,
-                                increase: r.increase,
-                                rank_change: StockRankChange::NoChange,
-                            }
-                        } else if cur_index < i {
-                            RpsResponse {
-                                ts_code: r.ts_code,
-                                name: r.name,
-                                rps: r.rps,
-                                increase: r.increase,
-                                rank_change: StockRankChange::Increase(i - cur_index),
-                            }
-                        } else {
-                            RpsResponse {
-                                ts_code: r.ts_code,
-                                name: r.name,
-                                rps: r.rps,
-                                increase: r.increase,
-                                rank_change: StockRankChange::Decrease(cur_index - i),
-                            }
-                        }
+                let rank = cur_index + 1;
+                let prev = prev_rank.get(&r.ts_code);
+                let rank_change = match prev {
+                    _ if prev_date.is_none() => None,
+                    Some((prev_rank, _)) if *prev_rank == rank => Some(StockRankChange::NoChange),
+                    Some((prev_rank, _)) if *prev_rank > rank => {
+                        Some(StockRankChange::Increase(prev_rank - rank))
                     }
-                    None => RpsResponse {
-                        ts_code: r.ts_code,
-                        name: r.name,
-                        rps: r.rps,
-                        increase: r.increase,
-                        rank_change: StockRankChange::NewInBoard,
-                    },
+                    Some((prev_rank, _)) => Some(StockRankChange::Decrease(rank - prev_rank)),
+                    None => Some(StockRankChange::NewInBoard),
+                };
+                RpsResponse {
+                    ts_code: r.ts_code,
+                    name: r.name,
+                    rps: r.rps,
+                    increase: r.increase,
+                    rank,
+                    prev_date: prev_date.clone(),
+                    prev_rank: prev.map(|(prev_rank, _)| *prev_rank),
+                    prev_rps: prev.and_then(|(_, prev_rps)| *prev_rps),
+                    rank_change,
                 }
             })
             .collect();
@@ -224,6 +277,180 @@ async fn get_stock_rps_top(
     Ok(Json(vec![]))
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqFetchIndustryRps {
+    date: Option<String>,
+    range: Option<usize>,
+    weight: Option<IndustryWeight>, // 成分股涨幅聚合方式：equal 等权 / amount 成交额加权
+    force: Option<bool>,            // 当天已有数据时是否删除并重新计算
+}
+
+#[post("/fetch_industry_rps_list", data = "<req>")]
+async fn get_industry_rps(db: Connection<Db>, req: Json<ReqFetchIndustryRps>) -> Result<()> {
+    match get_industry_rps_list::col_industry_rps(
+        db,
+        req.date.clone(),
+        req.range,
+        req.weight,
+        req.force.unwrap_or(false),
+    )
+    .await
+    {
+        Ok(()) => {}
+        Err(e) => {
+            println!("{:?}", e)
+        }
+    };
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct IndustryRpsRequest {
+    date: Option<String>,
+}
+
+#[post("/industry-rps-top", data = "<search>")]
+async fn get_industry_rps_top(
+    mut db: Connection<Db>,
+    search: Json<IndustryRpsRequest>,
+) -> Result<Json<Vec<IndustryRps>>> {
+    if let Some(date) = &search.date {
+        // 查询当天的行业RPS排名
+        let result: Vec<IndustryRps> = industry_rps_values::table
+            .filter(industry_rps_values::trade_date.eq(date.to_string()))
+            .order(industry_rps_values::rps.desc())
+            .load(&mut db)
+            .await?;
+        return Ok(Json(result));
+    }
+    Ok(Json(vec![]))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqIndustryStockRps {
+    ts_code: String,
+    date: Option<String>,
+    range: Option<usize>,
+}
+
+#[post("/industry-stock-rps", data = "<req>")]
+async fn get_industry_stock_rps(
+    db: Connection<Db>,
+    req: Json<ReqIndustryStockRps>,
+) -> Result<Json<Option<IndustryStockRps>>, Debug<AppErrorEnum>> {
+    let result = get_industry_rps_list::col_industry_stock_rps(
+        db,
+        req.ts_code.clone(),
+        req.date.clone(),
+        req.range,
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqRpsScreen {
+    date: String,
+    threshold: Option<f64>, // RPS 阈值，默认 90
+    days: Option<usize>,    // 连续天数 / 新高回看天数
+    within: Option<f64>,    // 距离 52 周最高价的百分比，默认 10
+}
+
+#[post("/rps-screen/persist", data = "<req>")]
+async fn get_rps_screen_persist(
+    mut db: Connection<Db>,
+    req: Json<ReqRpsScreen>,
+) -> Result<Json<Vec<RpsScreenItem>>, Debug<AppErrorEnum>> {
+    let result = rps_screen::screen_rps_persist(
+        &mut db,
+        req.date.clone(),
+        req.threshold.unwrap_or(90.0),
+        req.days.unwrap_or(5),
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+#[post("/rps-screen/cross", data = "<req>")]
+async fn get_rps_screen_cross(
+    mut db: Connection<Db>,
+    req: Json<ReqRpsScreen>,
+) -> Result<Json<Vec<RpsScreenItem>>, Debug<AppErrorEnum>> {
+    let result =
+        rps_screen::screen_rps_cross(&mut db, req.date.clone(), req.threshold.unwrap_or(90.0))
+            .await?;
+    Ok(Json(result))
+}
+
+#[post("/rps-screen/new-high", data = "<req>")]
+async fn get_rps_screen_new_high(
+    mut db: Connection<Db>,
+    req: Json<ReqRpsScreen>,
+) -> Result<Json<Vec<RpsScreenItem>>, Debug<AppErrorEnum>> {
+    let result = rps_screen::screen_rps_new_high(
+        &mut db,
+        req.date.clone(),
+        req.days.unwrap_or(20),
+        req.within.unwrap_or(10.0),
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqDeleteRps {
+    start_date: String, // 开始日期（含）
+    end_date: String,   // 结束日期（含）
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ResDeleteRps {
+    rps: usize,          // 删除的个股 RPS 行数
+    industry_rps: usize, // 删除的行业 RPS 行数
+}
+
+// 按日期范围删除 RPS，代替清空整张表
+#[post("/delete/rps", data = "<req>")]
+async fn delete_stock_rps(
+    db: Connection<Db>,
+    req: Json<ReqDeleteRps>,
+) -> Result<Json<ResDeleteRps>, Debug<AppErrorEnum>> {
+    let (rps, industry_rps) =
+        get_stock_rps_list::delete_stock_rps(db, req.start_date.clone(), req.end_date.clone())
+            .await?;
+    Ok(Json(ResDeleteRps { rps, industry_rps }))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqFetchIndexDaily {
+    ts_code: Option<String>, // 指数代码，默认 000300.SH
+    start_date: String,      // 开始日期（含）
+    end_date: String,        // 结束日期（含）
+}
+
+// 获取指数日线数据，作为回测的基准
+#[post("/fetch_index_daily", data = "<req>")]
+async fn fetch_index_daily(
+    mut db: Connection<Db>,
+    req: Json<ReqFetchIndexDaily>,
+) -> Result<Json<usize>, Debug<AppErrorEnum>> {
+    let affected = benchmark::fetch_index_daily(
+        &mut db,
+        req.ts_code.clone().unwrap_or(DEFAULT_BENCHMARK.to_string()),
+        req.start_date.clone(),
+        req.end_date.clone(),
+    )
+    .await?;
+    Ok(Json(affected))
+}
+
 #[get("/clear/rps-top")]
 async fn clear_stock_rps_top(mut db: Connection<Db>) -> Result<()> {
     diesel::sql_query("TRUNCATE rps_values")
@@ -295,51 +522,429 @@ async fn query_basic(
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 struct SimulateReq {
-    code: String,                         // 股票代码
-    assets: Option<f64>,                  // 初始资金
-    n1_range: Option<(usize, usize)>,     // N1范围
-    n2_range: Option<(usize, usize)>,     // N2范围
-    win_range: Option<(f64, f64)>,        // 盈利范围
-    loss_range: Option<(f64, f64)>,       // 亏损范围
-    adjust_range: Option<(usize, usize)>, // 调整范围
+    code: String,                                      // 股票代码
+    assets: Option<f64>,                               // 初始资金
+    strategy: Option<String>,                          // 策略名称，默认 turtle_channel
+    params: Option<HashMap<String, f64>>,              // 固定的策略参数，不参与参数优化
+    param_ranges: Option<HashMap<String, (f64, f64)>>, // 参与参数优化的范围
+    n1_range: Option<(usize, usize)>,                  // N1范围
+    n2_range: Option<(usize, usize)>,                  // N2范围
+    win_range: Option<(f64, f64)>,                     // 盈利范围
+    loss_range: Option<(f64, f64)>,                    // 亏损范围
+    adjust_range: Option<(usize, usize)>,              // 调整范围
+    rules: Option<TradeRules>,                         // A 股交易规则，默认 T+1、整手、涨跌停限制
+    cost: Option<CostModel>,                           // 交易成本模型，默认万 2.5 佣金、最低 5 元
+    objective: Option<Objective>,                      // 选择最优参数的目标，默认总收益率
+    benchmark: Option<String>,                         // 基准指数代码，默认 000300.SH
+    seed: Option<u64>,                                 // 参数搜索的随机数种子，不传时随机生成
+    iterations: Option<usize>,                         // 参数搜索的次数，默认 10000
+    optimizer: Option<OptimizerKind>,                  // 参数优化方法，默认随机搜索
+    top_k: Option<usize>,                              // 返回得分最高的参数组数，默认 5
+    cloud: Option<bool>,                               // 是否返回参数云和稳健性报告，默认不返回
+    save: Option<bool>,                                // 是否保存回测记录，默认不保存
+    execution: Option<ExecutionConfig>, // 事件驱动引擎的下单方式，不传时以信号当天的收盘价成交
+    sizer: Option<SizerConfig>,         // 仓位模型，不传时沿用策略给出的股数
+    missing_bar: Option<MissingBarPolicy>, // 缺失 K 线的处理方式，默认跳过
+}
+
+impl SimulateReq {
+    /// 合并参数范围，n1_range 等字段是海龟策略参数范围的简写
+    fn param_ranges(&self) -> HashMap<String, (f64, f64)> {
+        let mut param_ranges = self.param_ranges.clone().unwrap_or_default();
+        let legacy_ranges = [
+            (
+                "n1",
+                self.n1_range.map(|(min, max)| (min as f64, max as f64)),
+            ),
+            (
+                "n2",
+                self.n2_range.map(|(min, max)| (min as f64, max as f64)),
+            ),
+            ("win", self.win_range),
+            ("loss", self.loss_range),
+            (
+                "adjust",
+                self.adjust_range.map(|(min, max)| (min as f64, max as f64)),
+            ),
+        ];
+        for (name, range) in legacy_ranges {
+            if let Some(range) = range {
+                param_ranges.entry(name.to_string()).or_insert(range);
+            }
+        }
+        param_ranges
+    }
 }
 
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
 struct SimulateRes {
+    strategy: String,
     df_stock: Vec<TradeResult>,
     operate_record: Vec<OperateRecord>,
-    best_param: (
-        Option<usize>,
-        Option<usize>,
-        Option<f64>,
-        Option<f64>,
-        Option<usize>,
-    ),
+    reject_record: Vec<OperateRecord>,
+    order_events: Vec<OrderEvent>, // 订单事件，只在使用事件驱动引擎时生成
+    best_param: HashMap<String, f64>,
+    metrics: BacktestMetrics,
+    top_params: Vec<ParamScore>,          // 得分最高的几组参数
+    cloud: Vec<ParamScore>,               // 参数搜索回测过的全部参数及绩效指标，可用于画热力图
+    robustness: Option<RobustnessReport>, // 最优参数附近的稳健性
+    benchmark: Option<BenchmarkReport>,   // 与基准指数的对比，本地没有指数数据时为空
+    seed: u64,                            // 本次参数搜索使用的随机数种子，用于复现结果
+    iterations: usize,                    // 本次参数搜索的次数
+    stats: OptimizeStats,                 // 参数搜索的耗时和吞吐量
+    run_id: Option<i64>,                  // 保存的回测记录 id，未保存时为空
+}
+
+/// 按请求参数生成回测账户
+/// assets: 初始资金，默认 100000
+/// rules: 交易规则，默认 A 股规则
+/// cost: 交易成本模型
+fn new_account(
+    assets: Option<f64>,
+    rules: Option<TradeRules>,
+    cost: Option<CostModel>,
+) -> ST_Account {
+    ST_Account::new(
+        HashMap::new(),
+        assets.unwrap_or(100000.0),
+        DEFAULT_COMMISSION_COEFFICIENT,
+        DEFAULT_TAX_COEFFICIENT,
+    )
+    .with_rules(rules.unwrap_or_default())
+    .with_cost_model(cost.unwrap_or_default())
 }
 #[post("/simulate", data = "<req>")]
-async fn stock_simulate(db: Connection<Db>, req: Json<SimulateReq>) -> Result<Json<SimulateRes>> {
-    let res = simulate_stock_trade(
-        db,
+async fn stock_simulate(
+    mut db: Connection<Db>,
+    req: Json<SimulateReq>,
+) -> Result<Json<SimulateRes>, Debug<AppErrorEnum>> {
+    let strategy = req.strategy.clone().unwrap_or(DEFAULT_STRATEGY.to_string());
+    let benchmark_code = req
+        .benchmark
+        .clone()
+        .unwrap_or(DEFAULT_BENCHMARK.to_string());
+    let index_close = benchmark::get_local_index_close(&mut db, benchmark_code.clone()).await?;
+    let default_config = OptimizeConfig::default();
+    let config = OptimizeConfig {
+        iterations: req.iterations.unwrap_or(default_config.iterations),
+        objective: req.objective.unwrap_or_default(),
+        seed: req.seed.unwrap_or(default_config.seed),
+        method: req.optimizer.clone().unwrap_or_default(),
+        top_k: req.top_k.unwrap_or(default_config.top_k),
+        keep_cloud: req.cloud.unwrap_or(default_config.keep_cloud),
+    };
+    let mut res = simulate_stock_trade(
+        &mut db,
         vec![req.code.clone()],
-        req.assets.unwrap_or(100000.0),
-        None,
-        None,
-        req.n1_range.clone(),
-        req.n2_range.clone(),
-        req.win_range.clone(),
-        req.loss_range.clone(),
-        req.adjust_range.clone(),
+        new_account(req.assets, req.rules.clone(), req.cost.clone()),
+        &RunConfig {
+            execution: req.execution.clone(),
+            sizer: req.sizer.clone(),
+            missing_bar: req.missing_bar.unwrap_or_default(),
+        },
+        &strategy,
+        req.params.clone().unwrap_or_default(),
+        req.param_ranges(),
+        &config,
     )
-    .await;
-    let (code_result, best_param) = res.get(&req.code).unwrap().clone();
+    .await?;
+    let code_result = res
+        .remove(&req.code)
+        .ok_or_else(|| AppErrorEnum::InvalidParam(format!("股票 {} 没有回测结果", req.code)))?;
+    let dates: Vec<String> = code_result
+        .df_stock
+        .iter()
+        .map(|day| day.date.clone().unwrap_or_default())
+        .collect();
+    let total_assets: Vec<f64> = code_result
+        .df_stock
+        .iter()
+        .map(|day| day.total_assets.unwrap_or(0.0))
+        .collect();
+    let benchmark = benchmark::col_benchmark(
+        &benchmark_code,
+        &dates,
+        &total_assets,
+        req.assets.unwrap_or(100000.0),
+        &index_close,
+    );
+    let run_id = if req.save.unwrap_or(false) {
+        let run = NewBacktestRun {
+            codes: req.code.clone(),
+            strategy: strategy.clone(),
+            seed: config.seed,
+            iterations: config.iterations as u64,
+            init_cash: req.assets.unwrap_or(100000.0),
+            start_date: <[String]>::first(&dates).cloned(),
+            end_date: dates.last().cloned(),
+            benchmark: benchmark.as_ref().map(|report| report.ts_code.clone()),
+            request: json::to_string(&*req).unwrap_or_default(),
+            best_param: json::to_string(&code_result.best_param).unwrap_or_default(),
+            metrics: json::to_string(&code_result.metrics).unwrap_or_default(),
+            created_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        Some(
+            backtest_store::save_backtest_run(
+                &mut db,
+                run,
+                &code_result.df_stock,
+                benchmark.as_ref().map(|report| report.equity.as_slice()),
+                &code_result.operate_record,
+                &code_result.reject_record,
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
     Ok(Json(SimulateRes {
-        df_stock: code_result.0,
-        operate_record: code_result.1,
-        best_param,
+        strategy,
+        df_stock: code_result.df_stock,
+        operate_record: code_result.operate_record,
+        reject_record: code_result.reject_record,
+        order_events: code_result.order_events,
+        best_param: code_result.best_param,
+        metrics: code_result.metrics,
+        top_params: code_result.top_params,
+        cloud: code_result.cloud,
+        robustness: code_result.robustness,
+        benchmark,
+        seed: config.seed,
+        iterations: config.iterations,
+        stats: code_result.stats,
+        run_id,
     }))
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PortfolioSimulateReq {
+    codes: Vec<String>,                    // 股票代码
+    assets: Option<f64>,                   // 初始资金
+    strategy: Option<String>,              // 策略名称，默认 turtle_channel
+    params: Option<HashMap<String, f64>>,  // 策略参数，未指定的参数使用默认值
+    rules: Option<TradeRules>,             // A 股交易规则，默认 T+1、整手、涨跌停限制
+    cost: Option<CostModel>,               // 交易成本模型，默认万 2.5 佣金、最低 5 元
+    benchmark: Option<String>,             // 基准指数代码，默认 000300.SH
+    sizer: Option<SizerConfig>,            // 仓位模型，不传时沿用策略给出的股数
+    missing_bar: Option<MissingBarPolicy>, // 缺失 K 线的处理方式，默认跳过
+    risk: Option<RiskConfig>,              // 组合风控，不传时不启用
+}
+
+// 多只股票共用资金的组合回测
+#[post("/simulate/portfolio", data = "<req>")]
+async fn stock_simulate_portfolio(
+    mut db: Connection<Db>,
+    req: Json<PortfolioSimulateReq>,
+) -> Result<Json<PortfolioResult>, Debug<AppErrorEnum>> {
+    let strategy = req.strategy.clone().unwrap_or(DEFAULT_STRATEGY.to_string());
+    let benchmark_code = req
+        .benchmark
+        .clone()
+        .unwrap_or(DEFAULT_BENCHMARK.to_string());
+    let index_close = benchmark::get_local_index_close(&mut db, benchmark_code.clone()).await?;
+    let mut result = simulate_portfolio_trade(
+        db,
+        req.codes.clone(),
+        new_account(req.assets, req.rules.clone(), req.cost.clone()),
+        RunConfig {
+            sizer: req.sizer.clone(),
+            missing_bar: req.missing_bar.unwrap_or_default(),
+            ..RunConfig::default()
+        },
+        &strategy,
+        req.params.clone().unwrap_or_default(),
+        req.risk.clone().unwrap_or_default(),
+    )
+    .await?;
+    result.benchmark = benchmark::col_benchmark(
+        &benchmark_code,
+        &result.dates,
+        &result.total_assets,
+        req.assets.unwrap_or(100000.0),
+        &index_close,
+    );
+    Ok(Json(result))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct WalkForwardReq {
+    code: String,                                      // 股票代码
+    assets: Option<f64>,                               // 初始资金
+    strategy: Option<String>,                          // 策略名称，默认 turtle_channel
+    params: Option<HashMap<String, f64>>,              // 固定的策略参数，不参与参数优化
+    param_ranges: Option<HashMap<String, (f64, f64)>>, // 参与参数优化的范围
+    rules: Option<TradeRules>,                         // A 股交易规则，默认 T+1、整手、涨跌停限制
+    cost: Option<CostModel>,                           // 交易成本模型，默认万 2.5 佣金、最低 5 元
+    objective: Option<Objective>,                      // 选择最优参数的目标，默认总收益率
+    seed: Option<u64>,                                 // 参数搜索的随机数种子，不传时随机生成
+    iterations: Option<usize>,                         // 每个窗口参数搜索的次数，默认 10000
+    optimizer: Option<OptimizerKind>,                  // 参数优化方法，默认随机搜索
+    walk_forward: Option<WalkForwardConfig>,           // 样本内、样本外区间长度
+    execution: Option<ExecutionConfig>, // 事件驱动引擎的下单方式，不传时以信号当天的收盘价成交
+    sizer: Option<SizerConfig>,         // 仓位模型，不传时沿用策略给出的股数
+    missing_bar: Option<MissingBarPolicy>, // 缺失 K 线的处理方式，默认跳过
+}
+
+// 滚动窗口验证：每个窗口在样本内优化参数，在随后的样本外区间检验
+#[post("/simulate/walk_forward", data = "<req>")]
+async fn stock_simulate_walk_forward(
+    db: Connection<Db>,
+    req: Json<WalkForwardReq>,
+) -> Result<Json<WalkForwardResult>, Debug<AppErrorEnum>> {
+    let strategy = req.strategy.clone().unwrap_or(DEFAULT_STRATEGY.to_string());
+    let default_config = OptimizeConfig::default();
+    let wf_config = WalkForwardConfig {
+        optimize: OptimizeConfig {
+            iterations: req.iterations.unwrap_or(default_config.iterations),
+            objective: req.objective.unwrap_or_default(),
+            seed: req.seed.unwrap_or(default_config.seed),
+            method: req.optimizer.clone().unwrap_or_default(),
+            top_k: 1,
+            keep_cloud: false,
+        },
+        ..req.walk_forward.clone().unwrap_or_default()
+    };
+    let result = simulate_walk_forward(
+        db,
+        req.code.clone(),
+        new_account(req.assets, req.rules.clone(), req.cost.clone()),
+        RunConfig {
+            execution: req.execution.clone(),
+            sizer: req.sizer.clone(),
+            missing_bar: req.missing_bar.unwrap_or_default(),
+        },
+        &strategy,
+        req.params.clone().unwrap_or_default(),
+        req.param_ranges.clone().unwrap_or_default(),
+        &wf_config,
+    )
+    .await?;
+    Ok(Json(result))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct RebalanceReq {
+    codes: Option<Vec<String>>,            // 股票池，不传时使用全部股票
+    assets: Option<f64>,                   // 初始资金
+    rules: Option<TradeRules>,             // A 股交易规则，默认 T+1、整手、涨跌停限制
+    cost: Option<CostModel>,               // 交易成本模型，默认万 2.5 佣金、最低 5 元
+    benchmark: Option<String>,             // 基准指数代码，默认 000300.SH
+    missing_bar: Option<MissingBarPolicy>, // 缺失 K 线的处理方式，默认跳过
+    rebalance: Option<RebalanceConfig>,    // 因子、调仓间隔、持有数量和权重
+}
+
+// 截面排名轮动回测：每隔 N 个交易日按因子排名，持有得分最高的 K 只股票
+// 不传股票池时必须指定 rebalance.start_date，行情条数过多时返回参数错误
+#[post("/simulate/rebalance", data = "<req>")]
+async fn stock_simulate_rebalance(
+    mut db: Connection<Db>,
+    req: Json<RebalanceReq>,
+) -> Result<Json<RebalanceResult>, Debug<AppErrorEnum>> {
+    let benchmark_code = req
+        .benchmark
+        .clone()
+        .unwrap_or(DEFAULT_BENCHMARK.to_string());
+    let index_close = benchmark::get_local_index_close(&mut db, benchmark_code.clone()).await?;
+    let mut result = simulate_rebalance_trade(
+        &mut db,
+        req.codes.clone(),
+        new_account(req.assets, req.rules.clone(), req.cost.clone()),
+        req.missing_bar.unwrap_or_default(),
+        &req.rebalance.clone().unwrap_or_default(),
+    )
+    .await?;
+    result.benchmark = benchmark::col_benchmark(
+        &benchmark_code,
+        &result.dates,
+        &result.total_assets,
+        req.assets.unwrap_or(100000.0),
+        &index_close,
+    );
+    Ok(Json(result))
+}
+
+// 历史回测列表，按创建时间倒序
+#[get("/backtests?<limit>&<offset>")]
+async fn get_backtest_list(
+    mut db: Connection<Db>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Json<Vec<BacktestRunSummary>>, Debug<AppErrorEnum>> {
+    let run_list =
+        backtest_store::list_backtest_runs(&mut db, limit.unwrap_or(50), offset.unwrap_or(0))
+            .await?;
+    Ok(Json(run_list))
+}
+
+// 历史回测详情，包含每日净值和交易记录
+#[get("/backtests/<id>")]
+async fn get_backtest_detail(
+    mut db: Connection<Db>,
+    id: i64,
+) -> Result<Option<Json<BacktestRunDetail>>, Debug<AppErrorEnum>> {
+    let detail = backtest_store::get_backtest_run(&mut db, id).await?;
+    Ok(detail.map(Json))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqDeleteBacktests {
+    ids: Vec<i64>, // 回测记录 id
+}
+
+// 删除历史回测，返回删除的条数
+#[post("/backtests/delete", data = "<req>")]
+async fn delete_backtests(
+    mut db: Connection<Db>,
+    req: Json<ReqDeleteBacktests>,
+) -> Result<Json<usize>, Debug<AppErrorEnum>> {
+    let affected = backtest_store::delete_backtest_runs(&mut db, req.ids.clone()).await?;
+    Ok(Json(affected))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ReqCompareBacktests {
+    ids: Vec<i64>, // 回测记录 id，至少两个
+}
+
+// 对比多次回测：对齐的净值曲线、回撤曲线和绩效指标
+#[post("/backtests/compare", data = "<req>")]
+async fn compare_backtests(
+    mut db: Connection<Db>,
+    req: Json<ReqCompareBacktests>,
+) -> Result<Json<BacktestCompare>, Debug<AppErrorEnum>> {
+    let compare = backtest_store::compare_backtest_runs(&mut db, req.ids.clone()).await?;
+    Ok(Json(compare))
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct StrategyRes {
+    name: String,
+    param_space: Vec<ParamSpec>, // 参数空间，即参数优化的默认范围
+}
+
+// 内置策略及其参数空间
+#[get("/strategies")]
+async fn get_strategy_list() -> Json<Vec<StrategyRes>> {
+    let strategy_data = StrategyData::default();
+    Json(
+        STRATEGY_NAMES
+            .iter()
+            .filter_map(|name| create_strategy(name, &strategy_data))
+            .map(|strategy| StrategyRes {
+                name: strategy.name().to_string(),
+                param_space: strategy.param_space(),
+            })
+            .collect(),
+    )
+}
+
 pub fn stage() -> AdHoc {
     // AdHoc::on_ignite 是 Rocket 提供的一种机制，
     // 用于在 Rocket 启动时执行自定义的初始化代码。这个方法接受两个参数：
@@ -354,8 +959,24 @@ pub fn stage() -> AdHoc {
                 get_stock_rps,
                 get_stock_rps_top,
                 get_stock_daily_range,
+                fetch_index_daily,
                 stock_simulate,
-                clear_stock_rps_top
+                stock_simulate_portfolio,
+                stock_simulate_walk_forward,
+                stock_simulate_rebalance,
+                get_strategy_list,
+                get_backtest_list,
+                get_backtest_detail,
+                delete_backtests,
+                compare_backtests,
+                clear_stock_rps_top,
+                delete_stock_rps,
+                get_industry_rps,
+                get_industry_rps_top,
+                get_industry_stock_rps,
+                get_rps_screen_persist,
+                get_rps_screen_cross,
+                get_rps_screen_new_high
             ],
         )
     })
@@ -0,0 +1,478 @@
+use crate::db::schema::{backtest_equity, backtest_run, backtest_trade};
+use crate::db::stock_info::{
+    BacktestEquity, BacktestRun, BacktestTrade, NewBacktestRun, NewBacktestTrade,
+};
+use crate::stock_lib::metrics::BacktestMetrics;
+use crate::stock_lib::stock_trade::{OperateRecord, OrderType, TradeResult};
+use crate::stock_lib::strategy::StrategyParams;
+use crate::AppErrorEnum;
+use diesel::{ExpressionMethods, QueryDsl};
+use rocket::serde::json::{self, Value};
+use rocket::serde::{DeserializeOwned, Serialize};
+use rocket_db_pools::diesel::{AsyncConnection, AsyncMysqlConnection, RunQueryDsl};
+use std::collections::{BTreeMap, BTreeSet};
+
+/*
+* 回测记录
+*   每次回测保存到三张表，查看历史回测时不需要重新计算
+*   1. backtest_run：请求参数、策略、随机数种子、回测区间、最优参数和绩效指标，参数和指标以 JSON 保存
+*   2. backtest_equity：每日行情、交易信号、持仓、总资产和基准净值
+*   3. backtest_trade：交易记录和未能成交的订单
+* 对比多次回测
+*   按全部回测日期的并集对齐，净值 = 总资产 / 初始资金，回测开始前为空，中间缺少的日期沿用前一天的净值
+*   回撤 = 1 - 净值 / 此前的最高净值
+*/
+
+// 定义一个通用的 Result 类型，默认错误类型为 AppErrorEnum
+type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
+
+/// 回测记录概要，用于历史列表
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BacktestRunSummary {
+    pub id: i64,                    // 回测记录 id
+    pub codes: Vec<String>,         // 股票代码
+    pub strategy: String,           // 策略名称
+    pub seed: u64,                  // 随机数种子
+    pub iterations: u64,            // 参数搜索的次数
+    pub init_cash: f64,             // 初始资金
+    pub start_date: Option<String>, // 回测开始日期
+    pub end_date: Option<String>,   // 回测结束日期
+    pub benchmark: Option<String>,  // 基准指数代码
+    pub best_param: StrategyParams, // 最优参数
+    pub metrics: BacktestMetrics,   // 绩效指标
+    pub created_at: String,         // 创建时间
+}
+
+/// 解析以 JSON 保存的字段，损坏或与当前版本不兼容时返回错误，不按空值处理
+/// run_id: 回测记录 id
+/// field: 字段名
+/// text: 保存的 JSON
+fn parse_stored<T: DeserializeOwned>(run_id: i64, field: &str, text: &str) -> Result<T> {
+    json::from_str(text).map_err(|err| {
+        AppErrorEnum::CorruptRecord(format!(
+            "回测记录 {} 的 {} 无法解析: {}",
+            run_id, field, err
+        ))
+    })
+}
+
+impl TryFrom<BacktestRun> for BacktestRunSummary {
+    type Error = AppErrorEnum;
+    fn try_from(run: BacktestRun) -> Result<Self> {
+        Ok(BacktestRunSummary {
+            id: run.id,
+            codes: run.codes.split(',').map(|code| code.to_string()).collect(),
+            strategy: run.strategy,
+            seed: run.seed,
+            iterations: run.iterations,
+            init_cash: run.init_cash,
+            start_date: run.start_date,
+            end_date: run.end_date,
+            benchmark: run.benchmark,
+            best_param: parse_stored(run.id, "best_param", &run.best_param)?,
+            metrics: parse_stored(run.id, "metrics", &run.metrics)?,
+            created_at: run.created_at,
+        })
+    }
+}
+
+/// 保存的基准净值
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StoredBenchmark {
+    pub ts_code: String,  // 基准指数代码
+    pub equity: Vec<f64>, // 基准净值
+    pub excess: Vec<f64>, // 累计超额收益率
+}
+
+/// 回测记录详情，字段与 /stock/simulate 的返回值一致，前端可以直接展示
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BacktestRunDetail {
+    #[serde(flatten)]
+    pub run: BacktestRunSummary, // 回测记录概要
+    pub request: Value,                     // 回测请求参数
+    pub df_stock: Vec<TradeResult>,         // 每日行情和总资产，不含策略指标
+    pub operate_record: Vec<OperateRecord>, // 交易记录
+    pub reject_record: Vec<OperateRecord>,  // 未能成交的订单
+    pub benchmark: Option<StoredBenchmark>, // 基准净值
+}
+
+/// 生成交易记录表的一行
+fn new_trade(run_id: i64, rejected: bool, record: &OperateRecord) -> NewBacktestTrade {
+    NewBacktestTrade {
+        run_id,
+        rejected,
+        code: record.code.clone(),
+        order_type: match record.order_type {
+            OrderType::Buy => "Buy".to_string(),
+            OrderType::Sell => "Sell".to_string(),
+        },
+        hold: record.hold as u64,
+        assets: record.assets,
+        operate_num: record.operate_num as u64,
+        close: record.close,
+        operate_date: record.operate_date.clone(),
+        price: record.price,
+        fee: record.fee,
+        reason: record.reason.clone(),
+    }
+}
+
+/// 保存一次回测，返回回测记录 id
+/// run: 回测记录
+/// df_stock: 每日行情和总资产
+/// benchmark_equity: 基准净值，与 df_stock 一一对应
+/// operate_record: 交易记录
+/// reject_record: 未能成交的订单
+pub async fn save_backtest_run(
+    conn: &mut AsyncMysqlConnection,
+    run: NewBacktestRun,
+    df_stock: &[TradeResult],
+    benchmark_equity: Option<&[f64]>,
+    operate_record: &[OperateRecord],
+    reject_record: &[OperateRecord],
+) -> Result<i64> {
+    diesel::sql_function!(fn last_insert_id() -> BigInt);
+    let equity_list: Vec<BacktestEquity> = df_stock
+        .iter()
+        .enumerate()
+        .map(|(index, day)| BacktestEquity {
+            run_id: 0,
+            trade_date: day.date.clone().unwrap_or_default(),
+            open: day.open,
+            close: day.close,
+            high: day.high,
+            low: day.low,
+            volume: day.volume,
+            signal: day.signal.map(|signal| signal as i32),
+            hold: day.hold as u64,
+            total_assets: day.total_assets.unwrap_or(0.0),
+            benchmark: benchmark_equity.and_then(|equity| equity.get(index).copied()),
+        })
+        .collect();
+    let trade_list: Vec<(bool, OperateRecord)> = operate_record
+        .iter()
+        .map(|record| (false, record.clone()))
+        .chain(reject_record.iter().map(|record| (true, record.clone())))
+        .collect();
+    let run_id = conn
+        .transaction(|mut conn| {
+            Box::pin(async move {
+                diesel::insert_into(backtest_run::table)
+                    .values(&run)
+                    .execute(&mut conn)
+                    .await?;
+                let run_id: i64 = backtest_run::table
+                    .select(last_insert_id())
+                    .first(&mut conn)
+                    .await?;
+                let equity_list: Vec<BacktestEquity> = equity_list
+                    .into_iter()
+                    .map(|equity| BacktestEquity { run_id, ..equity })
+                    .collect();
+                for batch in equity_list.chunks(5000) {
+                    diesel::insert_into(backtest_equity::table)
+                        .values(batch)
+                        .execute(&mut conn)
+                        .await?;
+                }
+                let trade_list: Vec<NewBacktestTrade> = trade_list
+                    .iter()
+                    .map(|(rejected, record)| new_trade(run_id, *rejected, record))
+                    .collect();
+                for batch in trade_list.chunks(5000) {
+                    diesel::insert_into(backtest_trade::table)
+                        .values(batch)
+                        .execute(&mut conn)
+                        .await?;
+                }
+                Ok::<_, diesel::result::Error>(run_id)
+            })
+        })
+        .await?;
+    Ok(run_id)
+}
+
+/// 按创建时间倒序列出回测记录
+/// limit: 条数
+/// offset: 跳过的条数
+pub async fn list_backtest_runs(
+    conn: &mut AsyncMysqlConnection,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<BacktestRunSummary>> {
+    let run_list = backtest_run::table
+        .order(backtest_run::id.desc())
+        .limit(limit)
+        .offset(offset)
+        .load::<BacktestRun>(conn)
+        .await?;
+    run_list
+        .into_iter()
+        .map(BacktestRunSummary::try_from)
+        .collect()
+}
+
+/// 读取回测记录的每日净值
+/// run_id: 回测记录 id
+pub async fn get_backtest_equity(
+    conn: &mut AsyncMysqlConnection,
+    run_id: i64,
+) -> Result<Vec<BacktestEquity>> {
+    Ok(backtest_equity::table
+        .filter(backtest_equity::run_id.eq(run_id))
+        .order(backtest_equity::trade_date.asc())
+        .load::<BacktestEquity>(conn)
+        .await?)
+}
+
+/// 读取一次回测的全部记录，回测记录不存在时返回 None
+/// id: 回测记录 id
+pub async fn get_backtest_run(
+    conn: &mut AsyncMysqlConnection,
+    id: i64,
+) -> Result<Option<BacktestRunDetail>> {
+    let mut run_list = backtest_run::table
+        .filter(backtest_run::id.eq(id))
+        .load::<BacktestRun>(conn)
+        .await?;
+    let run = match run_list.pop() {
+        Some(run) => run,
+        None => return Ok(None),
+    };
+    let equity_list = get_backtest_equity(conn, id).await?;
+    let trade_list = backtest_trade::table
+        .filter(backtest_trade::run_id.eq(id))
+        .order(backtest_trade::id.asc())
+        .load::<BacktestTrade>(conn)
+        .await?;
+    let request: Value = parse_stored(run.id, "request", &run.request)?;
+    let run = BacktestRunSummary::try_from(run)?;
+    let code = <[String]>::first(&run.codes).cloned().unwrap_or_default();
+    let benchmark = run.benchmark.clone().and_then(|ts_code| {
+        let equity: Vec<f64> = equity_list
+            .iter()
+            .map(|day| day.benchmark)
+            .collect::<Option<Vec<f64>>>()?;
+        if equity.is_empty() || run.init_cash <= 0.0 {
+            return None;
+        }
+        let excess = equity_list
+            .iter()
+            .zip(equity.iter())
+            .map(|(day, bench)| (day.total_assets - bench) / run.init_cash)
+            .collect();
+        Some(StoredBenchmark {
+            ts_code,
+            equity,
+            excess,
+        })
+    });
+    let df_stock = equity_list
+        .into_iter()
+        .map(|day| TradeResult {
+            code: code.clone(),
+            date: Some(day.trade_date),
+            open: day.open,
+            close: day.close,
+            high: day.high,
+            low: day.low,
+            volume: day.volume,
+            signal: day.signal.map(|signal| signal as usize),
+            hold: day.hold as usize,
+            indicators: BTreeMap::new(),
+            total_assets: Some(day.total_assets),
+        })
+        .collect();
+    let (mut operate_record, mut reject_record) = (vec![], vec![]);
+    for trade in trade_list {
+        let record = OperateRecord {
+            code: trade.code,
+            order_type: if trade.order_type == "Sell" {
+                OrderType::Sell
+            } else {
+                OrderType::Buy
+            },
+            hold: trade.hold as usize,
+            assets: trade.assets,
+            operate_num: trade.operate_num as usize,
+            close: trade.close,
+            operate_date: trade.operate_date,
+            price: trade.price,
+            fee: trade.fee,
+            reason: trade.reason,
+        };
+        if trade.rejected {
+            reject_record.push(record);
+        } else {
+            operate_record.push(record);
+        }
+    }
+    Ok(Some(BacktestRunDetail {
+        run,
+        request,
+        df_stock,
+        operate_record,
+        reject_record,
+        benchmark,
+    }))
+}
+
+/// 删除回测记录及其净值和交易记录，返回删除的回测记录条数
+/// ids: 回测记录 id
+pub async fn delete_backtest_runs(conn: &mut AsyncMysqlConnection, ids: Vec<i64>) -> Result<usize> {
+    let affected = conn
+        .transaction(|mut conn| {
+            Box::pin(async move {
+                diesel::delete(backtest_equity::table)
+                    .filter(backtest_equity::run_id.eq_any(&ids))
+                    .execute(&mut conn)
+                    .await?;
+                diesel::delete(backtest_trade::table)
+                    .filter(backtest_trade::run_id.eq_any(&ids))
+                    .execute(&mut conn)
+                    .await?;
+                diesel::delete(backtest_run::table)
+                    .filter(backtest_run::id.eq_any(&ids))
+                    .execute(&mut conn)
+                    .await
+            })
+        })
+        .await?;
+    Ok(affected)
+}
+
+/// 单次回测对齐后的曲线
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CompareSeries {
+    #[serde(flatten)]
+    pub run: BacktestRunSummary, // 回测记录概要，包含绩效指标
+    pub nav: Vec<Option<f64>>,      // 净值，与 dates 一一对应
+    pub drawdown: Vec<Option<f64>>, // 回撤，与 dates 一一对应
+}
+
+/// 多次回测的对比结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BacktestCompare {
+    pub dates: Vec<String>,       // 全部回测日期的并集
+    pub runs: Vec<CompareSeries>, // 按请求顺序排列的回测
+}
+
+/// 对比多次回测，少于两个回测记录或任意一个回测记录不存在时返回错误
+/// ids: 回测记录 id
+pub async fn compare_backtest_runs(
+    conn: &mut AsyncMysqlConnection,
+    ids: Vec<i64>,
+) -> Result<BacktestCompare> {
+    if ids.len() < 2 {
+        return Err(AppErrorEnum::InvalidParam(
+            "至少需要两个回测记录".to_string(),
+        ));
+    }
+    let run_list = backtest_run::table
+        .filter(backtest_run::id.eq_any(&ids))
+        .load::<BacktestRun>(conn)
+        .await?;
+    let missing: Vec<String> = ids
+        .iter()
+        .filter(|id| !run_list.iter().any(|run| run.id == **id))
+        .map(|id| id.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppErrorEnum::InvalidParam(format!(
+            "回测记录不存在: {}",
+            missing.join(",")
+        )));
+    }
+    let mut equity_map: BTreeMap<i64, Vec<BacktestEquity>> = BTreeMap::new();
+    for id in &ids {
+        equity_map.insert(*id, get_backtest_equity(conn, *id).await?);
+    }
+    let dates: Vec<String> = equity_map
+        .values()
+        .flatten()
+        .map(|day| day.trade_date.clone())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect();
+    let runs = ids
+        .iter()
+        .filter_map(|id| run_list.iter().find(|run| run.id == *id))
+        .map(|run| {
+            let assets_map: BTreeMap<&str, f64> = equity_map[&run.id]
+                .iter()
+                .map(|day| (day.trade_date.as_str(), day.total_assets))
+                .collect();
+            let (mut last_nav, mut peak) = (None, 0.0_f64);
+            let mut nav = Vec::with_capacity(dates.len());
+            let mut drawdown = Vec::with_capacity(dates.len());
+            for date in &dates {
+                if let Some(assets) = assets_map.get(date.as_str()) {
+                    if run.init_cash > 0.0 {
+                        last_nav = Some(assets / run.init_cash);
+                    }
+                }
+                nav.push(last_nav);
+                drawdown.push(last_nav.map(|value| {
+                    peak = peak.max(value);
+                    if peak > 0.0 {
+                        1.0 - value / peak
+                    } else {
+                        0.0
+                    }
+                }));
+            }
+            Ok(CompareSeries {
+                run: BacktestRunSummary::try_from(run.clone())?,
+                nav,
+                drawdown,
+            })
+        })
+        .collect::<Result<Vec<CompareSeries>>>()?;
+    Ok(BacktestCompare { dates, runs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored_run(metrics: &str) -> BacktestRun {
+        BacktestRun {
+            id: 1,
+            codes: "600000.SH,600036.SH".to_string(),
+            strategy: "turtle_channel".to_string(),
+            seed: 42,
+            iterations: 100,
+            init_cash: 100000.0,
+            start_date: None,
+            end_date: None,
+            benchmark: None,
+            request: "{}".to_string(),
+            best_param: r#"{"n1": 14.0}"#.to_string(),
+            metrics: metrics.to_string(),
+            created_at: "2024-01-01 00:00:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn stored_run_round_trips() {
+        let metrics = json::to_string(&BacktestMetrics::default()).unwrap();
+        let summary = BacktestRunSummary::try_from(stored_run(&metrics)).unwrap();
+        assert_eq!(summary.codes, vec!["600000.SH", "600036.SH"]);
+        assert_eq!(summary.best_param["n1"], 14.0);
+    }
+
+    #[test]
+    fn corrupt_run_is_an_error() {
+        match BacktestRunSummary::try_from(stored_run(r#"{"total_return": "#)) {
+            Err(AppErrorEnum::CorruptRecord(message)) => {
+                assert!(message.contains("metrics"), "{}", message)
+            }
+            _ => panic!("expected CorruptRecord"),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use crate::db::schema::index_daily;
+use crate::db::stock_info::{IndexDailyInfo, StockPriceInfo};
+use crate::stock_lib::get_stock_rps_list::DailyRes;
+use crate::AppErrorEnum;
+use diesel::{ExpressionMethods, QueryDsl};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::diesel::{AsyncConnection, AsyncMysqlConnection, RunQueryDsl};
+use std::collections::BTreeMap;
+
+/*
+* 基准指数对比
+*   基准净值：初始资金 * 指数收盘点位 / 回测第一天的指数收盘点位，回测日期指数没有数据时沿用前一天的点位
+*   超额收益：策略累计收益率 - 基准累计收益率
+*   用策略和基准的日收益率 r_s、r_b 计算
+*     beta = cov(r_s, r_b) / var(r_b)
+*     alpha = (mean(r_s) - beta * mean(r_b)) * 252，年化，无风险利率取 0
+*     信息比率 = mean(r_s - r_b) / std(r_s - r_b) * sqrt(252)
+*/
+
+// 定义一个通用的 Result 类型，默认错误类型为 AppErrorEnum
+type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
+
+/// 默认的基准指数：沪深 300
+pub const DEFAULT_BENCHMARK: &str = "000300.SH";
+
+const TRADING_DAYS: f64 = 252.0;
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+struct IndexDailyReq {
+    api_name: String,
+    token: String,
+    params: IndexDailyParams,
+    fields: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+struct IndexDailyParams {
+    ts_code: String,
+    start_date: String,
+    end_date: String,
+}
+
+/// 获取指数一段时间内的日线数据
+/// ts_code：指数代码，如 000300.SH、000905.SH
+/// date_range：（开始时间，结束时间）时间范围
+pub async fn get_index_daily_data(
+    ts_code: String,
+    date_range: (String, String),
+) -> Result<Vec<IndexDailyInfo>, reqwest::Error> {
+    let (start_date, end_date) = date_range;
+    let client = reqwest::Client::new();
+    let res = client
+        .post("http://api.tushare.pro")
+        .json(&IndexDailyReq {
+            api_name: "index_daily".to_string(),
+            token: "---".to_string(),
+            params: IndexDailyParams {
+                ts_code,
+                start_date,
+                end_date,
+            },
+            // index_daily 默认的字段顺序与 daily 不同，这里指定为与 daily 相同的顺序
+            fields: "ts_code,trade_date,open,high,low,close,pre_close,change,pct_chg,vol,amount"
+                .to_string(),
+        })
+        .send()
+        .await?;
+    let result: DailyRes = res.json().await?;
+    Ok(result
+        .data
+        .items
+        .into_iter()
+        .map(|item| IndexDailyInfo::from(StockPriceInfo::from(item)))
+        .collect())
+}
+
+/// 获取指数日线并写入 index_daily，时间范围内已有的数据会被替换
+/// ts_code：指数代码
+/// start_date：开始日期
+/// end_date：结束日期
+pub async fn fetch_index_daily(
+    conn: &mut AsyncMysqlConnection,
+    ts_code: String,
+    start_date: String,
+    end_date: String,
+) -> Result<usize> {
+    let index_list =
+        get_index_daily_data(ts_code.clone(), (start_date.clone(), end_date.clone())).await?;
+    if index_list.is_empty() {
+        return Ok(0);
+    }
+    let affected = conn
+        .transaction(|mut conn| {
+            Box::pin(async move {
+                diesel::delete(index_daily::table)
+                    .filter(index_daily::ts_code.eq(ts_code))
+                    .filter(index_daily::trade_date.ge(start_date))
+                    .filter(index_daily::trade_date.le(end_date))
+                    .execute(&mut conn)
+                    .await?;
+                diesel::insert_into(index_daily::table)
+                    .values(&index_list)
+                    .execute(&mut conn)
+                    .await
+            })
+        })
+        .await?;
+    Ok(affected)
+}
+
+/// 获取本地保存的指数收盘点位，交易日期 -> 收盘点位
+/// ts_code：指数代码
+pub async fn get_local_index_close(
+    conn: &mut AsyncMysqlConnection,
+    ts_code: String,
+) -> Result<BTreeMap<String, f64>> {
+    let close_list = index_daily::table
+        .filter(index_daily::ts_code.eq(ts_code))
+        .select((index_daily::trade_date, index_daily::close))
+        .load::<(Option<String>, Option<f64>)>(conn)
+        .await?;
+    Ok(close_list
+        .into_iter()
+        .filter_map(|(trade_date, close)| Some((trade_date?, close?)))
+        .collect())
+}
+
+/// 回测结果与基准指数的对比
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BenchmarkReport {
+    pub ts_code: String,        // 基准指数代码
+    pub equity: Vec<f64>,       // 基准净值，与回测的日期一一对应
+    pub excess: Vec<f64>,       // 累计超额收益率
+    pub alpha: f64,             // 年化 alpha
+    pub beta: f64,              // beta
+    pub information_ratio: f64, // 信息比率
+}
+
+/// 计算收益率序列的均值和标准差
+fn col_mean_std(list: &[f64]) -> (f64, f64) {
+    let len = list.len().max(1) as f64;
+    let mean = list.iter().sum::<f64>() / len;
+    let var = list.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / len;
+    (mean, var.sqrt())
+}
+
+/// 计算回测相对基准指数的表现，回测开始前指数没有数据时返回 None
+/// ts_code：基准指数代码
+/// dates：回测的交易日期
+/// total_assets：每日总资产
+/// init_cash：初始资金
+/// index_close：交易日期 -> 指数收盘点位
+pub fn col_benchmark(
+    ts_code: &str,
+    dates: &[String],
+    total_assets: &[f64],
+    init_cash: f64,
+    index_close: &BTreeMap<String, f64>,
+) -> Option<BenchmarkReport> {
+    if dates.is_empty() || init_cash <= 0.0 {
+        return None;
+    }
+    // 回测第一天及之前最近的指数点位作为基准
+    let base_close = *index_close.range(..=dates[0].clone()).next_back()?.1;
+    if base_close <= 0.0 {
+        return None;
+    }
+    let mut last_close = base_close;
+    let index_list: Vec<f64> = dates
+        .iter()
+        .map(|date| {
+            if let Some(close) = index_close.get(date) {
+                last_close = *close;
+            }
+            last_close
+        })
+        .collect();
+    let equity: Vec<f64> = index_list
+        .iter()
+        .map(|close| init_cash * close / base_close)
+        .collect();
+    let excess: Vec<f64> = total_assets
+        .iter()
+        .zip(equity.iter())
+        .map(|(assets, bench)| (assets - bench) / init_cash)
+        .collect();
+    // 日收益率，第一天相对初始资金计算
+    let col_daily_return = |curve: &[f64]| -> Vec<f64> {
+        std::iter::once(init_cash)
+            .chain(curve.iter().copied())
+            .collect::<Vec<f64>>()
+            .windows(2)
+            .map(|pair| {
+                if pair[0] > 0.0 {
+                    pair[1] / pair[0] - 1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    };
+    let strategy_return = col_daily_return(total_assets);
+    let bench_return = col_daily_return(&equity);
+    let (strategy_mean, _) = col_mean_std(&strategy_return);
+    let (bench_mean, bench_std) = col_mean_std(&bench_return);
+    let cov = strategy_return
+        .iter()
+        .zip(bench_return.iter())
+        .map(|(s, b)| (s - strategy_mean) * (b - bench_mean))
+        .sum::<f64>()
+        / strategy_return.len().max(1) as f64;
+    let beta = if bench_std > 0.0 {
+        cov / bench_std.powi(2)
+    } else {
+        0.0
+    };
+    let active_return: Vec<f64> = strategy_return
+        .iter()
+        .zip(bench_return.iter())
+        .map(|(s, b)| s - b)
+        .collect();
+    let (active_mean, active_std) = col_mean_std(&active_return);
+    let information_ratio = if active_std > 0.0 {
+        active_mean / active_std * TRADING_DAYS.sqrt()
+    } else {
+        0.0
+    };
+    Some(BenchmarkReport {
+        ts_code: ts_code.to_string(),
+        equity,
+        excess,
+        alpha: (strategy_mean - beta * bench_mean) * TRADING_DAYS,
+        beta,
+        information_ratio,
+    })
+}
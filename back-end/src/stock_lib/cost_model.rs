@@ -0,0 +1,187 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::stock_trade::{
+    OrderType, DEFAULT_COMMISSION_COEFFICIENT, DEFAULT_TAX_COEFFICIENT,
+};
+use rocket::serde::{Deserialize, Serialize};
+
+/*
+* 交易成本模型
+*   1. 佣金：买卖双向收取，成交金额 * 佣金费率，不足最低佣金时按最低佣金收取
+*   2. 印花税：只在卖出时收取，成交金额 * 印花税率
+*   3. 过户费：买卖双向收取，成交金额 * 过户费率
+*   4. 滑点：成交价相对收盘价（或事件驱动引擎中的开盘价、限价）的偏移，买入向上、卖出向下，且不超出当天的最高价和最低价
+*/
+
+pub const DEFAULT_MIN_COMMISSION: f64 = 5.0; // 每笔最低佣金，默认 5 元
+pub const DEFAULT_TRANSFER_FEE_COEFFICIENT: f64 = 0.00001; // 过户费系数，默认十万分之一
+
+/// 滑点模型
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum Slippage {
+    #[default]
+    None, // 以收盘价成交
+    FixedBps {
+        bps: f64, // 固定滑点，单位为万分之一
+    },
+    RangeFraction {
+        fraction: f64, // 滑点为当天振幅（最高价 - 最低价）的比例
+    },
+    VolumeParticipation {
+        impact: f64, // 滑点比例 = impact * 委托股数 / 当天成交股数
+    },
+}
+
+/// 交易成本模型，每次回测请求可以单独设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CostModel {
+    pub commission_coeff: f64,   // 佣金系数，买卖双向收取
+    pub min_commission: f64,     // 每笔最低佣金
+    pub tax_coeff: f64,          // 印花税系数，只在卖出时收取
+    pub transfer_fee_coeff: f64, // 过户费系数，买卖双向收取
+    pub slippage: Slippage,      // 滑点模型
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            commission_coeff: DEFAULT_COMMISSION_COEFFICIENT,
+            min_commission: DEFAULT_MIN_COMMISSION,
+            tax_coeff: DEFAULT_TAX_COEFFICIENT,
+            transfer_fee_coeff: DEFAULT_TRANSFER_FEE_COEFFICIENT,
+            slippage: Slippage::None,
+        }
+    }
+}
+
+impl CostModel {
+    /// 计算一笔交易的总费用
+    /// value: 成交金额
+    /// order_type: 买入或卖出
+    pub fn fee(&self, value: f64, order_type: &OrderType) -> f64 {
+        if value <= 0.0 {
+            return 0.0;
+        }
+        let commission = (value * self.commission_coeff).max(self.min_commission);
+        let transfer_fee = value * self.transfer_fee_coeff;
+        let tax = match order_type {
+            OrderType::Buy => 0.0,
+            OrderType::Sell => value * self.tax_coeff,
+        };
+        commission + transfer_fee + tax
+    }
+    /// 计算现金最多能买入的股数，买入金额加上费用不超过现金
+    /// cash: 可用现金
+    /// price: 成交价
+    pub fn max_affordable(&self, cash: f64, price: f64) -> usize {
+        if cash <= 0.0 || price <= 0.0 {
+            return 0;
+        }
+        // 费用 = max(金额 * 佣金系数, 最低佣金) + 金额 * 过户费系数，两种情况分别求上限
+        let by_rate = cash / (price * (1.0 + self.commission_coeff + self.transfer_fee_coeff));
+        let by_min = (cash - self.min_commission) / (price * (1.0 + self.transfer_fee_coeff));
+        by_rate.min(by_min).max(0.0).floor() as usize
+    }
+    /// 计算含滑点的成交价
+    /// bar: 成交当天的 K 线
+    /// amount: 成交股数
+    /// order_type: 买入或卖出
+    pub fn fill_price(&self, bar: &StockPriceInfo, amount: usize, order_type: &OrderType) -> f64 {
+        self.fill_price_from(bar, bar.close.unwrap_or(0.0), amount, order_type)
+    }
+    /// 以指定价格为基准计算含滑点的成交价，如开盘价、限价
+    /// bar: 成交当天的 K 线
+    /// base_price: 不含滑点的成交价
+    /// amount: 成交股数
+    /// order_type: 买入或卖出
+    pub fn fill_price_from(
+        &self,
+        bar: &StockPriceInfo,
+        base_price: f64,
+        amount: usize,
+        order_type: &OrderType,
+    ) -> f64 {
+        let slip = match &self.slippage {
+            Slippage::None => 0.0,
+            Slippage::FixedBps { bps } => base_price * bps / 10000.0,
+            Slippage::RangeFraction { fraction } => match (bar.high, bar.low) {
+                (Some(high), Some(low)) => (high - low) * fraction,
+                _ => 0.0,
+            },
+            Slippage::VolumeParticipation { impact } => match bar.vol {
+                // 成交量的单位为手，一手 100 股
+                Some(vol) if vol > 0.0 => base_price * impact * amount as f64 / (vol * 100.0),
+                _ => 0.0,
+            },
+        };
+        match order_type {
+            OrderType::Buy => (base_price + slip).min(bar.high.unwrap_or(f64::MAX)),
+            OrderType::Sell => (base_price - slip).max(bar.low.unwrap_or(0.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::{assert_close, mock_bar};
+
+    #[test]
+    fn small_buy_pays_min_commission_and_transfer_fee() {
+        let cost_model = CostModel::default();
+        // 成交 1000 元：佣金 0.25 元不足 5 元按 5 元收取，过户费 0.01 元，买入不收印花税
+        assert_close(cost_model.fee(1000.0, &OrderType::Buy), 5.01);
+        // 成交 100000 元：佣金 25 元，过户费 1 元
+        assert_close(cost_model.fee(100000.0, &OrderType::Buy), 26.0);
+    }
+
+    #[test]
+    fn small_sell_pays_stamp_duty() {
+        let cost_model = CostModel::default();
+        // 成交 1000 元：最低佣金 5 元，过户费 0.01 元，印花税 1 元
+        assert_close(cost_model.fee(1000.0, &OrderType::Sell), 6.01);
+        // 成交 100000 元：佣金 25 元，过户费 1 元，印花税 100 元
+        assert_close(cost_model.fee(100000.0, &OrderType::Sell), 126.0);
+        assert_close(cost_model.fee(0.0, &OrderType::Sell), 0.0);
+    }
+
+    #[test]
+    fn max_affordable_covers_fees() {
+        let cost_model = CostModel::default();
+        let shares = cost_model.max_affordable(10000.0, 10.0);
+        assert_eq!(shares, 999);
+        let value = shares as f64 * 10.0;
+        assert!(value + cost_model.fee(value, &OrderType::Buy) <= 10000.0);
+        assert_eq!(cost_model.max_affordable(4.0, 1.0), 0);
+    }
+
+    #[test]
+    fn slippage_moves_against_the_order() {
+        let bar = mock_bar("600000.SH", 0, 10.0);
+        let cost_model = CostModel {
+            slippage: Slippage::FixedBps { bps: 10.0 },
+            ..CostModel::default()
+        };
+        assert_close(cost_model.fill_price(&bar, 100, &OrderType::Buy), 10.01);
+        assert_close(cost_model.fill_price(&bar, 100, &OrderType::Sell), 9.99);
+        // 振幅 0.4 元的 10%
+        let cost_model = CostModel {
+            slippage: Slippage::RangeFraction { fraction: 0.1 },
+            ..CostModel::default()
+        };
+        assert_close(cost_model.fill_price(&bar, 100, &OrderType::Buy), 10.04);
+        assert_close(cost_model.fill_price(&bar, 100, &OrderType::Sell), 9.96);
+        // 委托 10 万股占当天成交 100 万股的 10%，滑点 0.5 * 10% = 5%，被最高价和最低价截断
+        let cost_model = CostModel {
+            slippage: Slippage::VolumeParticipation { impact: 0.5 },
+            ..CostModel::default()
+        };
+        assert_close(cost_model.fill_price(&bar, 100000, &OrderType::Buy), 10.2);
+        assert_close(cost_model.fill_price(&bar, 100000, &OrderType::Sell), 9.8);
+        assert_close(
+            CostModel::default().fill_price(&bar, 100, &OrderType::Buy),
+            10.0,
+        );
+    }
+}
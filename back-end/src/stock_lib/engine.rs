@@ -0,0 +1,602 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::stock_trade::{OperateRecord, OrderType, ST_Account};
+use crate::stock_lib::strategy::TargetPosition;
+use rocket::serde::{Deserialize, Serialize};
+
+/*
+* 事件驱动的撮合引擎
+*   策略在第 t 根 K 线收盘后给出目标仓位，引擎按目标仓位与持仓的差值生成订单，从第 t + 1 根 K 线开始撮合
+*   1. 开盘价市价单：以下一根 K 线的开盘价成交
+*   2. 收盘价市价单：以下一根 K 线的收盘价成交
+*   3. 限价单：买入限价 = 信号收盘价 * (1 - offset_bps / 10000)，最低价不高于限价时以 min(开盘价, 限价) 成交；卖出方向相反
+*   4. 止损单：买入触发价 = 信号收盘价 * (1 + offset_bps / 10000)，最高价不低于触发价时以 max(开盘价, 触发价) 成交；卖出方向相反
+*   5. 部分成交：每根 K 线最多成交当天成交量的 participation 比例（按交易单位向下取整），没成交完的部分继续挂单，
+*      上限不足一个交易单位时当天不成交；因现金或可卖股数不足少成交时，剩余部分撤单
+*   6. 订单有效期为 expire_bars 根 K 线，到期未成交的部分撤单；因成交量上限少成交的 K 线不计入有效期，
+*      剩余部分一直挂单到全部成交；策略给出新的目标仓位时撤销旧订单
+*   7. 滑点以上述成交价为基准，涨跌停、整手和现金检查与收盘价成交时相同，不能成交的订单记为拒单
+*   订单的每次状态变化都记录为订单事件
+*/
+
+/// 订单类型
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum OrderKind {
+    #[default]
+    MarketOnOpen, // 下一根 K 线开盘价成交
+    MarketOnClose, // 下一根 K 线收盘价成交
+    Limit {
+        offset_bps: f64, // 限价相对信号收盘价的偏移，买入向下、卖出向上，单位为万分之一
+    },
+    Stop {
+        offset_bps: f64, // 触发价相对信号收盘价的偏移，买入向上、卖出向下，单位为万分之一
+    },
+}
+
+/// 事件驱动引擎的下单设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct ExecutionConfig {
+    pub order_kind: OrderKind, // 订单类型
+    pub participation: f64,    // 每根 K 线最多成交当天成交量的比例，默认 0.1，0 表示不限制
+    pub expire_bars: usize,    // 订单有效的 K 线根数，默认 1，即当日有效，不含受成交量限制的 K 线
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        ExecutionConfig {
+            order_kind: OrderKind::MarketOnOpen,
+            participation: 0.1,
+            expire_bars: 1,
+        }
+    }
+}
+
+/// 订单状态
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum OrderStatus {
+    Submitted,       // 已提交
+    PartiallyFilled, // 部分成交
+    Filled,          // 全部成交
+    Cancelled,       // 已撤单
+    Expired,         // 到期未成交
+    Rejected,        // 被交易规则拒绝
+}
+
+/// 订单事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OrderEvent {
+    pub order_id: usize,        // 订单编号，同一次回测内唯一
+    pub code: String,           // 股票代码
+    pub date: Option<String>,   // 事件发生的日期
+    pub status: OrderStatus,    // 订单状态
+    pub order_type: OrderType,  // 买入或卖出
+    pub kind: OrderKind,        // 订单类型
+    pub quantity: usize,        // 委托股数
+    pub filled: usize,          // 本次成交股数，提交和撤单时为 0
+    pub price: Option<f64>,     // 成交价（含滑点），提交时为限价或触发价
+    pub reason: Option<String>, // 撤单、拒单或数量调整的原因
+}
+
+/// 未完成的订单
+struct PendingOrder {
+    id: usize,
+    code: String,
+    order_type: OrderType,
+    quantity: usize,      // 委托股数
+    filled: usize,        // 已成交股数
+    trigger: Option<f64>, // 限价或止损触发价
+    bars_left: usize,     // 剩余有效的 K 线根数
+}
+
+/// 单只股票的撮合引擎，每次回测新建一个
+pub struct ExecutionEngine {
+    config: ExecutionConfig,
+    next_id: usize,
+    pending: Option<PendingOrder>,
+    pub events: Vec<OrderEvent>, // 订单事件，按发生的先后顺序排列
+}
+
+impl ExecutionEngine {
+    pub fn new(config: ExecutionConfig) -> Self {
+        ExecutionEngine {
+            config,
+            next_id: 1,
+            pending: None,
+            events: vec![],
+        }
+    }
+
+    /// 记录订单事件
+    fn push_event(
+        &mut self,
+        order: &PendingOrder,
+        bar: &StockPriceInfo,
+        status: OrderStatus,
+        filled: usize,
+        price: Option<f64>,
+        reason: Option<String>,
+    ) {
+        self.events.push(OrderEvent {
+            order_id: order.id,
+            code: order.code.clone(),
+            date: bar.trade_date.clone(),
+            status,
+            order_type: order.order_type.clone(),
+            kind: self.config.order_kind.clone(),
+            quantity: order.quantity,
+            filled,
+            price,
+            reason,
+        });
+    }
+
+    /// 按目标仓位与当前持仓的差值提交订单，未完成的旧订单先撤单
+    /// account: 股票账户
+    /// bar: 产生信号的 K 线
+    /// target: 策略给出的目标仓位
+    pub fn submit(&mut self, account: &ST_Account, bar: &StockPriceInfo, target: TargetPosition) {
+        if let Some(order) = self.pending.take() {
+            self.push_event(
+                &order,
+                bar,
+                OrderStatus::Cancelled,
+                0,
+                None,
+                Some("新的目标仓位替换旧订单".to_string()),
+            );
+        }
+        let hold = account.hold_available(bar.ts_code.clone());
+        let target_num = target.shares();
+        let (order_type, quantity) = if target_num > hold {
+            (OrderType::Buy, target_num - hold)
+        } else if target_num < hold {
+            (OrderType::Sell, hold - target_num)
+        } else {
+            return;
+        };
+        let close = bar.close.unwrap_or(0.0);
+        let trigger = match (&self.config.order_kind, &order_type) {
+            (OrderKind::MarketOnOpen | OrderKind::MarketOnClose, _) => None,
+            (OrderKind::Limit { offset_bps }, OrderType::Buy)
+            | (OrderKind::Stop { offset_bps }, OrderType::Sell) => {
+                Some(close * (1.0 - offset_bps / 10000.0))
+            }
+            (OrderKind::Limit { offset_bps }, OrderType::Sell)
+            | (OrderKind::Stop { offset_bps }, OrderType::Buy) => {
+                Some(close * (1.0 + offset_bps / 10000.0))
+            }
+        };
+        let order = PendingOrder {
+            id: self.next_id,
+            code: bar.ts_code.clone(),
+            order_type,
+            quantity,
+            filled: 0,
+            trigger,
+            bars_left: self.config.expire_bars.max(1),
+        };
+        self.next_id += 1;
+        self.push_event(&order, bar, OrderStatus::Submitted, 0, trigger, None);
+        self.pending = Some(order);
+    }
+
+    /// 订单在这根 K 线上的基准成交价，没有触发时返回 None
+    fn base_price(&self, order: &PendingOrder, bar: &StockPriceInfo) -> Option<f64> {
+        let close = bar.close?;
+        let open = bar.open.unwrap_or(close);
+        let high = bar.high.unwrap_or(close.max(open));
+        let low = bar.low.unwrap_or(close.min(open));
+        match (&self.config.order_kind, &order.order_type, order.trigger) {
+            (OrderKind::MarketOnOpen, _, _) => Some(open),
+            (OrderKind::MarketOnClose, _, _) => Some(close),
+            (OrderKind::Limit { .. }, OrderType::Buy, Some(price)) if low <= price => {
+                Some(open.min(price))
+            }
+            (OrderKind::Limit { .. }, OrderType::Sell, Some(price)) if high >= price => {
+                Some(open.max(price))
+            }
+            (OrderKind::Stop { .. }, OrderType::Buy, Some(price)) if high >= price => {
+                Some(open.max(price))
+            }
+            (OrderKind::Stop { .. }, OrderType::Sell, Some(price)) if low <= price => {
+                Some(open.min(price))
+            }
+            _ => None,
+        }
+    }
+
+    /// 用新的 K 线撮合未完成的订单，有成交时返回交易信号：1 买入，0 卖出
+    /// account: 股票账户
+    /// bar: 撮合用的 K 线
+    /// operate_query: 交易记录
+    /// reject_query: 被交易规则拒绝的订单
+    pub fn match_orders(
+        &mut self,
+        account: &mut ST_Account,
+        bar: &StockPriceInfo,
+        operate_query: &mut Vec<OperateRecord>,
+        reject_query: &mut Vec<OperateRecord>,
+    ) -> Option<usize> {
+        let mut order = self.pending.take()?;
+        let mut signal = None;
+        // 这根 K 线是否因成交量上限少成交
+        let mut capped = false;
+        if let Some(base_price) = self.base_price(&order, bar) {
+            let remaining = order.quantity - order.filled;
+            // 成交量的单位为手，一手 100 股；成交量上限按交易单位取整，避免取整后的差额被当作现金不足而撤单
+            let amount = match bar.vol {
+                Some(vol) if self.config.participation > 0.0 => {
+                    let cap = (vol * 100.0 * self.config.participation).floor() as usize;
+                    if cap < remaining {
+                        account.round_lot(&order.code, cap, &order.order_type)
+                    } else {
+                        remaining
+                    }
+                }
+                _ => remaining,
+            };
+            capped = amount < remaining;
+            // 成交量上限不足一个交易单位时当天不成交，继续挂单等待下一根 K 线
+            if amount > 0 {
+                let close = bar.close.unwrap_or(base_price);
+                match account.check_order_at(bar, base_price, amount, &order.order_type) {
+                    Err(reason) => {
+                        reject_query.push(OperateRecord {
+                            code: order.code.clone(),
+                            order_type: order.order_type.clone(),
+                            hold: account.hold_available(order.code.clone()),
+                            assets: account.latest_assets(),
+                            operate_num: remaining,
+                            operate_date: bar.trade_date.clone(),
+                            close,
+                            price: base_price,
+                            fee: 0.0,
+                            reason: Some(reason.clone()),
+                        });
+                        self.push_event(&order, bar, OrderStatus::Rejected, 0, None, Some(reason));
+                        return None;
+                    }
+                    Ok((operate_num, reason)) => {
+                        let price = account.fill_price_from(
+                            bar,
+                            base_price,
+                            operate_num,
+                            &order.order_type,
+                        );
+                        let fee = account.send_order(
+                            order.code.clone(),
+                            operate_num,
+                            price,
+                            order.order_type.clone(),
+                        );
+                        order.filled += operate_num;
+                        operate_query.push(OperateRecord {
+                            code: order.code.clone(),
+                            order_type: order.order_type.clone(),
+                            hold: account.hold_available(order.code.clone()),
+                            assets: account.latest_assets(),
+                            operate_num,
+                            operate_date: bar.trade_date.clone(),
+                            close,
+                            price,
+                            fee,
+                            reason: reason.clone(),
+                        });
+                        signal = Some(match order.order_type {
+                            OrderType::Buy => 1,
+                            OrderType::Sell => 0,
+                        });
+                        let status = if order.filled >= order.quantity {
+                            OrderStatus::Filled
+                        } else {
+                            OrderStatus::PartiallyFilled
+                        };
+                        self.push_event(&order, bar, status, operate_num, Some(price), None);
+                        if status == OrderStatus::Filled {
+                            return signal;
+                        }
+                        // 委托股数已按交易单位取整，少成交只可能是现金或可卖股数不足（或剩余不足一个交易单位），
+                        // 剩余部分以后也无法成交，直接撤单
+                        if operate_num < amount {
+                            self.push_event(&order, bar, OrderStatus::Cancelled, 0, None, reason);
+                            return signal;
+                        }
+                    }
+                }
+            }
+        }
+        if !capped {
+            order.bars_left -= 1;
+        }
+        if order.bars_left == 0 {
+            self.push_event(
+                &order,
+                bar,
+                OrderStatus::Expired,
+                0,
+                None,
+                Some(format!("{} 股到期未成交", order.quantity - order.filled)),
+            );
+        } else {
+            self.pending = Some(order);
+        }
+        signal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::{assert_close, mock_account, mock_bar};
+
+    const CODE: &str = "600000.SH";
+
+    /// 在第 0 根 K 线提交目标仓位，之后逐根撮合，返回引擎、账户和交易记录
+    fn run(
+        config: ExecutionConfig,
+        hold: usize,
+        target: TargetPosition,
+        bars: &[StockPriceInfo],
+    ) -> (ExecutionEngine, ST_Account, Vec<OperateRecord>) {
+        let mut engine = ExecutionEngine::new(config);
+        let mut account = mock_account(&[(CODE, hold)], 100000.0);
+        let (mut operate, mut reject) = (vec![], vec![]);
+        engine.submit(&account, &bars[0], target);
+        for bar in &bars[1..] {
+            account.new_trading_day();
+            engine.match_orders(&mut account, bar, &mut operate, &mut reject);
+        }
+        (engine, account, operate)
+    }
+
+    fn statuses(engine: &ExecutionEngine) -> Vec<OrderStatus> {
+        engine.events.iter().map(|event| event.status).collect()
+    }
+
+    /// 开盘价为 open、收盘价为 close 的 K 线
+    fn open_close_bar(day: usize, open: f64, close: f64) -> StockPriceInfo {
+        StockPriceInfo {
+            open: Some(open),
+            high: Some(open.max(close) * 1.01),
+            low: Some(open.min(close) * 0.99),
+            ..mock_bar(CODE, day, close)
+        }
+    }
+
+    /// 成交量为 vol 手的 K 线
+    fn volume_bars(vol_list: &[f64]) -> Vec<StockPriceInfo> {
+        vol_list
+            .iter()
+            .enumerate()
+            .map(|(day, vol)| StockPriceInfo {
+                vol: Some(*vol),
+                ..mock_bar(CODE, day, 10.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partial_fill_is_carried_to_next_bar() {
+        // 成交量 10 手，参与率 15% 上限 150 股，按整手取整为每根 K 线成交 100 股
+        let bars = volume_bars(&[10.0, 10.0, 10.0, 10.0]);
+        let config = ExecutionConfig {
+            participation: 0.15,
+            expire_bars: 5,
+            ..ExecutionConfig::default()
+        };
+        let (engine, account, operate) = run(config, 0, TargetPosition::Shares(300), &bars);
+        assert_eq!(
+            statuses(&engine),
+            vec![
+                OrderStatus::Submitted,
+                OrderStatus::PartiallyFilled,
+                OrderStatus::PartiallyFilled,
+                OrderStatus::Filled
+            ]
+        );
+        assert!(operate.iter().all(|record| record.operate_num == 100));
+        assert_eq!(account.hold_available(CODE.to_string()), 300);
+    }
+
+    #[test]
+    fn default_config_carries_capped_remainder() {
+        // 默认参与率 10%、当日有效：成交量 10 手时每根 K 线成交 100 股，剩余部分不因到期撤单
+        let bars = volume_bars(&[10.0, 10.0, 10.0, 10.0]);
+        let (engine, account, operate) = run(
+            ExecutionConfig::default(),
+            0,
+            TargetPosition::Shares(300),
+            &bars,
+        );
+        assert_eq!(
+            statuses(&engine),
+            vec![
+                OrderStatus::Submitted,
+                OrderStatus::PartiallyFilled,
+                OrderStatus::PartiallyFilled,
+                OrderStatus::Filled
+            ]
+        );
+        assert_eq!(operate.len(), 3);
+        assert_eq!(account.hold_available(CODE.to_string()), 300);
+    }
+
+    #[test]
+    fn cap_below_one_lot_waits() {
+        // 第 1 根 K 线成交量上限 5 股，不足一手，不成交也不拒单
+        let bars = volume_bars(&[10.0, 0.5, 10.0]);
+        let (engine, account, _) = run(
+            ExecutionConfig::default(),
+            0,
+            TargetPosition::Shares(100),
+            &bars,
+        );
+        assert_eq!(
+            statuses(&engine),
+            vec![OrderStatus::Submitted, OrderStatus::Filled]
+        );
+        assert_eq!(account.hold_available(CODE.to_string()), 100);
+    }
+
+    #[test]
+    fn capped_order_is_rejected_by_rules() {
+        // 卖出 300 股受成交量限制，跌停的 K 线上按交易规则拒单
+        let mut bars = volume_bars(&[10.0, 10.0]);
+        bars[1] = StockPriceInfo {
+            pre_close: Some(10.0),
+            ..mock_bar(CODE, 1, 9.0)
+        };
+        let (engine, account, operate) =
+            run(ExecutionConfig::default(), 300, TargetPosition::Flat, &bars);
+        assert_eq!(
+            statuses(&engine),
+            vec![OrderStatus::Submitted, OrderStatus::Rejected]
+        );
+        assert!(engine.events[1].reason.as_ref().unwrap().contains("跌停"));
+        assert!(operate.is_empty());
+        assert_eq!(account.hold_available(CODE.to_string()), 300);
+        assert!(engine.pending.is_none());
+    }
+
+    #[test]
+    fn partially_filled_limit_order_expires() {
+        // 限价 9.9：第 1 根 K 线受成交量限制成交 100 股，之后两根 K 线没有触发限价，到期撤单
+        let mut bars = volume_bars(&[10.0, 10.0, 10.0, 10.0]);
+        bars[2] = mock_bar(CODE, 2, 10.5);
+        bars[3] = mock_bar(CODE, 3, 10.5);
+        let config = ExecutionConfig {
+            order_kind: OrderKind::Limit { offset_bps: 100.0 },
+            expire_bars: 2,
+            ..ExecutionConfig::default()
+        };
+        let (engine, account, _) = run(config, 0, TargetPosition::Shares(300), &bars);
+        assert_eq!(
+            statuses(&engine),
+            vec![
+                OrderStatus::Submitted,
+                OrderStatus::PartiallyFilled,
+                OrderStatus::Expired
+            ]
+        );
+        assert_eq!(account.hold_available(CODE.to_string()), 100);
+        assert!(engine.pending.is_none());
+    }
+
+    #[test]
+    fn untriggered_order_expires() {
+        // 限价 9 元，之后两根 K 线的最低价都高于限价
+        let bars = vec![
+            mock_bar(CODE, 0, 10.0),
+            mock_bar(CODE, 1, 10.0),
+            mock_bar(CODE, 2, 10.0),
+            mock_bar(CODE, 3, 8.0),
+        ];
+        let config = ExecutionConfig {
+            order_kind: OrderKind::Limit { offset_bps: 1000.0 },
+            expire_bars: 2,
+            ..ExecutionConfig::default()
+        };
+        let (engine, account, operate) = run(config, 0, TargetPosition::Shares(100), &bars);
+        assert_eq!(
+            statuses(&engine),
+            vec![OrderStatus::Submitted, OrderStatus::Expired]
+        );
+        assert!(operate.is_empty());
+        assert_eq!(account.hold_available(CODE.to_string()), 0);
+    }
+
+    /// 持有 hold 股、目标 target 股，在信号 K 线之后的一根 K 线上撮合，返回提交时的触发价和成交价
+    fn trigger_fill(
+        order_kind: OrderKind,
+        hold: usize,
+        target: usize,
+        bar: StockPriceInfo,
+    ) -> (Option<f64>, Option<f64>) {
+        let config = ExecutionConfig {
+            order_kind,
+            ..ExecutionConfig::default()
+        };
+        let bars = vec![mock_bar(CODE, 0, 10.0), bar];
+        let (engine, _, operate) = run(config, hold, TargetPosition::Shares(target), &bars);
+        (
+            engine.events[0].price,
+            operate.first().map(|record| record.price),
+        )
+    }
+
+    #[test]
+    fn limit_order_trigger_and_fill() {
+        let limit = OrderKind::Limit { offset_bps: 100.0 };
+        // 买入限价 9.9：最低价 9.8 元时以限价成交，低开时以开盘价成交，最低价高于限价时不成交
+        let (trigger, price) = trigger_fill(limit.clone(), 0, 100, mock_bar(CODE, 1, 10.0));
+        assert_close(trigger.unwrap(), 9.9);
+        assert_close(price.unwrap(), 9.9);
+        let (_, price) = trigger_fill(limit.clone(), 0, 100, open_close_bar(1, 9.5, 9.7));
+        assert_close(price.unwrap(), 9.5);
+        let (_, price) = trigger_fill(limit.clone(), 0, 100, mock_bar(CODE, 1, 10.5));
+        assert!(price.is_none());
+        // 卖出限价 10.1
+        let (trigger, price) = trigger_fill(limit, 100, 0, mock_bar(CODE, 1, 10.0));
+        assert_close(trigger.unwrap(), 10.1);
+        assert_close(price.unwrap(), 10.1);
+    }
+
+    #[test]
+    fn stop_order_trigger_and_fill() {
+        let stop = OrderKind::Stop { offset_bps: 100.0 };
+        // 买入触发价 10.1：最高价 10.2 元时以触发价成交，高开时以开盘价成交，最高价低于触发价时不成交
+        let (trigger, price) = trigger_fill(stop.clone(), 0, 100, mock_bar(CODE, 1, 10.0));
+        assert_close(trigger.unwrap(), 10.1);
+        assert_close(price.unwrap(), 10.1);
+        let (_, price) = trigger_fill(stop.clone(), 0, 100, open_close_bar(1, 10.5, 10.6));
+        assert_close(price.unwrap(), 10.5);
+        let (_, price) = trigger_fill(stop.clone(), 0, 100, mock_bar(CODE, 1, 9.5));
+        assert!(price.is_none());
+        // 卖出触发价 9.9
+        let (trigger, price) = trigger_fill(stop, 100, 0, mock_bar(CODE, 1, 10.0));
+        assert_close(trigger.unwrap(), 9.9);
+        assert_close(price.unwrap(), 9.9);
+    }
+
+    #[test]
+    fn market_on_open_and_close_prices() {
+        let bars = vec![mock_bar(CODE, 0, 10.0), open_close_bar(1, 10.3, 10.6)];
+        let config = ExecutionConfig {
+            order_kind: OrderKind::MarketOnOpen,
+            ..ExecutionConfig::default()
+        };
+        let (_, _, operate) = run(config, 0, TargetPosition::Shares(100), &bars);
+        assert_close(operate[0].price, 10.3);
+        let config = ExecutionConfig {
+            order_kind: OrderKind::MarketOnClose,
+            ..ExecutionConfig::default()
+        };
+        let (_, _, operate) = run(config, 0, TargetPosition::Shares(100), &bars);
+        assert_close(operate[0].price, 10.6);
+    }
+
+    #[test]
+    fn cash_shortfall_cancels_remainder() {
+        let bars = vec![mock_bar(CODE, 0, 10.0), mock_bar(CODE, 1, 10.0)];
+        let config = ExecutionConfig {
+            participation: 0.0,
+            expire_bars: 5,
+            ..ExecutionConfig::default()
+        };
+        // 现金 10 万元最多买入 9900 股
+        let (engine, account, _) = run(config, 0, TargetPosition::Shares(20000), &bars);
+        assert_eq!(
+            statuses(&engine),
+            vec![
+                OrderStatus::Submitted,
+                OrderStatus::PartiallyFilled,
+                OrderStatus::Cancelled
+            ]
+        );
+        assert_eq!(account.hold_available(CODE.to_string()), 9900);
+        assert!(engine.pending.is_none());
+    }
+}
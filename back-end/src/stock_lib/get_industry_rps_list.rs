@@ -0,0 +1,289 @@
+use crate::db::schema::{industry_rps_values, stock_info_list};
+use crate::db::{
+    connection::Db,
+    stock_info::{IndustryRps, StockPriceInfo},
+};
+use crate::stock_lib::get_stock_rps_list::{
+    col_percent_rank, col_window_increase, get_local_stock_window,
+};
+use crate::AppErrorEnum;
+use chrono::Utc;
+use diesel::dsl::count_star;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::diesel::{AsyncConnection, AsyncMysqlConnection, RunQueryDsl};
+use rocket_db_pools::Connection;
+use std::collections::HashMap;
+
+/*
+* 行业 RPS
+* 把同一行业（stock_info_list.industry）的成分股看作一个整体，
+* 用成分股在指定时间段内涨幅的平均值作为行业涨幅，再按个股 RPS 的方式对所有行业排名。
+*
+* 行业涨幅的两种聚合方式
+*   1. 等权：industry_return = sum(return_i) / n
+*   2. 成交额加权：industry_return = sum(return_i * amount_i) / sum(amount_i)
+*      其中 amount_i 为股票 i 在时间段内的累计成交额（stock_daily_info.amount）
+*
+* 时间段内有缺失交易日的股票（新上市或停牌）不参与计算，避免用不完整的涨幅拉高或拉低行业均值
+*/
+
+// 定义一个通用的 Result 类型，默认错误类型为 AppErrorEnum
+type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
+
+/// 行业内成分股涨幅的聚合方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum IndustryWeight {
+    #[default]
+    Equal, // 等权
+    Amount, // 按区间累计成交额加权
+}
+
+#[derive(Debug)]
+struct StockWindowIncrease {
+    // 单只股票在时间段内的涨幅
+    ts_code: String,
+    increase: f64,
+    amount: f64, // 时间段内累计成交额
+}
+
+/// 计算每只股票在时间段内的涨幅和累计成交额
+/// 时间段的交易日数量取所有股票中最多的 K 线数量，K 线不足的股票被剔除
+fn col_stock_window_increase(
+    stock_map: &HashMap<String, Vec<StockPriceInfo>>,
+) -> Vec<StockWindowIncrease> {
+    let window_len = stock_map.values().map(Vec::len).max().unwrap_or(0);
+    stock_map
+        .iter()
+        .filter(|(_, stock_list)| stock_list.len() == window_len)
+        .filter_map(|(ts_code, stock_list)| {
+            let increase = col_window_increase(stock_list)?;
+            let amount = stock_list.iter().filter_map(|stock| stock.amount).sum();
+            Some(StockWindowIncrease {
+                ts_code: ts_code.to_string(),
+                increase,
+                amount,
+            })
+        })
+        .collect()
+}
+
+/// 按行业聚合成分股涨幅并排名
+/// stock_map：按股票代码分组的价格数据
+/// industry_map：股票代码 -> 所属行业
+/// weight：聚合方式
+/// trade_date：写入结果的交易日期
+pub fn rank_industry_rps(
+    stock_map: &HashMap<String, Vec<StockPriceInfo>>,
+    industry_map: &HashMap<String, String>,
+    weight: IndustryWeight,
+    trade_date: &str,
+) -> Vec<IndustryRps> {
+    // 行业 -> (加权涨幅之和, 权重之和, 成分股数量)
+    let mut industry_sum: HashMap<&str, (f64, f64, i32)> = HashMap::new();
+    for stock in col_stock_window_increase(stock_map) {
+        let industry = match industry_map.get(&stock.ts_code) {
+            Some(industry) => industry,
+            None => continue,
+        };
+        let stock_weight = match weight {
+            IndustryWeight::Equal => 1.0,
+            IndustryWeight::Amount => stock.amount,
+        };
+        let sum = industry_sum
+            .entry(industry.as_str())
+            .or_insert((0.0, 0.0, 0));
+        sum.0 += stock.increase * stock_weight;
+        sum.1 += stock_weight;
+        sum.2 += 1;
+    }
+    let mut industry_increase: Vec<(&str, f64, i32)> = industry_sum
+        .into_iter()
+        .filter(|(_, (_, weight_sum, _))| *weight_sum > 0.0)
+        .map(|(industry, (increase_sum, weight_sum, count))| {
+            (industry, increase_sum / weight_sum, count)
+        })
+        .collect();
+    // 对所有行业的涨幅进行排序，得到每个行业的排名
+    industry_increase.sort_by(|v_1, v_2| v_1.1.partial_cmp(&v_2.1).unwrap());
+    let percent_rank = col_percent_rank(industry_increase.len());
+    industry_increase
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (industry, increase, count))| IndustryRps {
+            industry: industry.to_string(),
+            trade_date: Some(trade_date.to_string()),
+            rps: Some(percent_rank[idx]),
+            increase: Some(increase),
+            stock_count: Some(count),
+        })
+        .collect()
+}
+
+/// 获取股票代码 -> 所属行业的映射，没有行业信息的股票不参与计算
+pub async fn get_industry_map(conn: &mut AsyncMysqlConnection) -> Result<HashMap<String, String>> {
+    let industry_list = stock_info_list::table
+        .select((stock_info_list::ts_code, stock_info_list::industry))
+        .load::<(String, Option<String>)>(conn)
+        .await?;
+    Ok(industry_list
+        .into_iter()
+        .filter_map(|(ts_code, industry)| industry.map(|industry| (ts_code, industry)))
+        .collect())
+}
+
+/// 计算行业 RPS 并写入 industry_rps_values
+/// end_date：截止日期，默认当天
+/// range：涨幅计算的交易日数量，默认 120
+/// weight：成分股涨幅的聚合方式，默认等权
+/// force：是否删除当天已有的数据并重新计算
+pub async fn col_industry_rps(
+    mut db: Connection<Db>,
+    end_date: Option<String>,
+    range: Option<usize>,
+    weight: Option<IndustryWeight>,
+    force: bool,
+) -> Result<()> {
+    let rps_range = range.unwrap_or(120);
+    let today_str = end_date.unwrap_or(Utc::now().date_naive().format("%Y%m%d").to_string());
+    // 检查数据库中是否有当天的数据，有则直接返回，不执行后续操作
+    let has_data = industry_rps_values::table
+        .filter(industry_rps_values::trade_date.eq(today_str.clone()))
+        .select(count_star())
+        .first::<i64>(&mut db)
+        .await?
+        != 0;
+    if has_data && !force {
+        return Ok(());
+    }
+    let industry_map = get_industry_map(&mut db).await?;
+    let stock_map = get_local_stock_window(&mut db, today_str.clone(), rps_range).await?;
+    let industry_rps = rank_industry_rps(
+        &stock_map,
+        &industry_map,
+        weight.unwrap_or_default(),
+        &today_str,
+    );
+    if industry_rps.is_empty() {
+        return Ok(());
+    }
+    db.transaction(|mut conn| {
+        Box::pin(async move {
+            // 只删除当天的数据，不影响其他日期
+            diesel::delete(industry_rps_values::table)
+                .filter(industry_rps_values::trade_date.eq(today_str))
+                .execute(&mut conn)
+                .await?;
+            diesel::insert_into(industry_rps_values::table)
+                .values(&industry_rps)
+                .execute(&mut conn)
+                .await?;
+            Ok::<_, diesel::result::Error>(())
+        })
+    })
+    .await?;
+    Ok(())
+}
+
+/// 股票在所属行业内的强度
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IndustryStockRps {
+    ts_code: String,
+    name: Option<String>,
+    industry: String,
+    increase: f64,                  // 个股指定时间涨幅
+    rps: f64,                       // 个股在行业内的 RPS
+    rank: usize,                    // 个股在行业内的名次，从 1 开始
+    stock_count: usize,             // 行业内参与排名的股票数量
+    industry_rps: Option<f64>,      // 行业 RPS，需要先计算当天的行业 RPS
+    industry_increase: Option<f64>, // 行业指定时间涨幅
+}
+
+/// 计算单只股票在所属行业内的 RPS
+/// ts_code：股票代码
+/// end_date：截止日期，默认当天
+/// range：涨幅计算的交易日数量，默认 120
+pub async fn col_industry_stock_rps(
+    mut db: Connection<Db>,
+    ts_code: String,
+    end_date: Option<String>,
+    range: Option<usize>,
+) -> Result<Option<IndustryStockRps>> {
+    let rps_range = range.unwrap_or(120);
+    let today_str = end_date.unwrap_or(Utc::now().date_naive().format("%Y%m%d").to_string());
+    let stock_info = stock_info_list::table
+        .filter(stock_info_list::ts_code.eq(ts_code.clone()))
+        .select((stock_info_list::name, stock_info_list::industry))
+        .first::<(Option<String>, Option<String>)>(&mut db)
+        .await
+        .optional()?;
+    let (name, industry) = match stock_info {
+        Some((name, Some(industry))) => (name, industry),
+        _ => return Ok(None),
+    };
+    let industry_map = get_industry_map(&mut db).await?;
+    let stock_map = get_local_stock_window(&mut db, today_str.clone(), rps_range).await?;
+    // 先按全市场的交易日剔除 K 线不足的股票，再筛选同行业的成分股
+    let mut member_increase: Vec<StockWindowIncrease> = col_stock_window_increase(&stock_map)
+        .into_iter()
+        .filter(|stock| industry_map.get(&stock.ts_code) == Some(&industry))
+        .collect();
+    member_increase.sort_by(|v_1, v_2| v_1.increase.partial_cmp(&v_2.increase).unwrap());
+    let position = match member_increase
+        .iter()
+        .position(|stock| stock.ts_code == ts_code)
+    {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let percent_rank = col_percent_rank(member_increase.len());
+    let industry_rps = industry_rps_values::table
+        .filter(industry_rps_values::industry.eq(industry.clone()))
+        .filter(industry_rps_values::trade_date.eq(today_str))
+        .select((industry_rps_values::rps, industry_rps_values::increase))
+        .first::<(Option<f64>, Option<f64>)>(&mut db)
+        .await
+        .optional()?;
+    let (industry_rps, industry_increase) = industry_rps.unwrap_or((None, None));
+    Ok(Some(IndustryStockRps {
+        ts_code,
+        name,
+        industry,
+        increase: member_increase[position].increase,
+        rps: percent_rank[position],
+        rank: member_increase.len() - position,
+        stock_count: member_increase.len(),
+        industry_rps,
+        industry_increase,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::mock_series;
+
+    #[test]
+    fn partial_window_is_excluded() {
+        let stock_map: HashMap<String, Vec<StockPriceInfo>> = [
+            ("600000.SH", mock_series("600000.SH", &[10.0, 10.5, 11.0])),
+            ("600036.SH", mock_series("600036.SH", &[10.0, 10.0, 10.0])),
+            // 新上市的股票只有两根 K 线，不参与行业涨幅的计算
+            ("601318.SH", mock_series("601318.SH", &[10.0, 20.0])),
+        ]
+        .into_iter()
+        .map(|(code, bars)| (code.to_string(), bars))
+        .collect();
+        let industry_map: HashMap<String, String> = stock_map
+            .keys()
+            .map(|code| (code.clone(), "银行".to_string()))
+            .collect();
+        let industry_rps =
+            rank_industry_rps(&stock_map, &industry_map, IndustryWeight::Equal, "20240103");
+        assert_eq!(industry_rps.len(), 1);
+        assert_eq!(industry_rps[0].stock_count, Some(2));
+        assert_eq!(industry_rps[0].increase, Some(5.0));
+    }
+}
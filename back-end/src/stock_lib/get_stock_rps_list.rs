@@ -1,4 +1,4 @@
-use crate::db::schema::{rps_values, stock_daily_info, stock_info_list};
+use crate::db::schema::{industry_rps_values, rps_values, stock_daily_info, stock_info_list};
 use crate::db::{
     connection::Db,
     stock_info::{StockPriceInfo, StockRps},
@@ -12,10 +12,10 @@ use rocket::tokio;
 use rocket::tokio::time::{sleep, Duration as to_do};
 use rocket_db_pools::diesel::{AsyncConnection, RunQueryDsl};
 use rocket_db_pools::Connection;
-use std::ops::DerefMut;
+use std::collections::HashMap;
 // 导入 Rocket 的 Debug 类型，用于调试错误响应。
+use rayon::prelude::*;
 use rocket::serde::{Deserialize, Serialize};
-use rocket::State;
 use std::sync::Arc;
 
 /**
@@ -47,18 +47,18 @@ type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
 
 #[derive(Deserialize, Debug)]
 #[serde(crate = "rocket::serde")]
-struct DailyRes {
+pub(crate) struct DailyRes {
     request_id: String,
     code: i32,
     msg: String,
-    data: ResData,
+    pub(crate) data: ResData,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(crate = "rocket::serde")]
-struct ResData {
+pub(crate) struct ResData {
     fields: Vec<String>,
-    items: Vec<(
+    pub(crate) items: Vec<(
         String,         // ts_code
         Option<String>, // trade_date
         Option<f64>,    // open
@@ -123,25 +123,81 @@ pub async fn get_stock_price_data(
 }
 
 #[derive(Debug)]
-struct StockIncrease {
+pub struct StockIncrease {
     // 股票涨幅
     ts_code: String,
     increase: f64,
     trade_date: Option<String>,
 }
+/// 计算升序排列后每个位置的百分比排名
+/// percent_rank_i = (rank_i - 1) / (total - 1) * 100
+/// total：参与排名的数量，只有一个时排名为 100
+pub fn col_percent_rank(total: usize) -> Array1<f64> {
+    if total <= 1 {
+        return Array1::from_elem(total, 100.0);
+    }
+    let rank_list: Vec<f64> = (1..=total).map(|rank| rank as f64).collect();
+    let vector_list = Array1::from(rank_list);
+    (&vector_list - 1.0) / (total - 1) as f64 * 100.0
+}
+
+/// 并行计算每只股票在时间段内的涨幅
+/// stock_map：按股票代码分组、日期升序排列的价格数据，每只股票的第一条数据为涨幅的基准
+/// trade_date：写入结果的交易日期
+pub fn col_stock_increase_list(
+    stock_map: &HashMap<String, Vec<StockPriceInfo>>,
+    trade_date: &str,
+) -> Vec<StockIncrease> {
+    stock_map
+        .par_iter()
+        .filter_map(|(ts_code, stock_list)| {
+            Some(StockIncrease {
+                ts_code: ts_code.to_string(),
+                increase: col_window_increase(stock_list)?,
+                trade_date: Some(trade_date.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// 根据涨幅计算全部股票的 RPS，返回排名前 top 的股票
+/// stock_map：按股票代码分组、日期升序排列的价格数据
+/// trade_date：写入结果的交易日期
+/// top：保留的股票数量
+pub fn rank_stock_rps(
+    stock_map: &HashMap<String, Vec<StockPriceInfo>>,
+    trade_date: &str,
+    top: usize,
+) -> Vec<StockRps> {
+    let mut all_increase = col_stock_increase_list(stock_map, trade_date);
+    // 对所有股票的涨幅进行排序，得到每只股票的排名
+    all_increase.par_sort_by(|v_1, v_2| v_1.increase.partial_cmp(&v_2.increase).unwrap());
+    let percent_rank = col_percent_rank(all_increase.len());
+    let top_start = all_increase.len().saturating_sub(top);
+    // 取排名前 top 的票，以及得到整个盘面的 rps
+    all_increase
+        .iter()
+        .enumerate()
+        .skip(top_start)
+        .map(|(idx, stock)| StockRps {
+            ts_code: stock.ts_code.to_string(),
+            trade_date: stock.trade_date.clone(),
+            increase: Some(stock.increase),
+            rps: Some(percent_rank[idx]),
+        })
+        .collect()
+}
+
 // 计算股票的 RPS
+// 一次范围查询取出所有股票在时间段内的价格数据，在内存中分组后用 rayon 并行计算涨幅
+// force 为 true 时删除当天已有的数据并重新计算，否则当天已有数据时直接返回
 pub async fn col_stock_rps(
     mut db: Connection<Db>,
-    db_state: &State<Db>,
     end_date: Option<String>,
     range: Option<usize>,
+    force: bool,
 ) -> Result<()> {
     let rps_range = range.unwrap_or(120);
-    let code_list = stock_info_list::table
-        .select(stock_info_list::ts_code)
-        .load::<String>(&mut db)
-        .await?;
-    let list_len = code_list.len();
     // 获取当前日期
     let today = Utc::now().date_naive();
     // 定义格式化字符串
@@ -154,116 +210,21 @@ pub async fn col_stock_rps(
         .first::<i64>(&mut db)
         .await?
         != 0;
-    if has_data {
+    if has_data && !force {
         return Ok(());
     }
-    /*
-    Arc<Mutex<T>> 会导致线程在访问共享数据时需要获取锁，这可能导致线程的并发性能下降。如果锁的争用很严重，线程可能会被迫等待，这样看起来像是同步执行。
-    为了减少锁的争用，可以尝试以下几种方法：
-    •	减少锁的粒度：在 thread::spawn 内部尽量减少对锁的持有时间，只在需要的时候锁定数据。
-    •	使用无锁数据结构：考虑使用无锁数据结构（如 crossbeam 提供的无锁队列）来代替 Mutex。
-
-    因此这里去掉了锁，其实锁本来也没用😂
-     */
-    let share_code_list = Arc::new(code_list);
-    // 使用 Arc 共享日期字符串
-    let today_str = Arc::new(today_str);
-    // 线程任务队列
-    let mut tasks = vec![];
-    // 线程数最好不超过本机的 CPU 核心数
-    // 我的电脑为 11 核心，所以这里设置为 10
-    // 服务器为2核心，在处理任务时出现了连接超时的问题，一共出现了3次，所以这里设置为 5
-    for offset in 0..5 {
-        // 启动 10 个线程
-        let today_str = Arc::clone(&today_str);
-        let code_list = Arc::clone(&share_code_list);
-        // db_state 是一个 Rocket 的 State 类型，用于存储全局状态
-        // 这里存储的是数据库连接
-        // 通过 db_state.get 获取数据库连接
-        let mut db_conn = db_state.get().await.expect("db connect err");
-
-        let task = tokio::spawn(async move {
-            let mut result = vec![];
-            for (idx, code) in code_list.iter().enumerate() {
-                if (idx % 5) == offset {
-                    let db_conn = db_conn.deref_mut();
-                    match get_local_stock_price_data(db_conn, code.to_string()).await {
-                        Ok(stock_basic_info_list) => {
-                            let mut last_date_index =
-                                stock_basic_info_list.iter().position(|stock| {
-                                    stock.trade_date.as_ref().unwrap() == today_str.as_str()
-                                });
-                            let mut cur_today_str = today_str.to_string();
-                            // 股票周六日不交易，所以当天的数据不存在Ç
-                            // 如果当天的数据不存在，向前查找
-                            while last_date_index.is_none() {
-                                // 定义格式化字符串
-                                let format = "%Y%m%d";
-                                let cur_date =
-                                    NaiveDate::parse_from_str(&cur_today_str, format).unwrap();
-                                let prev_date =
-                                    cur_date.pred_opt().unwrap().format(format).to_string();
-                                last_date_index = stock_basic_info_list.iter().position(|stock| {
-                                    stock.trade_date.as_ref().unwrap() == prev_date.as_str()
-                                });
-                                cur_today_str = prev_date;
-                            }
-                            if last_date_index.is_none() {
-                                eprintln!("没有找到当天的数据");
-                                continue;
-                            }
-                            let before_stock = if last_date_index.unwrap() > rps_range {
-                                stock_basic_info_list[last_date_index.unwrap() - rps_range].clone()
-                            } else {
-                                stock_basic_info_list[0].clone()
-                            };
-                            let now_stock = stock_basic_info_list[last_date_index.unwrap()].clone();
-                            result.push(StockIncrease {
-                                ts_code: code.to_string(),
-                                increase: (now_stock.close.unwrap() - before_stock.close.unwrap())
-                                    / before_stock.close.unwrap()
-                                    * 100.0,
-                                trade_date: Some(today_str.to_string()),
-                            });
-                        }
-                        Err(e) => eprintln!("Error fetching stock data: {:?}", e),
-                    }
-                }
-            }
-            result
-        });
-
-        tasks.push(task);
-    }
-    let mut all_increase: Vec<StockIncrease> = Vec::with_capacity(list_len);
-    for task in tasks {
-        let mut res: Vec<StockIncrease> = task.await?;
-        all_increase.append(&mut res);
-    }
-    // 对所有股票的涨幅进行排序，得到每只股票的排名
-    all_increase.sort_by(|v_1, v_2| v_1.increase.partial_cmp(&v_2.increase).unwrap());
-    let stock_rank_list: Vec<f64> = all_increase
-        .iter()
-        .enumerate()
-        .map(|(idx, _)| (idx + 1) as f64)
-        .collect();
-    let vector_list = Array1::from(stock_rank_list);
-    // percent_rank_i = (rank_i - 1) / (total_stocks - 1) * 100
-    let percent_rank = (&vector_list - 1.0) / (all_increase.len() - 1) as f64 * 100.0;
-    let mut stock_rps: Vec<StockRps> = Vec::with_capacity(all_increase.len());
-    for (idx, stock) in all_increase.iter().enumerate() {
-        if idx >= all_increase.len() - 300 {
-            // 取排名前 300 的票，以及得到整个盘面的 rps
-            stock_rps.push(StockRps {
-                ts_code: stock.ts_code.to_string(),
-                trade_date: stock.trade_date.clone(),
-                increase: Some(stock.increase),
-                rps: Some(percent_rank[idx]),
-            })
-        }
+    let stock_map = get_local_stock_window(&mut db, today_str.clone(), rps_range).await?;
+    let stock_rps = rank_stock_rps(&stock_map, &today_str, 300);
+    if stock_rps.is_empty() {
+        return Ok(());
     }
     db.transaction(|mut conn| {
         Box::pin(async move {
+            // 只删除当天的数据，不影响其他日期
+            diesel::delete(rps_values::table)
+                .filter(rps_values::trade_date.eq(today_str))
+                .execute(&mut conn)
+                .await?;
             diesel::insert_into(rps_values::table)
                 .values(&stock_rps)
                 .execute(&mut conn)
@@ -274,7 +235,34 @@ pub async fn col_stock_rps(
     .await?;
     Ok(())
 }
-use rayon::prelude::*;
+/// 删除一段日期内的个股 RPS 和行业 RPS，返回删除的行数 (个股, 行业)
+/// start_date：开始日期（含）
+/// end_date：结束日期（含）
+pub async fn delete_stock_rps(
+    mut db: Connection<Db>,
+    start_date: String,
+    end_date: String,
+) -> Result<(usize, usize)> {
+    let affected = db
+        .transaction(|mut conn| {
+            Box::pin(async move {
+                let stock_affected = diesel::delete(rps_values::table)
+                    .filter(rps_values::trade_date.ge(start_date.clone()))
+                    .filter(rps_values::trade_date.le(end_date.clone()))
+                    .execute(&mut conn)
+                    .await?;
+                let industry_affected = diesel::delete(industry_rps_values::table)
+                    .filter(industry_rps_values::trade_date.ge(start_date))
+                    .filter(industry_rps_values::trade_date.le(end_date))
+                    .execute(&mut conn)
+                    .await?;
+                Ok::<_, diesel::result::Error>((stock_affected, industry_affected))
+            })
+        })
+        .await?;
+    Ok(affected)
+}
+
 // 获取股票的价格数据
 pub async fn fetch_stock_daily_range(
     mut db: Connection<Db>,
@@ -421,6 +409,75 @@ pub async fn get_local_stock_price_data(
 ) -> Result<Vec<StockPriceInfo>> {
     Ok(stock_daily_info::table
         .filter(stock_daily_info::ts_code.eq(ts_code))
+        .order(stock_daily_info::trade_date.asc())
         .load(conn)
         .await?)
 }
+
+/// 获取截止日期前 range 个交易日内全部股票的价格数据，按股票代码分组并按日期升序排列
+/// 一次范围查询代替逐只股票查询
+/// end_date：截止日期
+/// range：交易日数量，会多取一天作为涨幅的基准日
+pub async fn get_local_stock_window(
+    conn: &mut AsyncMysqlConnection,
+    end_date: String,
+    range: usize,
+) -> Result<HashMap<String, Vec<StockPriceInfo>>> {
+    // 截止日期前（含）最近的 range + 1 个交易日
+    let trade_date_list = stock_daily_info::table
+        .select(stock_daily_info::trade_date)
+        .filter(stock_daily_info::trade_date.le(end_date.clone()))
+        .distinct()
+        .order(stock_daily_info::trade_date.desc())
+        .limit(range as i64 + 1)
+        .load::<Option<String>>(conn)
+        .await?;
+    let start_date = match trade_date_list.into_iter().flatten().last() {
+        Some(date) => date,
+        None => return Ok(HashMap::new()),
+    };
+    let stock_list = stock_daily_info::table
+        .filter(stock_daily_info::trade_date.ge(start_date))
+        .filter(stock_daily_info::trade_date.le(end_date))
+        .order((
+            stock_daily_info::ts_code.asc(),
+            stock_daily_info::trade_date.asc(),
+        ))
+        .load::<StockPriceInfo>(conn)
+        .await?;
+    let mut stock_map: HashMap<String, Vec<StockPriceInfo>> = HashMap::new();
+    for stock in stock_list {
+        stock_map
+            .entry(stock.ts_code.clone())
+            .or_default()
+            .push(stock);
+    }
+    Ok(stock_map)
+}
+
+/// 计算一段按日期升序排列的价格数据首尾之间的涨幅（百分比）
+/// 收盘价缺失或基准价为 0 时返回 None
+pub fn col_window_increase(stock_list: &[StockPriceInfo]) -> Option<f64> {
+    let start_close = stock_list.first()?.close?;
+    let end_close = stock_list.last()?.close?;
+    if start_close == 0.0 {
+        return None;
+    }
+    Some((end_close - start_close) / start_close * 100.0)
+}
+
+// 获取本地股票的 RPS 数据，交易日期 -> rps
+pub async fn get_local_stock_rps_data(
+    conn: &mut AsyncMysqlConnection,
+    ts_code: String,
+) -> Result<HashMap<String, f64>> {
+    let rps_list = rps_values::table
+        .filter(rps_values::ts_code.eq(ts_code))
+        .select((rps_values::trade_date, rps_values::rps))
+        .load::<(Option<String>, Option<f64>)>(conn)
+        .await?;
+    Ok(rps_list
+        .into_iter()
+        .filter_map(|(trade_date, rps)| Some((trade_date?, rps?)))
+        .collect())
+}
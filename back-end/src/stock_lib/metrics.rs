@@ -0,0 +1,318 @@
+use crate::stock_lib::stock_trade::{OperateRecord, OrderType};
+use chrono::NaiveDate;
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/*
+* 回测绩效指标
+*   以每日总资产作为净值曲线，日收益率 r_t = assets_t / assets_(t-1) - 1，一年按 252 个交易日计算，无风险利率取 0
+*   1. 年化收益率：(1 + 总收益率) ^ (252 / 交易日数) - 1
+*   2. 年化波动率：std(r) * sqrt(252)
+*   3. 夏普比率：mean(r) / std(r) * sqrt(252)
+*   4. 索提诺比率：mean(r) / 下行标准差 * sqrt(252)，下行标准差只统计 r < 0 的部分
+*   5. 最大回撤：max(1 - assets_t / max(assets_0..t))，回撤持续时间为从前高到收复前高（或回测结束）的交易日数
+*   6. 卡玛比率：年化收益率 / 最大回撤
+*   7. 胜率、盈亏比：按持仓从 0 到清仓为一笔交易，统计交易的盈亏（已扣除费用）
+*   8. 换手率：年化成交金额 / 平均总资产
+*   9. 持仓暴露：每日持仓市值 / 总资产的平均值
+*/
+
+const TRADING_DAYS: f64 = 252.0;
+
+/// 回测绩效指标
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BacktestMetrics {
+    pub total_return: f64,        // 总收益率
+    pub annual_return: f64,       // 年化收益率
+    pub volatility: f64,          // 年化波动率
+    pub sharpe: f64,              // 夏普比率
+    pub sortino: f64,             // 索提诺比率
+    pub max_drawdown: f64,        // 最大回撤
+    pub max_drawdown_days: usize, // 最长回撤持续交易日数
+    pub calmar: f64,              // 卡玛比率
+    pub trade_count: usize,       // 已平仓的交易笔数
+    pub win_rate: f64,            // 胜率
+    pub profit_factor: f64,       // 盈亏比：盈利交易总额 / 亏损交易总额
+    pub avg_hold_days: f64,       // 平均持仓自然日数
+    pub turnover: f64,            // 年化换手率
+    pub exposure: f64,            // 平均持仓暴露
+}
+
+/// 参数优化的目标，值越大越好
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum Objective {
+    #[default]
+    TotalReturn, // 总收益率，与按最终总资产选优等价
+    AnnualReturn, // 年化收益率
+    Sharpe,       // 夏普比率
+    Sortino,      // 索提诺比率
+    Calmar,       // 卡玛比率
+    MaxDrawdown,  // 最大回撤越小越好
+}
+
+impl Objective {
+    /// 计算指标在该目标下的得分
+    pub fn score(&self, metrics: &BacktestMetrics) -> f64 {
+        match self {
+            Objective::TotalReturn => metrics.total_return,
+            Objective::AnnualReturn => metrics.annual_return,
+            Objective::Sharpe => metrics.sharpe,
+            Objective::Sortino => metrics.sortino,
+            Objective::Calmar => metrics.calmar,
+            Objective::MaxDrawdown => -metrics.max_drawdown,
+        }
+    }
+}
+
+/// 一笔从开仓到清仓的交易
+#[derive(Debug, Default)]
+struct RoundTrip {
+    cost: f64,     // 买入金额加费用
+    shares: usize, // 当前持股数
+    pnl: f64,      // 已实现盈亏
+    open_date: Option<String>,
+}
+
+/// 计算两个 YYYYMMDD 日期之间的自然日数
+fn col_days_between(start: &str, end: &str) -> Option<i64> {
+    let format = "%Y%m%d";
+    let start = NaiveDate::parse_from_str(start, format).ok()?;
+    let end = NaiveDate::parse_from_str(end, format).ok()?;
+    Some((end - start).num_days())
+}
+
+/// 按持仓从 0 到清仓划分交易，返回每笔交易的 (盈亏, 持仓自然日数)
+fn col_round_trips(operate_record: &[OperateRecord]) -> Vec<(f64, Option<i64>)> {
+    let mut open_map: HashMap<&str, RoundTrip> = HashMap::new();
+    let mut trips = vec![];
+    for record in operate_record {
+        let value = record.price * record.operate_num as f64;
+        let trip = open_map.entry(record.code.as_str()).or_default();
+        match record.order_type {
+            OrderType::Buy => {
+                if trip.shares == 0 {
+                    trip.open_date = record.operate_date.clone();
+                }
+                trip.cost += value + record.fee;
+                trip.shares += record.operate_num;
+            }
+            OrderType::Sell => {
+                if trip.shares == 0 {
+                    continue;
+                }
+                let sell_num = record.operate_num.min(trip.shares);
+                // 按平均成本结转卖出部分的成本
+                let sell_cost = trip.cost * sell_num as f64 / trip.shares as f64;
+                trip.pnl += value - record.fee - sell_cost;
+                trip.cost -= sell_cost;
+                trip.shares -= sell_num;
+                if trip.shares == 0 {
+                    let hold_days = match (&trip.open_date, &record.operate_date) {
+                        (Some(start), Some(end)) => col_days_between(start, end),
+                        _ => None,
+                    };
+                    trips.push((trip.pnl, hold_days));
+                    *trip = RoundTrip::default();
+                }
+            }
+        }
+    }
+    trips
+}
+
+/// 计算回测绩效指标
+/// total_assets: 每日总资产
+/// position_value: 每日持仓市值，与 total_assets 一一对应
+/// operate_record: 交易记录
+/// init_cash: 初始资金
+pub fn col_metrics(
+    total_assets: &[f64],
+    position_value: &[f64],
+    operate_record: &[OperateRecord],
+    init_cash: f64,
+) -> BacktestMetrics {
+    let mut metrics = BacktestMetrics::default();
+    if total_assets.is_empty() || init_cash <= 0.0 {
+        return metrics;
+    }
+    let days = total_assets.len() as f64;
+    let final_assets = total_assets[total_assets.len() - 1];
+    metrics.total_return = final_assets / init_cash - 1.0;
+    metrics.annual_return = if metrics.total_return > -1.0 {
+        (1.0 + metrics.total_return).powf(TRADING_DAYS / days) - 1.0
+    } else {
+        -1.0
+    };
+    // 日收益率，第一天相对初始资金计算
+    let daily_return: Vec<f64> = std::iter::once(init_cash)
+        .chain(total_assets.iter().copied())
+        .collect::<Vec<f64>>()
+        .windows(2)
+        .map(|pair| {
+            if pair[0] > 0.0 {
+                pair[1] / pair[0] - 1.0
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let mean = daily_return.iter().sum::<f64>() / days;
+    let std = (daily_return.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / days).sqrt();
+    let downside = (daily_return.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / days).sqrt();
+    metrics.volatility = std * TRADING_DAYS.sqrt();
+    if std > 0.0 {
+        metrics.sharpe = mean / std * TRADING_DAYS.sqrt();
+    }
+    if downside > 0.0 {
+        metrics.sortino = mean / downside * TRADING_DAYS.sqrt();
+    }
+    // 最大回撤及持续时间
+    let mut peak = init_cash;
+    let mut peak_index = 0;
+    for (index, assets) in total_assets.iter().enumerate() {
+        if *assets >= peak {
+            peak = *assets;
+            peak_index = index;
+        } else {
+            metrics.max_drawdown = metrics.max_drawdown.max(1.0 - assets / peak);
+            metrics.max_drawdown_days = metrics.max_drawdown_days.max(index - peak_index);
+        }
+    }
+    if metrics.max_drawdown > 0.0 {
+        metrics.calmar = metrics.annual_return / metrics.max_drawdown;
+    }
+    // 交易统计
+    let trips = col_round_trips(operate_record);
+    metrics.trade_count = trips.len();
+    if !trips.is_empty() {
+        let win_count = trips.iter().filter(|(pnl, _)| *pnl > 0.0).count();
+        metrics.win_rate = win_count as f64 / trips.len() as f64;
+        let gross_profit: f64 = trips.iter().map(|(pnl, _)| pnl.max(0.0)).sum();
+        let gross_loss: f64 = trips.iter().map(|(pnl, _)| (-pnl).max(0.0)).sum();
+        metrics.profit_factor = if gross_loss > 0.0 {
+            gross_profit / gross_loss
+        } else {
+            gross_profit
+        };
+        let hold_days: Vec<i64> = trips.iter().filter_map(|(_, days)| *days).collect();
+        if !hold_days.is_empty() {
+            metrics.avg_hold_days = hold_days.iter().sum::<i64>() as f64 / hold_days.len() as f64;
+        }
+    }
+    let avg_assets = total_assets.iter().sum::<f64>() / days;
+    let trade_value: f64 = operate_record
+        .iter()
+        .map(|record| record.price * record.operate_num as f64)
+        .sum();
+    if avg_assets > 0.0 {
+        metrics.turnover = trade_value / avg_assets * TRADING_DAYS / days;
+    }
+    metrics.exposure = total_assets
+        .iter()
+        .zip(position_value.iter())
+        .map(|(assets, value)| if *assets > 0.0 { value / assets } else { 0.0 })
+        .sum::<f64>()
+        / days;
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::assert_close;
+
+    fn record(order_type: OrderType, price: f64, date: &str) -> OperateRecord {
+        OperateRecord {
+            code: "600000.SH".to_string(),
+            order_type,
+            hold: 0,
+            assets: 0.0,
+            operate_num: 100,
+            close: price,
+            operate_date: Some(date.to_string()),
+            price,
+            fee: 5.0,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn empty_curve_returns_default() {
+        let metrics = col_metrics(&[], &[], &[], 100.0);
+        assert_close(metrics.total_return, 0.0);
+        assert_eq!(metrics.trade_count, 0);
+    }
+
+    #[test]
+    fn flat_curve_has_zero_ratios() {
+        let metrics = col_metrics(&[100.0; 5], &[0.0; 5], &[], 100.0);
+        assert_close(metrics.total_return, 0.0);
+        assert_close(metrics.annual_return, 0.0);
+        assert_close(metrics.volatility, 0.0);
+        assert_close(metrics.sharpe, 0.0);
+        assert_close(metrics.sortino, 0.0);
+        assert_close(metrics.max_drawdown, 0.0);
+        assert_close(metrics.calmar, 0.0);
+        assert_close(metrics.exposure, 0.0);
+        // 没有交易
+        assert_eq!(metrics.trade_count, 0);
+        assert_close(metrics.win_rate, 0.0);
+        assert_close(metrics.profit_factor, 0.0);
+        assert_close(metrics.turnover, 0.0);
+    }
+
+    #[test]
+    fn single_bar() {
+        let metrics = col_metrics(&[110.0], &[110.0], &[], 100.0);
+        assert_close(metrics.total_return, 0.1);
+        assert_close(metrics.annual_return, 1.1f64.powf(TRADING_DAYS) - 1.0);
+        // 只有一个日收益率，标准差为 0
+        assert_close(metrics.sharpe, 0.0);
+        assert_close(metrics.max_drawdown, 0.0);
+        assert_close(metrics.exposure, 1.0);
+    }
+
+    #[test]
+    fn sharpe_from_daily_return() {
+        // 日收益率为 10% 和 0%，均值 5%，标准差 5%
+        let metrics = col_metrics(&[110.0, 110.0], &[0.0, 0.0], &[], 100.0);
+        assert_close(metrics.volatility, 0.05 * TRADING_DAYS.sqrt());
+        assert_close(metrics.sharpe, TRADING_DAYS.sqrt());
+        // 没有下跌的交易日
+        assert_close(metrics.sortino, 0.0);
+    }
+
+    #[test]
+    fn max_drawdown_and_duration() {
+        let total_assets = [100.0, 120.0, 90.0, 100.0, 130.0, 117.0];
+        let metrics = col_metrics(&total_assets, &[0.0; 6], &[], 100.0);
+        assert_close(metrics.max_drawdown, 0.25);
+        assert_eq!(metrics.max_drawdown_days, 2);
+        assert_close(metrics.calmar, metrics.annual_return / 0.25);
+    }
+
+    #[test]
+    fn round_trip_stats_and_turnover() {
+        let operate_record = [
+            // 盈利 1200 - 5 - 1005 = 190
+            record(OrderType::Buy, 10.0, "20240101"),
+            record(OrderType::Sell, 12.0, "20240111"),
+            // 亏损 900 - 5 - 1005 = -110
+            record(OrderType::Buy, 10.0, "20240111"),
+            record(OrderType::Sell, 9.0, "20240131"),
+            // 未平仓的交易不计入笔数
+            record(OrderType::Buy, 10.0, "20240201"),
+        ];
+        let total_assets = vec![1000.0; 252];
+        let metrics = col_metrics(&total_assets, &total_assets, &operate_record, 1000.0);
+        assert_eq!(metrics.trade_count, 2);
+        assert_close(metrics.win_rate, 0.5);
+        assert_close(metrics.profit_factor, 190.0 / 110.0);
+        assert_close(metrics.avg_hold_days, 15.0);
+        // 一年成交 5100 元，平均总资产 1000 元
+        assert_close(metrics.turnover, 5.1);
+        assert_close(metrics.exposure, 1.0);
+    }
+}
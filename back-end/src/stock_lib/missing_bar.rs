@@ -0,0 +1,95 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::AppErrorEnum;
+use rocket::serde::{Deserialize, Serialize};
+
+/*
+* 缺失 K 线处理
+*   停牌或数据源缺失时，K 线的收盘价等字段可能为空，回测前按请求指定的方式统一处理，信号计算不再遇到空的收盘价
+*   1. 跳过：丢弃没有收盘价的 K 线
+*   2. 前值填充：没有收盘价的 K 线用前一根 K 线的收盘价作为开高低收，成交量和成交额为 0，
+*      第一根有收盘价的 K 线之前的 K 线无法填充，直接丢弃
+*   两种方式下，有收盘价但缺少开盘价、最高价或最低价的 K 线都用收盘价补齐
+*   处理后的 K 线少于 MIN_HISTORY_BARS 根时返回 AppErrorEnum::InsufficientHistory
+*/
+
+/// 回测至少需要的 K 线根数：信号按前一天的数据计算，至少要有两根 K 线才可能产生交易
+pub const MIN_HISTORY_BARS: usize = 2;
+
+/// 缺失 K 线的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum MissingBarPolicy {
+    #[default]
+    Skip, // 丢弃没有收盘价的 K 线
+    ForwardFill, // 用前一根 K 线的收盘价填充
+}
+
+/// 用收盘价补齐缺失的开盘价、最高价和最低价
+fn fill_ohlc(mut bar: StockPriceInfo, close: f64) -> StockPriceInfo {
+    let open = bar.open.unwrap_or(close);
+    bar.open = Some(open);
+    bar.high = Some(bar.high.unwrap_or(close.max(open)));
+    bar.low = Some(bar.low.unwrap_or(close.min(open)));
+    bar
+}
+
+/// 按处理方式清洗 K 线，返回的每根 K 线都有开高低收
+/// df_stock: 按日期升序排列的 K 线
+/// policy: 缺失 K 线的处理方式
+pub fn fill_missing_bars(
+    df_stock: Vec<StockPriceInfo>,
+    policy: MissingBarPolicy,
+) -> Vec<StockPriceInfo> {
+    let mut last_close: Option<f64> = None;
+    df_stock
+        .into_iter()
+        .filter_map(|mut bar| match (bar.close, policy, last_close) {
+            (Some(close), _, _) => {
+                last_close = Some(close);
+                Some(fill_ohlc(bar, close))
+            }
+            (None, MissingBarPolicy::ForwardFill, Some(close)) => {
+                bar.open = Some(close);
+                bar.high = Some(close);
+                bar.low = Some(close);
+                bar.close = Some(close);
+                bar.pre_close = Some(close);
+                bar.vol = Some(0.0);
+                bar.amount = Some(0.0);
+                bar.change = Some(0.0);
+                bar.pct_chg = Some(0.0);
+                Some(bar)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// 清洗 K 线并检查数量是否足够回测
+/// code: 股票代码
+/// df_stock: 按日期升序排列的 K 线
+/// policy: 缺失 K 线的处理方式
+pub fn prepare_bars(
+    code: &str,
+    df_stock: Vec<StockPriceInfo>,
+    policy: MissingBarPolicy,
+) -> Result<Vec<StockPriceInfo>, AppErrorEnum> {
+    let df_stock = fill_missing_bars(df_stock, policy);
+    check_history(code, df_stock.len(), MIN_HISTORY_BARS)?;
+    Ok(df_stock)
+}
+
+/// 检查 K 线数量，不足 required 根时返回 AppErrorEnum::InsufficientHistory
+/// code: 股票代码
+/// bars: 可用的 K 线根数
+/// required: 至少需要的 K 线根数
+pub fn check_history(code: &str, bars: usize, required: usize) -> Result<(), AppErrorEnum> {
+    if bars < required {
+        return Err(AppErrorEnum::InsufficientHistory {
+            code: code.to_string(),
+            bars,
+            required,
+        });
+    }
+    Ok(())
+}
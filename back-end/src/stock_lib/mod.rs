@@ -1,3 +1,21 @@
+pub mod backtest_store;
+pub mod benchmark;
+pub mod cost_model;
+pub mod engine;
 pub mod get_all_stock_list;
+pub mod get_industry_rps_list;
 pub mod get_stock_rps_list;
-pub mod stock_trade;
\ No newline at end of file
+pub mod metrics;
+pub mod missing_bar;
+pub mod optimizer;
+pub mod portfolio;
+pub mod rebalance;
+pub mod risk;
+pub mod rps_screen;
+pub mod sensitivity;
+pub mod sizer;
+pub mod stock_trade;
+pub mod strategy;
+#[cfg(test)]
+mod test_util;
+pub mod walk_forward;
\ No newline at end of file
@@ -0,0 +1,705 @@
+use crate::stock_lib::metrics::{BacktestMetrics, Objective};
+use crate::stock_lib::strategy::{ParamSpec, StrategyParams};
+use crate::AppErrorEnum;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/*
+* 参数优化器
+*   所有优化器共用同一个目标（Objective 的得分，越大越好），通过 evaluate 批量回测一组参数并返回得分，
+*   同一批参数由调用方并行回测，优化器只负责决定下一批要回测的参数
+*   1. grid：按步长穷举参数空间，参数组数不能超过 iterations；未指定步长的参数按 iterations 平均分配取值个数，
+*      整数参数最多取 20 个值，小数参数最多把范围分成 10 份
+*   2. random：在参数空间中均匀随机采样
+*   3. genetic：遗传算法，锦标赛选择 + 均匀交叉 + 变异，每一代保留最优的个体
+*   4. tpe：Tree-structured Parzen Estimator，先随机采样，之后把已回测的参数按得分分为好、坏两组，
+*      对每个参数分别用高斯核估计两组的密度 l(x)、g(x)，从好的一组附近采样候选参数，选 l(x) / g(x) 最大的回测
+*/
+
+/// 未指定步长时，整数参数在网格搜索中最多取的值的个数
+const GRID_INTEGER_VALUES: f64 = 20.0;
+/// 未指定步长时，小数参数在网格搜索中最多取的值的个数
+const GRID_FLOAT_VALUES: f64 = 10.0;
+
+/// 参数优化方法
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum OptimizerKind {
+    #[default]
+    Random, // 随机搜索
+    Grid {
+        steps: Option<HashMap<String, f64>>, // 参数名 -> 步长
+    },
+    Genetic {
+        population: Option<usize>,  // 种群大小，默认 50
+        mutation_rate: Option<f64>, // 每个参数的变异概率，默认 0.1
+    },
+    Tpe {
+        startup: Option<usize>,    // 开始建模前随机采样的次数，默认 iterations 的 10%
+        candidates: Option<usize>, // 每次从好的一组附近采样的候选数量，默认 24
+    },
+}
+
+/// 参数优化设置
+#[derive(Debug, Clone)]
+pub struct OptimizeConfig {
+    pub iterations: usize,     // 回测次数上限，网格搜索的参数组数也不能超过它
+    pub objective: Objective,  // 选择最优参数的目标
+    pub seed: u64,             // 随机数种子，相同的种子和参数得到相同的结果
+    pub method: OptimizerKind, // 优化方法
+    pub top_k: usize,          // 返回得分最高的参数组数
+    pub keep_cloud: bool,      // 是否保留回测过的全部参数，用于参数敏感性分析，默认不保留
+}
+
+impl Default for OptimizeConfig {
+    fn default() -> Self {
+        OptimizeConfig {
+            iterations: 10000,
+            objective: Objective::default(),
+            seed: rand::random(),
+            method: OptimizerKind::default(),
+            top_k: 5,
+            keep_cloud: false,
+        }
+    }
+}
+
+/// 一组参数及其得分
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ParamScore {
+    pub params: StrategyParams,   // 参数
+    pub score: f64,               // 优化目标的得分
+    pub metrics: BacktestMetrics, // 绩效指标
+}
+
+/// 批量回测一组参数，返回与参数一一对应的得分
+pub type Evaluate<'a> = dyn Fn(&[StrategyParams]) -> Vec<f64> + 'a;
+
+/// 参数优化器
+pub trait Optimizer {
+    /// 优化器名称
+    fn name(&self) -> &'static str;
+    /// 在参数空间中搜索，返回回测过的全部参数及得分
+    /// param_space: 参与搜索的参数
+    /// fixed_params: 固定的参数，会合并到每一组参数中
+    /// evaluate: 批量回测
+    fn optimize(
+        &mut self,
+        param_space: &[ParamSpec],
+        fixed_params: &StrategyParams,
+        evaluate: &Evaluate,
+    ) -> Result<Vec<(StrategyParams, f64)>, AppErrorEnum>;
+}
+
+/// 根据设置创建优化器
+pub fn create_optimizer(config: &OptimizeConfig) -> Box<dyn Optimizer> {
+    let rng = StdRng::seed_from_u64(config.seed);
+    let iterations = config.iterations.max(1);
+    match &config.method {
+        OptimizerKind::Random => Box::new(RandomSearch { iterations, rng }),
+        OptimizerKind::Grid { steps } => Box::new(GridSearch {
+            steps: steps.clone().unwrap_or_default(),
+            iterations,
+        }),
+        OptimizerKind::Genetic {
+            population,
+            mutation_rate,
+        } => Box::new(GeneticSearch {
+            iterations,
+            population: population.unwrap_or(50).max(2),
+            mutation_rate: mutation_rate.unwrap_or(0.1),
+            rng,
+        }),
+        OptimizerKind::Tpe {
+            startup,
+            candidates,
+        } => Box::new(TpeSearch {
+            iterations,
+            startup: startup.unwrap_or(iterations / 10).max(2),
+            candidates: candidates.unwrap_or(24).max(1),
+            rng,
+        }),
+    }
+}
+
+/// 按得分从高到低排序，去掉重复的参数后取前 k 组，得分相同时保持回测的先后顺序
+pub fn col_top_k(mut scores: Vec<(StrategyParams, f64)>, k: usize) -> Vec<(StrategyParams, f64)> {
+    scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    let mut top: Vec<(StrategyParams, f64)> = Vec::with_capacity(k);
+    for (params, score) in scores {
+        if top.len() >= k {
+            break;
+        }
+        if !top.iter().any(|(p, _)| *p == params) {
+            top.push((params, score));
+        }
+    }
+    top
+}
+
+/// 把参数值限制在范围内，整数参数取整
+fn clamp_value(spec: &ParamSpec, value: f64) -> f64 {
+    if spec.min >= spec.max {
+        return spec.min;
+    }
+    if spec.integer {
+        // 整数参数的上限不含 max
+        value
+            .round()
+            .clamp(spec.min, (spec.max - 1.0).max(spec.min))
+    } else {
+        value.clamp(spec.min, spec.max)
+    }
+}
+
+/// 在范围内均匀随机取一个参数值
+fn sample_value<R: Rng>(rng: &mut R, spec: &ParamSpec) -> f64 {
+    if spec.min >= spec.max {
+        spec.min
+    } else if spec.integer {
+        // 上下限取整后可能相等，如 (14.2, 14.9)
+        let (lo, hi) = (spec.min as i64, spec.max as i64);
+        if lo >= hi {
+            lo as f64
+        } else {
+            rng.gen_range(lo..hi) as f64
+        }
+    } else {
+        rng.gen_range(spec.min..spec.max)
+    }
+}
+
+/// 在参数空间中随机取一组参数
+pub fn sample_params<R: Rng>(
+    rng: &mut R,
+    param_space: &[ParamSpec],
+    fixed_params: &StrategyParams,
+) -> StrategyParams {
+    let mut params = fixed_params.clone();
+    for spec in param_space {
+        params.insert(spec.name.clone(), sample_value(rng, spec));
+    }
+    params
+}
+
+/// 标准正态分布随机数（Box-Muller 变换）
+fn sample_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// 随机搜索
+pub struct RandomSearch {
+    iterations: usize,
+    rng: StdRng,
+}
+
+impl Optimizer for RandomSearch {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+    fn optimize(
+        &mut self,
+        param_space: &[ParamSpec],
+        fixed_params: &StrategyParams,
+        evaluate: &Evaluate,
+    ) -> Result<Vec<(StrategyParams, f64)>, AppErrorEnum> {
+        let n = if param_space.is_empty() {
+            1
+        } else {
+            self.iterations
+        };
+        let params_list: Vec<StrategyParams> = (0..n)
+            .map(|_| sample_params(&mut self.rng, param_space, fixed_params))
+            .collect();
+        let scores = evaluate(&params_list);
+        Ok(params_list.into_iter().zip(scores).collect())
+    }
+}
+
+/// 网格搜索
+pub struct GridSearch {
+    steps: HashMap<String, f64>, // 参数名 -> 步长
+    iterations: usize,           // 参数组数上限
+}
+
+impl GridSearch {
+    /// 参数指定的步长，不大于 0 时视为未指定
+    fn step_of(&self, spec: &ParamSpec) -> Option<f64> {
+        self.steps
+            .get(&spec.name)
+            .copied()
+            .filter(|step| *step > 0.0)
+    }
+    /// 未指定步长的参数各自最多取的值的个数
+    /// iterations 除以指定了步长的参数的组合数后，平均分配给未指定步长的参数，每个参数至少 2 个值
+    fn col_default_count(&self, param_space: &[ParamSpec]) -> f64 {
+        let (explicit, free): (Vec<&ParamSpec>, Vec<&ParamSpec>) = param_space
+            .iter()
+            .filter(|spec| spec.min < spec.max)
+            .partition(|spec| self.step_of(spec).is_some());
+        if free.is_empty() {
+            return 1.0;
+        }
+        let explicit_size = explicit
+            .iter()
+            .map(|spec| self.col_values(spec, 1.0).len())
+            .fold(1usize, |acc, count| acc.saturating_mul(count));
+        let budget = (self.iterations / explicit_size.max(1)) as f64;
+        budget.powf(1.0 / free.len() as f64).floor().max(2.0)
+    }
+    /// 单个参数的全部取值
+    /// default_count: 未指定步长时最多取的值的个数
+    fn col_values(&self, spec: &ParamSpec, default_count: f64) -> Vec<f64> {
+        if spec.min >= spec.max {
+            return vec![spec.min];
+        }
+        let step = self.step_of(spec).unwrap_or_else(|| {
+            if spec.integer {
+                ((spec.max - spec.min) / default_count.min(GRID_INTEGER_VALUES))
+                    .ceil()
+                    .max(1.0)
+            } else {
+                (spec.max - spec.min) / default_count.min(GRID_FLOAT_VALUES)
+            }
+        });
+        let mut values = vec![];
+        // 与随机搜索一致，上限不含 max；按下标计算取值，避免累加步长的误差多出一个接近 max 的值
+        let mut index = 0.0;
+        loop {
+            let value = spec.min + step * index;
+            if value >= spec.max - step * 1e-9 {
+                break;
+            }
+            let value_clamped = clamp_value(spec, value);
+            if values.last() != Some(&value_clamped) {
+                values.push(value_clamped);
+            }
+            index += 1.0;
+        }
+        values
+    }
+}
+
+impl Optimizer for GridSearch {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+    fn optimize(
+        &mut self,
+        param_space: &[ParamSpec],
+        fixed_params: &StrategyParams,
+        evaluate: &Evaluate,
+    ) -> Result<Vec<(StrategyParams, f64)>, AppErrorEnum> {
+        let default_count = self.col_default_count(param_space);
+        let value_list: Vec<Vec<f64>> = param_space
+            .iter()
+            .map(|spec| self.col_values(spec, default_count))
+            .collect();
+        let grid_size = value_list
+            .iter()
+            .try_fold(1usize, |acc, values| acc.checked_mul(values.len()))
+            .unwrap_or(usize::MAX);
+        if grid_size > self.iterations {
+            // 按取值个数从多到少列出参数，提示先增大哪些参数的步长
+            let mut count_list: Vec<(&str, usize)> = param_space
+                .iter()
+                .zip(value_list.iter())
+                .map(|(spec, values)| (spec.name.as_str(), values.len()))
+                .collect();
+            count_list.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            let detail: Vec<String> = count_list
+                .iter()
+                .map(|(name, count)| format!("{} {} 个取值", name, count))
+                .collect();
+            return Err(AppErrorEnum::InvalidParam(format!(
+                "网格搜索共 {} 组参数，超过上限 {}，请增大以下参数的步长或缩小范围：{}",
+                grid_size,
+                self.iterations,
+                detail.join("，")
+            )));
+        }
+        // 笛卡尔积
+        let mut params_list = vec![fixed_params.clone()];
+        for (spec, values) in param_space.iter().zip(value_list.iter()) {
+            params_list = params_list
+                .into_iter()
+                .flat_map(|params| {
+                    values.iter().map(move |value| {
+                        let mut params = params.clone();
+                        params.insert(spec.name.clone(), *value);
+                        params
+                    })
+                })
+                .collect();
+        }
+        let scores = evaluate(&params_list);
+        Ok(params_list.into_iter().zip(scores).collect())
+    }
+}
+
+/// 遗传算法
+pub struct GeneticSearch {
+    iterations: usize,  // 回测次数上限，代数 = iterations / population
+    population: usize,  // 种群大小
+    mutation_rate: f64, // 每个参数的变异概率
+    rng: StdRng,
+}
+
+impl GeneticSearch {
+    /// 锦标赛选择：随机取两个个体，保留得分高的
+    fn select<'a>(&mut self, scored: &'a [(StrategyParams, f64)]) -> &'a StrategyParams {
+        let a = &scored[self.rng.gen_range(0..scored.len())];
+        let b = &scored[self.rng.gen_range(0..scored.len())];
+        if a.1 >= b.1 {
+            &a.0
+        } else {
+            &b.0
+        }
+    }
+}
+
+impl Optimizer for GeneticSearch {
+    fn name(&self) -> &'static str {
+        "genetic"
+    }
+    fn optimize(
+        &mut self,
+        param_space: &[ParamSpec],
+        fixed_params: &StrategyParams,
+        evaluate: &Evaluate,
+    ) -> Result<Vec<(StrategyParams, f64)>, AppErrorEnum> {
+        if param_space.is_empty() {
+            let params_list = vec![fixed_params.clone()];
+            let scores = evaluate(&params_list);
+            return Ok(params_list.into_iter().zip(scores).collect());
+        }
+        let population = self.population.min(self.iterations);
+        let generations = (self.iterations / population).max(1);
+        let mut history: Vec<(StrategyParams, f64)> = vec![];
+        let mut generation: Vec<StrategyParams> = (0..population)
+            .map(|_| sample_params(&mut self.rng, param_space, fixed_params))
+            .collect();
+        for _ in 0..generations {
+            let scores = evaluate(&generation);
+            let mut scored: Vec<(StrategyParams, f64)> =
+                generation.into_iter().zip(scores).collect();
+            history.extend(scored.iter().cloned());
+            scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            // 精英保留：最优的个体直接进入下一代
+            let mut next = vec![scored[0].0.clone()];
+            while next.len() < population {
+                let father = self.select(&scored).clone();
+                let mother = self.select(&scored).clone();
+                let mut child = fixed_params.clone();
+                for spec in param_space {
+                    // 均匀交叉
+                    let parent = if self.rng.gen_bool(0.5) {
+                        &father
+                    } else {
+                        &mother
+                    };
+                    let mut value = parent.get(&spec.name).copied().unwrap_or(spec.default);
+                    // 变异：在原值附近按范围的 10% 扰动
+                    if self.rng.gen::<f64>() < self.mutation_rate {
+                        value += sample_normal(&mut self.rng) * (spec.max - spec.min) * 0.1;
+                    }
+                    child.insert(spec.name.clone(), clamp_value(spec, value));
+                }
+                next.push(child);
+            }
+            generation = next;
+        }
+        Ok(history)
+    }
+}
+
+/// Tree-structured Parzen Estimator
+pub struct TpeSearch {
+    iterations: usize, // 回测次数上限
+    startup: usize,    // 开始建模前随机采样的次数
+    candidates: usize, // 每次采样的候选数量
+    rng: StdRng,
+}
+
+/// 好的一组所占的比例
+const TPE_GAMMA: f64 = 0.25;
+/// 每一批并行回测的参数组数
+const TPE_BATCH: usize = 16;
+
+/// 高斯核密度估计
+/// points: 样本
+/// bandwidth: 带宽
+fn col_kde(points: &[f64], bandwidth: f64, x: f64) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+    points
+        .iter()
+        .map(|p| (-0.5 * ((x - p) / bandwidth).powi(2)).exp())
+        .sum::<f64>()
+        / points.len() as f64
+        / bandwidth
+}
+
+impl Optimizer for TpeSearch {
+    fn name(&self) -> &'static str {
+        "tpe"
+    }
+    fn optimize(
+        &mut self,
+        param_space: &[ParamSpec],
+        fixed_params: &StrategyParams,
+        evaluate: &Evaluate,
+    ) -> Result<Vec<(StrategyParams, f64)>, AppErrorEnum> {
+        if param_space.is_empty() {
+            let params_list = vec![fixed_params.clone()];
+            let scores = evaluate(&params_list);
+            return Ok(params_list.into_iter().zip(scores).collect());
+        }
+        let startup = self.startup.min(self.iterations);
+        let startup_list: Vec<StrategyParams> = (0..startup)
+            .map(|_| sample_params(&mut self.rng, param_space, fixed_params))
+            .collect();
+        let scores = evaluate(&startup_list);
+        let mut history: Vec<(StrategyParams, f64)> =
+            startup_list.into_iter().zip(scores).collect();
+        while history.len() < self.iterations {
+            // 按得分把已回测的参数分为好、坏两组
+            let mut sorted: Vec<&(StrategyParams, f64)> = history.iter().collect();
+            sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            let good_len = ((sorted.len() as f64 * TPE_GAMMA).ceil() as usize).max(1);
+            let (good, bad) = sorted.split_at(good_len);
+            let batch = TPE_BATCH.min(self.iterations - history.len());
+            let mut batch_list = Vec::with_capacity(batch);
+            for _ in 0..batch {
+                let mut params = fixed_params.clone();
+                // 每个参数独立建模
+                for spec in param_space {
+                    let value_of = |list: &[&(StrategyParams, f64)]| -> Vec<f64> {
+                        list.iter()
+                            .map(|(p, _)| p.get(&spec.name).copied().unwrap_or(spec.default))
+                            .collect()
+                    };
+                    let good_points = value_of(good);
+                    let bad_points = value_of(bad);
+                    let range = (spec.max - spec.min).max(f64::EPSILON);
+                    let bandwidth = range / (good_points.len() as f64).powf(0.2) * 0.2;
+                    let mut best_value = sample_value(&mut self.rng, spec);
+                    let mut best_ratio = f64::NEG_INFINITY;
+                    for _ in 0..self.candidates {
+                        let center = good_points[self.rng.gen_range(0..good_points.len())];
+                        let value =
+                            clamp_value(spec, center + sample_normal(&mut self.rng) * bandwidth);
+                        let l = col_kde(&good_points, bandwidth, value);
+                        let g = col_kde(&bad_points, bandwidth, value) + 1e-12;
+                        if l / g > best_ratio {
+                            best_ratio = l / g;
+                            best_value = value;
+                        }
+                    }
+                    params.insert(spec.name.clone(), best_value);
+                }
+                batch_list.push(params);
+            }
+            let scores = evaluate(&batch_list);
+            history.extend(batch_list.into_iter().zip(scores));
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::strategy::{create_strategy, StrategyData, STRATEGY_NAMES};
+
+    fn param_space() -> Vec<ParamSpec> {
+        vec![
+            ParamSpec::new("n", 2.0, 20.0, true, 5.0),
+            ParamSpec::new("k", 0.5, 1.5, false, 1.0),
+        ]
+    }
+
+    fn fixed_params() -> StrategyParams {
+        StrategyParams::from([("fixed".to_string(), 1.0)])
+    }
+
+    /// 得分在 n = 10、k = 1 时最高
+    fn evaluate(params_list: &[StrategyParams]) -> Vec<f64> {
+        params_list
+            .iter()
+            .map(|params| -(params["n"] - 10.0).powi(2) - (params["k"] - 1.0).powi(2))
+            .collect()
+    }
+
+    fn search(method: OptimizerKind, seed: u64) -> Vec<(StrategyParams, f64)> {
+        let config = OptimizeConfig {
+            iterations: 200,
+            seed,
+            method,
+            ..OptimizeConfig::default()
+        };
+        create_optimizer(&config)
+            .optimize(&param_space(), &fixed_params(), &evaluate)
+            .unwrap()
+    }
+
+    fn methods() -> Vec<OptimizerKind> {
+        vec![
+            OptimizerKind::Random,
+            OptimizerKind::Genetic {
+                population: Some(20),
+                mutation_rate: Some(0.5),
+            },
+            OptimizerKind::Tpe {
+                startup: None,
+                candidates: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn sample_value_handles_empty_integer_range() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let spec = ParamSpec::new("n", 14.2, 14.9, true, 14.0);
+        assert_eq!(sample_value(&mut rng, &spec), 14.0);
+        let spec = ParamSpec::new("n", 3.0, 3.0, true, 3.0);
+        assert_eq!(sample_value(&mut rng, &spec), 3.0);
+        let spec = ParamSpec::new("k", 0.5, 0.5, false, 0.5);
+        assert_eq!(sample_value(&mut rng, &spec), 0.5);
+    }
+
+    #[test]
+    fn same_seed_gives_same_result() {
+        for method in methods() {
+            let first = search(method.clone(), 7);
+            assert_eq!(first, search(method.clone(), 7));
+            assert_ne!(first, search(method, 8));
+        }
+    }
+
+    #[test]
+    fn search_stays_within_bounds() {
+        for method in methods() {
+            let history = search(method, 3);
+            assert_eq!(history.len(), 200);
+            for (params, _) in history {
+                assert_eq!(params["fixed"], 1.0);
+                let n = params["n"];
+                assert!((2.0..20.0).contains(&n) && n.fract() == 0.0, "n = {}", n);
+                let k = params["k"];
+                assert!((0.5..=1.5).contains(&k), "k = {}", k);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_enumerates_cartesian_product() {
+        let space = vec![
+            ParamSpec::new("n", 0.0, 3.0, true, 0.0),
+            ParamSpec::new("k", 0.0, 1.0, false, 0.0),
+        ];
+        let steps = HashMap::from([("k".to_string(), 0.5)]);
+        let history = GridSearch {
+            steps,
+            iterations: 100,
+        }
+        .optimize(
+            &space,
+            &fixed_params(),
+            &|params_list: &[StrategyParams]| vec![0.0; params_list.len()],
+        )
+        .unwrap();
+        let mut grid: Vec<(f64, f64)> = history
+            .iter()
+            .map(|(params, _)| (params["n"], params["k"]))
+            .collect();
+        grid.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            grid,
+            vec![
+                (0.0, 0.0),
+                (0.0, 0.5),
+                (1.0, 0.0),
+                (1.0, 0.5),
+                (2.0, 0.0),
+                (2.0, 0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn default_grid_fits_iterations() {
+        for iterations in [100, OptimizeConfig::default().iterations] {
+            let grid = GridSearch {
+                steps: HashMap::new(),
+                iterations,
+            };
+            for name in STRATEGY_NAMES {
+                let param_space = create_strategy(name, &StrategyData::default())
+                    .unwrap()
+                    .param_space();
+                let default_count = grid.col_default_count(&param_space);
+                let count_list: Vec<usize> = param_space
+                    .iter()
+                    .map(|spec| grid.col_values(spec, default_count).len())
+                    .collect();
+                let grid_size: usize = count_list.iter().product();
+                assert!(grid_size <= iterations, "{}: {}", name, grid_size);
+                // 每个参数都至少取两个值
+                assert!(count_list.iter().all(|count| *count >= 2), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_splits_iterations_across_free_params() {
+        let space = vec![
+            ParamSpec::new("n", 0.0, 100.0, true, 0.0),
+            ParamSpec::new("k", 0.0, 1.0, false, 0.0),
+        ];
+        // 指定步长的 n 有 10 个取值，剩余的 10 组全部分给 k
+        let steps = HashMap::from([("n".to_string(), 10.0)]);
+        let history = GridSearch {
+            steps,
+            iterations: 100,
+        }
+        .optimize(
+            &space,
+            &fixed_params(),
+            &|params_list: &[StrategyParams]| vec![0.0; params_list.len()],
+        )
+        .unwrap();
+        assert_eq!(history.len(), 100);
+        assert!(history.iter().all(|(params, _)| params["k"] < 1.0));
+    }
+
+    #[test]
+    fn oversize_grid_names_the_widest_range() {
+        let space = vec![
+            ParamSpec::new("n", 0.0, 2000.0, true, 0.0),
+            ParamSpec::new("m", 0.0, 2000.0, true, 0.0),
+        ];
+        let steps = HashMap::from([("n".to_string(), 1.0), ("m".to_string(), 1.0)]);
+        let result = GridSearch {
+            steps,
+            iterations: 10000,
+        }
+        .optimize(
+            &space,
+            &StrategyParams::new(),
+            &|params_list: &[StrategyParams]| vec![0.0; params_list.len()],
+        );
+        match result {
+            Err(AppErrorEnum::InvalidParam(message)) => {
+                assert!(message.contains("n 2000 个取值"), "{}", message)
+            }
+            _ => panic!("expected InvalidParam"),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::db::connection::Db;
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::benchmark::BenchmarkReport;
+use crate::stock_lib::get_industry_rps_list::get_industry_map;
+use crate::stock_lib::get_stock_rps_list::{get_local_stock_price_data, get_local_stock_rps_data};
+use crate::stock_lib::metrics::{col_metrics, BacktestMetrics};
+use crate::stock_lib::missing_bar::prepare_bars;
+use crate::stock_lib::risk::{RiskConfig, RiskManager};
+use crate::stock_lib::sizer::PositionSizer;
+use crate::stock_lib::stock_trade::{OperateRecord, RunConfig, ST_Account};
+use crate::stock_lib::strategy::{
+    create_strategy, BarContext, IndicatorCache, Strategy, StrategyData, StrategyParams,
+};
+use crate::AppErrorEnum;
+use rocket::serde::Serialize;
+use rocket::tokio::task;
+use rocket_db_pools::Connection;
+
+/// 组合回测结果，所有曲线与 dates 一一对应
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PortfolioResult {
+    pub dates: Vec<String>,                    // 统一的交易日历
+    pub cash: Vec<f64>,                        // 每日现金
+    pub total_assets: Vec<f64>,                // 每日总资产
+    pub positions: BTreeMap<String, Vec<f64>>, // 股票代码 -> 每日持仓市值
+    pub operate_record: Vec<OperateRecord>,    // 交易记录
+    pub reject_record: Vec<OperateRecord>,     // 因交易规则未能成交的订单
+    pub params: StrategyParams,                // 使用的策略参数
+    pub metrics: BacktestMetrics,              // 绩效指标
+    pub benchmark: Option<BenchmarkReport>,    // 与基准指数的对比
+}
+
+/// 多只股票共用一个账户，按统一的交易日历逐日回测
+/// 每只股票各自运行一个策略实例，策略看到的总资产为组合总资产按股票数量等分后的份额，现金为组合共享的现金
+/// 设置了仓位模型时，仓位上限同样按这个份额计算
+/// stock_map: 股票代码 -> 按日期升序排列的 K 线
+/// strategy_map: 股票代码 -> 已经用该股票 K 线初始化过的策略
+/// account: 共享的股票账户
+/// run_config: 回测运行设置，组合回测不使用其中的下单方式
+/// risk: 组合风控，在策略和仓位模型之后执行
+pub fn simulate_portfolio(
+    stock_map: &HashMap<String, Vec<StockPriceInfo>>,
+    strategy_map: &mut HashMap<String, Box<dyn Strategy>>,
+    account: &mut ST_Account,
+    run_config: &RunConfig,
+    risk: &mut RiskManager,
+) -> PortfolioResult {
+    // 所有股票交易日期的并集作为统一的交易日历
+    let calendar: BTreeSet<&String> = stock_map
+        .values()
+        .flat_map(|bars| bars.iter().filter_map(|bar| bar.trade_date.as_ref()))
+        .collect();
+    let mut code_list: Vec<&String> = stock_map.keys().collect();
+    code_list.sort();
+    let code_count = code_list.len().max(1) as f64;
+    let init_cash = account.latest_assets();
+    // 每只股票下一根待处理 K 线的下标
+    let mut cursor: HashMap<&String, usize> = code_list.iter().map(|code| (*code, 0)).collect();
+    // 每只股票各自的仓位模型，与策略一样只看到该股票的资产份额
+    let mut sizer_map: HashMap<&String, PositionSizer> = match run_config.sizer.as_ref() {
+        Some(config) => code_list
+            .iter()
+            .map(|code| {
+                let sizer = PositionSizer::new(
+                    config.clone(),
+                    account.rules().clone(),
+                    &stock_map[*code],
+                    &IndicatorCache::default(),
+                );
+                (*code, sizer)
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+    let mut result = PortfolioResult {
+        dates: Vec::with_capacity(calendar.len()),
+        cash: Vec::with_capacity(calendar.len()),
+        total_assets: Vec::with_capacity(calendar.len()),
+        positions: code_list
+            .iter()
+            .map(|code| (code.to_string(), Vec::with_capacity(calendar.len())))
+            .collect(),
+        operate_record: vec![],
+        reject_record: vec![],
+        params: StrategyParams::new(),
+        metrics: BacktestMetrics::default(),
+        benchmark: None,
+    };
+    for date in calendar {
+        account.new_trading_day();
+        // 当天有行情的股票及其 K 线下标，停牌的股票不参与当天的交易
+        let mut today_list: Vec<(&String, usize)> = vec![];
+        for code in code_list.iter() {
+            let bars = &stock_map[*code];
+            let index = cursor[code];
+            if index < bars.len() && bars[index].trade_date.as_ref() == Some(date) {
+                if let Some(close) = bars[index].close {
+                    account.mark_price(code.to_string(), close);
+                }
+                today_list.push((code, index));
+                cursor.insert(code, index + 1);
+            }
+        }
+        // 先统一更新价格并检查组合回撤，再按股票代码顺序执行策略
+        let today_bars: Vec<&StockPriceInfo> = today_list
+            .iter()
+            .map(|(code, index)| &stock_map[*code][*index])
+            .collect();
+        risk.on_day(
+            account,
+            &today_bars,
+            &mut result.operate_record,
+            &mut result.reject_record,
+        );
+        for (code, index) in today_list {
+            let today = &stock_map[code][index];
+            let strategy = match strategy_map.get_mut(code) {
+                Some(strategy) => strategy,
+                None => continue,
+            };
+            let ctx = BarContext {
+                index,
+                bar: today,
+                hold: account.hold_available(code.to_string()),
+                cash: account.cash_available(),
+                assets: account.latest_assets() / code_count,
+            };
+            let mut target = strategy.on_bar(&ctx);
+            if let Some(sizer) = sizer_map.get_mut(code) {
+                target = sizer.size(&ctx, target);
+            }
+            risk.execute(
+                account,
+                today,
+                target,
+                &mut result.operate_record,
+                &mut result.reject_record,
+            );
+        }
+        result.dates.push(date.to_string());
+        result.cash.push(account.cash_available());
+        result.total_assets.push(account.latest_assets());
+        for (code, curve) in result.positions.iter_mut() {
+            curve.push(account.hold_value(code.to_string()));
+        }
+    }
+    let position_value: Vec<f64> = (0..result.dates.len())
+        .map(|index| result.positions.values().map(|curve| curve[index]).sum())
+        .collect();
+    result.metrics = col_metrics(
+        &result.total_assets,
+        &position_value,
+        &result.operate_record,
+        init_cash,
+    );
+    result
+}
+
+/// 组合模拟交易
+/// codes: 股票代码, 数组，必填
+/// account: 初始账户，包含初始现金、费率和交易规则
+/// run_config: 回测运行设置
+/// strategy_name: 策略名称，必填
+/// params: 策略参数，未指定的参数使用默认值
+/// risk: 组合风控设置
+pub async fn simulate_portfolio_trade(
+    mut db: Connection<Db>,
+    codes: Vec<String>,
+    mut account: ST_Account,
+    run_config: RunConfig,
+    strategy_name: &str,
+    params: StrategyParams,
+    risk: RiskConfig,
+) -> Result<PortfolioResult, AppErrorEnum> {
+    // 未指定的参数使用策略的默认值
+    let mut params = params;
+    create_strategy(strategy_name, &StrategyData::default())
+        .ok_or_else(|| AppErrorEnum::InvalidParam(format!("未知的策略: {}", strategy_name)))?
+        .param_space()
+        .into_iter()
+        .for_each(|spec| {
+            params.entry(spec.name).or_insert(spec.default);
+        });
+    let mut stock_map: HashMap<String, Vec<StockPriceInfo>> = HashMap::new();
+    let mut strategy_map: HashMap<String, Box<dyn Strategy>> = HashMap::new();
+    for code in codes.iter() {
+        let df_stock = prepare_bars(
+            code,
+            get_local_stock_price_data(&mut db, code.clone()).await?,
+            run_config.missing_bar,
+        )?;
+        let strategy_data = StrategyData {
+            rps: Arc::new(get_local_stock_rps_data(&mut db, code.clone()).await?),
+            ..StrategyData::default()
+        };
+        let mut strategy = create_strategy(strategy_name, &strategy_data)
+            .ok_or_else(|| AppErrorEnum::InvalidParam(format!("未知的策略: {}", strategy_name)))?;
+        strategy.init(&params, &df_stock);
+        strategy_map.insert(code.clone(), strategy);
+        stock_map.insert(code.clone(), df_stock);
+    }
+    // 只保留回测股票的行业
+    let industry_map = get_industry_map(&mut db)
+        .await?
+        .into_iter()
+        .filter(|(code, _)| stock_map.contains_key(code))
+        .collect();
+    let mut risk = RiskManager::new(risk, industry_map);
+    // 多只股票逐日回测是 CPU 密集型任务，放到阻塞线程中执行，避免占用异步运行时
+    let mut result = task::spawn_blocking(move || {
+        simulate_portfolio(
+            &stock_map,
+            &mut strategy_map,
+            &mut account,
+            &run_config,
+            &mut risk,
+        )
+    })
+    .await?;
+    result.params = params;
+    Ok(result)
+}
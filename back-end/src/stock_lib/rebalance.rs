@@ -0,0 +1,641 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::db::schema::{rps_values, stock_daily_info};
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::benchmark::BenchmarkReport;
+use crate::stock_lib::metrics::{col_metrics, BacktestMetrics};
+use crate::stock_lib::missing_bar::{fill_missing_bars, MissingBarPolicy, MIN_HISTORY_BARS};
+use crate::stock_lib::stock_trade::{execute_target, OperateRecord, ST_Account};
+use crate::stock_lib::strategy::TargetPosition;
+use crate::AppErrorEnum;
+use diesel::mysql::Mysql;
+use diesel::{ExpressionMethods, QueryDsl};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::task;
+use rocket_db_pools::diesel::{AsyncMysqlConnection, RunQueryDsl};
+
+/*
+* 截面排名轮动回测
+*   从第一个有股票可交易的交易日开始，每隔 period 个交易日对当天可交易的股票按因子打分排名，持有得分最高的 top_k 只
+*   1. 因子：RPS 取 rps_values 当天的值；动量为 lookback 日涨幅；波动率为 lookback 日收益率的标准差，越低得分越高；
+*      成交额为 lookback 日平均成交额
+*   2. 可交易：当天有收盘价，因子有值，且之前至少有 lookback 根 K 线；rps_values 只保存排名靠前的股票，不在榜单中的股票不参与 RPS 排名
+*   3. 权重：等权，或按因子在可交易股票中的百分位排名加权
+*   4. 调仓：目标股数 = 调仓前总资产 * 权重 / 收盘价，先卖后买，成交、费用和交易规则都通过 ST_Account 处理，
+*      停牌的持仓无法卖出，继续持有到下一次调仓
+*   5. 换手率：单次调仓的成交金额 / 调仓前总资产
+*   不指定股票池时必须指定开始日期，股票池和日期范围内的行情超过 MAX_RANGE_ROWS 条时返回错误
+*/
+
+/// 一次轮动回测最多加载的行情条数，约为全市场 5000 只股票一年半的行情
+const MAX_RANGE_ROWS: i64 = 2_000_000;
+
+// 定义一个通用的 Result 类型，默认错误类型为 AppErrorEnum
+type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
+
+/// 排名因子，得分越高越优先持有
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum RankFactor {
+    /// rps_values 中当天的 RPS，rps_values 每天只保存排名靠前的约 300 只股票，
+    /// 不在榜单中的股票当天不参与排名，可交易的股票池会比其他因子小得多
+    #[default]
+    Rps,
+    Momentum {
+        lookback: usize, // 涨幅的交易日数
+    },
+    Volatility {
+        lookback: usize, // 计算波动率的交易日数，波动率越低得分越高
+    },
+    Amount {
+        lookback: usize, // 平均成交额的交易日数
+    },
+}
+
+impl RankFactor {
+    /// 因子需要的回看 K 线根数
+    fn lookback(&self) -> usize {
+        match self {
+            RankFactor::Rps => 0,
+            RankFactor::Momentum { lookback }
+            | RankFactor::Volatility { lookback }
+            | RankFactor::Amount { lookback } => *lookback,
+        }
+    }
+}
+
+/// 持仓权重
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum Weighting {
+    #[default]
+    Equal, // 等权
+    Score, // 按因子的百分位排名加权
+}
+
+/// 轮动回测设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RebalanceConfig {
+    pub factor: RankFactor,         // 排名因子，默认 RPS
+    pub period: usize,              // 调仓间隔的交易日数，默认 20
+    pub top_k: usize,               // 持有的股票数，默认 10
+    pub weighting: Weighting,       // 持仓权重，默认等权
+    pub start_date: Option<String>, // 开始日期，不传时从最早的行情开始
+    pub end_date: Option<String>,   // 结束日期，不传时到最新的行情
+}
+
+impl Default for RebalanceConfig {
+    fn default() -> Self {
+        RebalanceConfig {
+            factor: RankFactor::Rps,
+            period: 20,
+            top_k: 10,
+            weighting: Weighting::Equal,
+            start_date: None,
+            end_date: None,
+        }
+    }
+}
+
+/// 单次调仓的一只目标持仓
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RebalanceHolding {
+    pub code: String, // 股票代码
+    pub score: f64,   // 因子得分
+    pub weight: f64,  // 目标权重
+}
+
+/// 单次调仓记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RebalanceRecord {
+    pub date: String,                    // 调仓日期
+    pub eligible: usize,                 // 参与排名的股票数
+    pub holdings: Vec<RebalanceHolding>, // 目标持仓，按得分从高到低排列
+    pub turnover: f64,                   // 成交金额 / 调仓前总资产
+    pub fee: f64,                        // 本次调仓的交易费用
+}
+
+/// 轮动回测结果，所有曲线与 dates 一一对应
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RebalanceResult {
+    pub dates: Vec<String>,                 // 统一的交易日历
+    pub cash: Vec<f64>,                     // 每日现金
+    pub total_assets: Vec<f64>,             // 每日总资产
+    pub operate_record: Vec<OperateRecord>, // 交易记录
+    pub reject_record: Vec<OperateRecord>,  // 因交易规则未能成交的订单
+    pub rebalances: Vec<RebalanceRecord>,   // 每次调仓的目标持仓、换手率和费用
+    pub avg_turnover: f64,                  // 平均单次调仓换手率
+    pub total_fee: f64,                     // 交易费用合计
+    pub metrics: BacktestMetrics,           // 绩效指标
+    pub benchmark: Option<BenchmarkReport>, // 与基准指数的对比
+}
+
+/// 计算第 index 根 K 线上的因子得分，数据不足时返回 None
+/// rps: 当天的 RPS，只有 RPS 因子用到
+fn col_factor(
+    factor: &RankFactor,
+    bars: &[StockPriceInfo],
+    index: usize,
+    rps: Option<f64>,
+) -> Option<f64> {
+    let lookback = factor.lookback();
+    if index < lookback {
+        return None;
+    }
+    let window = &bars[index - lookback..=index];
+    match factor {
+        RankFactor::Rps => rps,
+        RankFactor::Momentum { .. } => {
+            let start = window.first()?.close.filter(|close| *close > 0.0)?;
+            Some(window.last()?.close? / start - 1.0)
+        }
+        RankFactor::Volatility { .. } => {
+            let returns: Vec<f64> = window
+                .windows(2)
+                .filter_map(|pair| {
+                    let pre_close = pair[0].close.filter(|close| *close > 0.0)?;
+                    Some(pair[1].close? / pre_close - 1.0)
+                })
+                .collect();
+            if returns.len() < 2 {
+                return None;
+            }
+            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+            let var = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+                / (returns.len() - 1) as f64;
+            Some(-var.sqrt())
+        }
+        RankFactor::Amount { .. } => {
+            let amount_list: Vec<f64> = window[1..].iter().filter_map(|bar| bar.amount).collect();
+            if amount_list.is_empty() {
+                return None;
+            }
+            Some(amount_list.iter().sum::<f64>() / amount_list.len() as f64)
+        }
+    }
+}
+
+/// 按得分排名并计算前 top_k 只股票的目标权重
+/// score_list: (股票代码, 得分)
+fn col_target_weights(
+    mut score_list: Vec<(String, f64)>,
+    top_k: usize,
+    weighting: Weighting,
+) -> Vec<RebalanceHolding> {
+    // 得分相同时按股票代码排序，保证结果可以复现
+    score_list.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let total = score_list.len() as f64;
+    let selected: Vec<(String, f64, f64)> = score_list
+        .into_iter()
+        .take(top_k)
+        .enumerate()
+        // 百分位排名：排第一的为 100
+        .map(|(rank, (code, score))| (code, score, (total - rank as f64) / total * 100.0))
+        .collect();
+    let rank_sum: f64 = selected.iter().map(|(_, _, pct)| pct).sum();
+    let count = selected.len() as f64;
+    selected
+        .into_iter()
+        .map(|(code, score, pct)| RebalanceHolding {
+            code,
+            score,
+            weight: match weighting {
+                Weighting::Equal => 1.0 / count,
+                Weighting::Score => pct / rank_sum,
+            },
+        })
+        .collect()
+}
+
+/// 按统一的交易日历逐日回测，每 period 个交易日调仓一次
+/// stock_map: 股票代码 -> 按日期升序排列的 K 线
+/// rps_map: 交易日期 -> 股票代码 -> rps
+/// account: 股票账户
+/// config: 轮动回测设置
+pub fn simulate_rebalance(
+    stock_map: &HashMap<String, Vec<StockPriceInfo>>,
+    rps_map: &HashMap<String, HashMap<String, f64>>,
+    account: &mut ST_Account,
+    config: &RebalanceConfig,
+) -> RebalanceResult {
+    let calendar: BTreeSet<&String> = stock_map
+        .values()
+        .flat_map(|bars| bars.iter().filter_map(|bar| bar.trade_date.as_ref()))
+        .collect();
+    let mut code_list: Vec<&String> = stock_map.keys().collect();
+    code_list.sort();
+    let init_cash = account.latest_assets();
+    let period = config.period.max(1);
+    // 下一次调仓的交易日序号，因子预热期间每天检查一次
+    let mut next_rebalance = 0;
+    // 每只股票下一根待处理 K 线的下标
+    let mut cursor: HashMap<&String, usize> = code_list.iter().map(|code| (*code, 0)).collect();
+    let mut result = RebalanceResult {
+        dates: Vec::with_capacity(calendar.len()),
+        cash: Vec::with_capacity(calendar.len()),
+        total_assets: Vec::with_capacity(calendar.len()),
+        operate_record: vec![],
+        reject_record: vec![],
+        rebalances: vec![],
+        avg_turnover: 0.0,
+        total_fee: 0.0,
+        metrics: BacktestMetrics::default(),
+        benchmark: None,
+    };
+    for (day, date) in calendar.into_iter().enumerate() {
+        account.new_trading_day();
+        // 当天有行情的股票及其 K 线下标
+        let mut today_map: HashMap<&String, usize> = HashMap::new();
+        for code in code_list.iter() {
+            let bars = &stock_map[*code];
+            let index = cursor[code];
+            if index < bars.len() && bars[index].trade_date.as_ref() == Some(date) {
+                if let Some(close) = bars[index].close {
+                    account.mark_price(code.to_string(), close);
+                }
+                today_map.insert(code, index);
+                cursor.insert(code, index + 1);
+            }
+        }
+        if day >= next_rebalance {
+            let today_rps = rps_map.get(date);
+            let score_list: Vec<(String, f64)> = code_list
+                .iter()
+                .filter_map(|code| {
+                    let index = *today_map.get(code)?;
+                    let bars = &stock_map[*code];
+                    bars[index].close?;
+                    let rps = today_rps.and_then(|rps| rps.get(*code)).copied();
+                    let score = col_factor(&config.factor, bars, index, rps)?;
+                    Some((code.to_string(), score))
+                })
+                .collect();
+            let eligible = score_list.len();
+            // 没有可交易的股票时（如因子还在预热）维持原有持仓，第二天再检查
+            next_rebalance = day + 1;
+            if eligible > 0 {
+                next_rebalance = day + period;
+                let holdings = col_target_weights(score_list, config.top_k, config.weighting);
+                let assets = account.latest_assets();
+                let target_map: HashMap<&String, f64> = holdings
+                    .iter()
+                    .map(|holding| (&holding.code, holding.weight))
+                    .collect();
+                let operate_len = result.operate_record.len();
+                // 先卖出不在目标中或超出目标的持仓，释放现金后再买入
+                let mut order_list: Vec<(&String, usize, usize)> = code_list
+                    .iter()
+                    .filter_map(|code| {
+                        let index = *today_map.get(code)?;
+                        let close = stock_map[*code][index].close.filter(|close| *close > 0.0)?;
+                        let weight = target_map.get(code).copied().unwrap_or(0.0);
+                        let target = (assets * weight / close).floor() as usize;
+                        let hold = account.hold_available(code.to_string());
+                        (target != hold).then_some((*code, index, target))
+                    })
+                    .collect();
+                order_list.sort_by_key(|(code, _, target)| {
+                    (*target > account.hold_available(code.to_string()), *code)
+                });
+                for (code, index, target) in order_list {
+                    execute_target(
+                        account,
+                        &stock_map[code][index],
+                        TargetPosition::Shares(target),
+                        &mut result.operate_record,
+                        &mut result.reject_record,
+                    );
+                }
+                let records = &result.operate_record[operate_len..];
+                let value: f64 = records
+                    .iter()
+                    .map(|record| record.price * record.operate_num as f64)
+                    .sum();
+                let fee: f64 = records.iter().map(|record| record.fee).sum();
+                result.total_fee += fee;
+                result.rebalances.push(RebalanceRecord {
+                    date: date.to_string(),
+                    eligible,
+                    holdings,
+                    turnover: if assets > 0.0 { value / assets } else { 0.0 },
+                    fee,
+                });
+            }
+        }
+        result.dates.push(date.to_string());
+        result.cash.push(account.cash_available());
+        result.total_assets.push(account.latest_assets());
+    }
+    let position_value: Vec<f64> = result
+        .total_assets
+        .iter()
+        .zip(result.cash.iter())
+        .map(|(assets, cash)| assets - cash)
+        .collect();
+    result.metrics = col_metrics(
+        &result.total_assets,
+        &position_value,
+        &result.operate_record,
+        init_cash,
+    );
+    let count = result.rebalances.len().max(1) as f64;
+    result.avg_turnover = result
+        .rebalances
+        .iter()
+        .map(|record| record.turnover)
+        .sum::<f64>()
+        / count;
+    result
+}
+
+/// 按股票池和日期范围筛选行情
+/// codes: 股票池，为空时不限制股票
+fn stock_range_query(
+    codes: &Option<Vec<String>>,
+    start_date: &Option<String>,
+    end_date: &Option<String>,
+) -> stock_daily_info::BoxedQuery<'static, Mysql> {
+    let mut query = stock_daily_info::table.into_boxed();
+    if let Some(codes) = codes {
+        query = query.filter(stock_daily_info::ts_code.eq_any(codes.clone()));
+    }
+    if let Some(start_date) = start_date {
+        query = query.filter(stock_daily_info::trade_date.ge(start_date.clone()));
+    }
+    if let Some(end_date) = end_date {
+        query = query.filter(stock_daily_info::trade_date.le(end_date.clone()));
+    }
+    query
+}
+
+/// 加载日期范围内的行情，按股票代码分组并按日期升序排列，行情条数超过 MAX_RANGE_ROWS 时返回错误
+/// codes: 股票池，为空时加载全部股票
+async fn get_local_stock_range(
+    conn: &mut AsyncMysqlConnection,
+    codes: &Option<Vec<String>>,
+    start_date: &Option<String>,
+    end_date: &Option<String>,
+) -> Result<HashMap<String, Vec<StockPriceInfo>>> {
+    let rows: i64 = stock_range_query(codes, start_date, end_date)
+        .count()
+        .get_result(conn)
+        .await?;
+    if rows > MAX_RANGE_ROWS {
+        return Err(AppErrorEnum::InvalidParam(format!(
+            "股票池和日期范围内共有 {} 条行情，超过上限 {}，请缩小股票池或日期范围",
+            rows, MAX_RANGE_ROWS
+        )));
+    }
+    let stock_list = stock_range_query(codes, start_date, end_date)
+        .order((
+            stock_daily_info::ts_code.asc(),
+            stock_daily_info::trade_date.asc(),
+        ))
+        .load::<StockPriceInfo>(conn)
+        .await?;
+    let mut stock_map: HashMap<String, Vec<StockPriceInfo>> = HashMap::new();
+    for stock in stock_list {
+        stock_map
+            .entry(stock.ts_code.clone())
+            .or_default()
+            .push(stock);
+    }
+    Ok(stock_map)
+}
+
+/// 加载日期范围内的 RPS，交易日期 -> 股票代码 -> rps
+async fn get_rps_range(
+    conn: &mut AsyncMysqlConnection,
+    start_date: &Option<String>,
+    end_date: &Option<String>,
+) -> Result<HashMap<String, HashMap<String, f64>>> {
+    let mut query = rps_values::table
+        .select((rps_values::ts_code, rps_values::trade_date, rps_values::rps))
+        .into_boxed();
+    if let Some(start_date) = start_date {
+        query = query.filter(rps_values::trade_date.ge(start_date.clone()));
+    }
+    if let Some(end_date) = end_date {
+        query = query.filter(rps_values::trade_date.le(end_date.clone()));
+    }
+    let rps_list = query
+        .load::<(String, Option<String>, Option<f64>)>(conn)
+        .await?;
+    let mut rps_map: HashMap<String, HashMap<String, f64>> = HashMap::new();
+    for (ts_code, trade_date, rps) in rps_list {
+        if let (Some(trade_date), Some(rps)) = (trade_date, rps) {
+            rps_map.entry(trade_date).or_default().insert(ts_code, rps);
+        }
+    }
+    Ok(rps_map)
+}
+
+/// 截面排名轮动回测
+/// codes: 股票池，为空时使用全部股票，此时必须指定开始日期
+/// account: 初始账户，包含初始现金、费率和交易规则
+/// missing_bar: 缺失 K 线的处理方式
+/// config: 轮动回测设置
+pub async fn simulate_rebalance_trade(
+    conn: &mut AsyncMysqlConnection,
+    codes: Option<Vec<String>>,
+    mut account: ST_Account,
+    missing_bar: MissingBarPolicy,
+    config: &RebalanceConfig,
+) -> Result<RebalanceResult> {
+    if config.top_k == 0 {
+        return Err(AppErrorEnum::InvalidParam(
+            "持有的股票数必须大于 0".to_string(),
+        ));
+    }
+    if codes.is_none() && config.start_date.is_none() {
+        return Err(AppErrorEnum::InvalidParam(
+            "不指定股票池时必须指定开始日期".to_string(),
+        ));
+    }
+    let stock_map: HashMap<String, Vec<StockPriceInfo>> =
+        get_local_stock_range(conn, &codes, &config.start_date, &config.end_date)
+            .await?
+            .into_iter()
+            .map(|(code, bars)| (code, fill_missing_bars(bars, missing_bar)))
+            .filter(|(_, bars)| bars.len() >= MIN_HISTORY_BARS)
+            .collect();
+    if stock_map.is_empty() {
+        return Err(AppErrorEnum::InvalidParam(
+            "日期范围内没有可回测的股票".to_string(),
+        ));
+    }
+    let rps_map = match config.factor {
+        RankFactor::Rps => get_rps_range(conn, &config.start_date, &config.end_date).await?,
+        _ => HashMap::new(),
+    };
+    // 全市场逐日排名是 CPU 密集型任务，放到阻塞线程中执行，避免占用异步运行时
+    let config = config.clone();
+    let result = task::spawn_blocking(move || {
+        simulate_rebalance(&stock_map, &rps_map, &mut account, &config)
+    })
+    .await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::stock_trade::OrderType;
+    use crate::stock_lib::test_util::{mock_account, mock_series};
+
+    const CODE_A: &str = "600000.SH";
+    const CODE_B: &str = "600036.SH";
+    const CODE_C: &str = "601318.SH";
+
+    fn score_list(list: &[(&str, f64)]) -> Vec<(String, f64)> {
+        list.iter()
+            .map(|(code, score)| (code.to_string(), *score))
+            .collect()
+    }
+
+    /// 按收盘价序列回测，动量因子回看 1 天
+    fn run(
+        close_map: &[(&str, &[f64])],
+        period: usize,
+        top_k: usize,
+    ) -> (RebalanceResult, ST_Account) {
+        let stock_map: HashMap<String, Vec<StockPriceInfo>> = close_map
+            .iter()
+            .map(|(code, close_list)| (code.to_string(), mock_series(code, close_list)))
+            .collect();
+        let config = RebalanceConfig {
+            factor: RankFactor::Momentum { lookback: 1 },
+            period,
+            top_k,
+            ..RebalanceConfig::default()
+        };
+        let mut account = mock_account(&[], 100000.0);
+        let result = simulate_rebalance(&stock_map, &HashMap::new(), &mut account, &config);
+        (result, account)
+    }
+
+    #[test]
+    fn ranking_breaks_ties_by_code() {
+        let holdings = col_target_weights(
+            score_list(&[
+                (CODE_A, 3.0),
+                (CODE_C, 2.0),
+                (CODE_B, 2.0),
+                ("000001.SZ", 1.0),
+            ]),
+            3,
+            Weighting::Equal,
+        );
+        let codes: Vec<&str> = holdings
+            .iter()
+            .map(|holding| holding.code.as_str())
+            .collect();
+        assert_eq!(codes, vec![CODE_A, CODE_B, CODE_C]);
+        assert!(holdings.iter().all(|holding| holding.weight == 1.0 / 3.0));
+    }
+
+    #[test]
+    fn score_weighting_uses_percentile_rank() {
+        let holdings = col_target_weights(
+            score_list(&[
+                (CODE_A, 3.0),
+                (CODE_B, 2.0),
+                (CODE_C, 1.0),
+                ("000001.SZ", 0.0),
+            ]),
+            3,
+            Weighting::Score,
+        );
+        // 百分位排名为 100、75、50
+        let weights: Vec<f64> = holdings.iter().map(|holding| holding.weight).collect();
+        assert_eq!(weights, vec![100.0 / 225.0, 75.0 / 225.0, 50.0 / 225.0]);
+        // 可交易的股票少于 top_k 时全部持有
+        let holdings = col_target_weights(score_list(&[(CODE_A, 1.0)]), 3, Weighting::Score);
+        assert_eq!(holdings.len(), 1);
+        assert_eq!(holdings[0].weight, 1.0);
+    }
+
+    #[test]
+    fn factor_needs_lookback() {
+        let bars = mock_series(CODE_A, &[10.0, 11.0, 12.0]);
+        let momentum = RankFactor::Momentum { lookback: 2 };
+        assert_eq!(col_factor(&momentum, &bars, 1, None), None);
+        let score = col_factor(&momentum, &bars, 2, None).unwrap();
+        assert!((score - 0.2).abs() < 1e-9);
+        // 波动率越低得分越高
+        let volatility = RankFactor::Volatility { lookback: 2 };
+        assert!(col_factor(&volatility, &bars, 2, None).unwrap() < 0.0);
+        assert_eq!(
+            col_factor(&RankFactor::Rps, &bars, 0, Some(95.0)),
+            Some(95.0)
+        );
+        assert_eq!(col_factor(&RankFactor::Rps, &bars, 0, None), None);
+    }
+
+    #[test]
+    fn rebalance_every_period_after_warmup() {
+        let close_list = [10.0, 10.1, 10.2, 10.3, 10.4, 10.5, 10.6, 10.7];
+        let (result, _) = run(&[(CODE_A, &close_list), (CODE_B, &close_list)], 3, 1);
+        // 第一天没有可交易的股票，从第二天开始每 3 个交易日调仓一次
+        let dates: Vec<&str> = result
+            .rebalances
+            .iter()
+            .map(|record| record.date.as_str())
+            .collect();
+        assert_eq!(dates, vec!["20240102", "20240105", "20240108"]);
+        assert!(result.rebalances.iter().all(|record| record.eligible == 2));
+        assert_eq!(result.dates.len(), 8);
+    }
+
+    #[test]
+    fn holds_top_k_by_score() {
+        let (result, account) = run(
+            &[
+                (CODE_A, &[10.0, 11.0, 11.0]),
+                (CODE_B, &[10.0, 10.5, 10.5]),
+                (CODE_C, &[10.0, 9.0, 9.0]),
+            ],
+            5,
+            2,
+        );
+        let codes: Vec<&str> = result.rebalances[0]
+            .holdings
+            .iter()
+            .map(|holding| holding.code.as_str())
+            .collect();
+        assert_eq!(codes, vec![CODE_A, CODE_B]);
+        assert!(account.hold_available(CODE_A.to_string()) > 0);
+        assert!(account.hold_available(CODE_B.to_string()) > 0);
+        assert_eq!(account.hold_available(CODE_C.to_string()), 0);
+    }
+
+    #[test]
+    fn sells_before_buys() {
+        // A 先涨后跌，B 先跌后涨，第 4 天从 A 全仓换到 B
+        let (result, account) = run(
+            &[
+                (CODE_A, &[10.0, 11.0, 12.0, 11.0, 11.0]),
+                (CODE_B, &[10.0, 9.0, 8.0, 9.0, 9.0]),
+            ],
+            2,
+            1,
+        );
+        let records: Vec<&OperateRecord> = result
+            .operate_record
+            .iter()
+            .filter(|record| record.operate_date.as_deref() == Some("20240104"))
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert!(matches!(records[0].order_type, OrderType::Sell) && records[0].code == CODE_A);
+        assert!(matches!(records[1].order_type, OrderType::Buy) && records[1].code == CODE_B);
+        // 卖出 A 的现金用于买入 B，接近全仓
+        let hold_b = account.hold_available(CODE_B.to_string());
+        assert!(hold_b as f64 * 9.0 > account.latest_assets() * 0.95);
+        assert_eq!(account.hold_available(CODE_A.to_string()), 0);
+        assert!(result.rebalances[1].turnover > 1.5);
+    }
+}
@@ -0,0 +1,555 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::stock_trade::{execute_target, OperateRecord, OrderType, ST_Account};
+use crate::stock_lib::strategy::TargetPosition;
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/*
+* 组合风控
+*   在策略和仓位模型给出目标仓位之后执行，只在组合回测中生效，每次干预都写入交易记录的 reason
+*   1. 移动止损：收盘价从持仓期间（含开仓当天）的最高收盘价回撤 trailing_stop 比例时清仓
+*   2. 时间止损：持仓超过 time_stop 根 K 线时清仓
+*      止损后不再买入该股票，直到策略给出清仓信号，或设置了 stop_cooldown 时经过 stop_cooldown 个交易日
+*   3. 回撤熔断：组合总资产从最高点回撤 max_drawdown 比例时清仓全部持仓，之后 cooldown 根 K 线内不开新仓，
+*      冷却结束后以当时的总资产作为新的最高点；cooldown 为 0 时熔断后不再开仓
+*   4. 最大持仓数：同时持有的股票数达到 max_positions 时不再买入新的股票
+*   5. 行业上限：同一行业（stock_info_list.industry）的持仓市值不超过总资产的 industry_cap 比例，超出时减少买入股数
+*   被风控拦截的买入记入未成交订单，风控触发的卖出记入交易记录
+*/
+
+/// 风控设置，字段为空表示不启用对应的规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RiskConfig {
+    pub trailing_stop: Option<f64>,   // 移动止损的回撤比例，如 0.1
+    pub time_stop: Option<usize>,     // 最长持仓 K 线根数
+    pub stop_cooldown: Option<usize>, // 止损后禁止买入的交易日数，为空时等到策略给出清仓信号
+    pub max_drawdown: Option<f64>,    // 触发熔断的组合回撤比例，如 0.2
+    pub cooldown: usize,              // 熔断后暂停开仓的 K 线根数，0 表示不再开仓
+    pub max_positions: Option<usize>, // 最多同时持有的股票数
+    pub industry_cap: Option<f64>,    // 单个行业持仓市值占总资产的上限，如 0.3
+}
+
+/// 单只持仓的风控状态
+struct PositionState {
+    peak_close: f64, // 持仓期间的最高收盘价
+    bars: usize,     // 已持有的 K 线根数
+}
+
+/// 组合回测的风控，每次回测新建一个
+pub struct RiskManager {
+    config: RiskConfig,
+    industry_map: HashMap<String, String>, // 股票代码 -> 所属行业
+    positions: HashMap<String, PositionState>,
+    stopped: HashMap<String, usize>, // 被止损的股票 -> 止损的交易日
+    peak_assets: f64,                // 总资产的最高点
+    day: usize,                      // 已回测的交易日数
+    halted_until: Option<usize>,     // 熔断结束的交易日，usize::MAX 表示不再开仓
+}
+
+impl RiskManager {
+    /// config: 风控设置
+    /// industry_map: 股票代码 -> 所属行业，没有行业信息的股票不受行业上限约束
+    pub fn new(config: RiskConfig, industry_map: HashMap<String, String>) -> Self {
+        RiskManager {
+            config,
+            industry_map,
+            positions: HashMap::new(),
+            stopped: HashMap::new(),
+            peak_assets: 0.0,
+            day: 0,
+            halted_until: None,
+        }
+    }
+
+    /// 是否处于熔断期间
+    fn halted(&self) -> bool {
+        self.halted_until.is_some_and(|until| self.day < until)
+    }
+
+    /// 每个交易日更新价格之后调用，检查组合回撤，触发熔断时清仓当天有行情的全部持仓
+    /// account: 共享的股票账户
+    /// bars: 当天有行情的股票的 K 线
+    /// operate_query: 交易记录
+    /// reject_query: 未能成交的订单
+    pub fn on_day(
+        &mut self,
+        account: &mut ST_Account,
+        bars: &[&StockPriceInfo],
+        operate_query: &mut Vec<OperateRecord>,
+        reject_query: &mut Vec<OperateRecord>,
+    ) {
+        self.day += 1;
+        let assets = account.latest_assets();
+        if self.halted_until.is_some() && !self.halted() {
+            // 冷却结束，以当前资产作为新的最高点
+            self.halted_until = None;
+            self.peak_assets = assets;
+        }
+        self.peak_assets = self.peak_assets.max(assets);
+        let max_drawdown = match self.config.max_drawdown {
+            Some(max_drawdown) if !self.halted() && self.peak_assets > 0.0 => max_drawdown,
+            _ => return,
+        };
+        let drawdown = 1.0 - assets / self.peak_assets;
+        if drawdown < max_drawdown {
+            return;
+        }
+        self.halted_until = Some(if self.config.cooldown == 0 {
+            usize::MAX
+        } else {
+            self.day + self.config.cooldown
+        });
+        let reason = format!("组合回撤 {:.2}% 触发熔断，清仓全部持仓", drawdown * 100.0);
+        for bar in bars {
+            if account.hold_available(bar.ts_code.clone()) > 0 {
+                execute_with_reason(
+                    account,
+                    bar,
+                    TargetPosition::Flat,
+                    &reason,
+                    operate_query,
+                    reject_query,
+                );
+            }
+        }
+    }
+
+    /// 更新持仓的最高价和持有天数，触发移动止损或时间止损时返回原因
+    fn check_stop(&mut self, code: &str, hold: usize, close: Option<f64>) -> Option<String> {
+        if hold == 0 {
+            self.positions.remove(code);
+            return None;
+        }
+        let close = close?;
+        let state = self
+            .positions
+            .entry(code.to_string())
+            .or_insert(PositionState {
+                peak_close: close,
+                bars: 0,
+            });
+        state.peak_close = state.peak_close.max(close);
+        state.bars += 1;
+        if let Some(trailing_stop) = self.config.trailing_stop {
+            if close <= state.peak_close * (1.0 - trailing_stop) {
+                return Some(format!(
+                    "移动止损：收盘价 {:.2} 较持仓最高价 {:.2} 回撤超过 {:.2}%",
+                    close,
+                    state.peak_close,
+                    trailing_stop * 100.0
+                ));
+            }
+        }
+        match self.config.time_stop {
+            Some(time_stop) if state.bars > time_stop => {
+                Some(format!("时间止损：持仓超过 {} 根 K 线", time_stop))
+            }
+            _ => None,
+        }
+    }
+
+    /// 止损后是否仍禁止买入，策略给出清仓信号或冷却结束时解除
+    /// code: 股票代码
+    /// target: 策略和仓位模型给出的目标仓位
+    fn check_stopped(&mut self, code: &str, target: &TargetPosition) -> bool {
+        let stop_day = match self.stopped.get(code) {
+            Some(stop_day) => *stop_day,
+            None => return false,
+        };
+        let cooled = self
+            .config
+            .stop_cooldown
+            .is_some_and(|cooldown| self.day >= stop_day + cooldown);
+        if target.shares() == 0 || cooled {
+            self.stopped.remove(code);
+            return false;
+        }
+        true
+    }
+
+    /// 开仓时以开仓当天的收盘价作为持仓期间的最高价
+    fn on_filled(&mut self, account: &ST_Account, bar: &StockPriceInfo, hold: usize) {
+        let code = &bar.ts_code;
+        if hold == 0 && account.hold_available(code.clone()) > 0 {
+            if let Some(close) = bar.close {
+                self.positions.insert(
+                    code.clone(),
+                    PositionState {
+                        peak_close: close,
+                        bars: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    /// 按熔断、最大持仓数和行业上限限制买入，返回调整后的目标持股数和原因
+    fn limit_buy(
+        &self,
+        account: &ST_Account,
+        bar: &StockPriceInfo,
+        hold: usize,
+        target: usize,
+    ) -> (usize, Option<String>) {
+        if self.halted() {
+            return (hold, Some("回撤熔断期间不开仓".to_string()));
+        }
+        let code = &bar.ts_code;
+        let hold_codes = account.hold_codes();
+        if let Some(max_positions) = self.config.max_positions {
+            if hold == 0 && hold_codes.len() >= max_positions {
+                return (
+                    hold,
+                    Some(format!(
+                        "已持有 {} 只股票，达到最大持仓数",
+                        hold_codes.len()
+                    )),
+                );
+            }
+        }
+        let (industry_cap, industry, close) = match (
+            self.config.industry_cap,
+            self.industry_map.get(code),
+            bar.close.filter(|close| *close > 0.0),
+        ) {
+            (Some(industry_cap), Some(industry), Some(close)) => (industry_cap, industry, close),
+            _ => return (target, None),
+        };
+        let exposure: f64 = hold_codes
+            .into_iter()
+            .filter(|hold_code| self.industry_map.get(hold_code) == Some(industry))
+            .map(|hold_code| account.hold_value(hold_code))
+            .sum();
+        let room = account.latest_assets() * industry_cap - exposure;
+        let max_buy = (room.max(0.0) / close).floor() as usize;
+        if target - hold <= max_buy {
+            return (target, None);
+        }
+        (
+            hold + max_buy,
+            Some(format!(
+                "{} 行业持仓市值 {:.2} 达到总资产的 {:.2}% 上限",
+                industry,
+                exposure,
+                industry_cap * 100.0
+            )),
+        )
+    }
+
+    /// 按风控规则调整策略给出的目标仓位后下单，每只股票有行情的每根 K 线都要调用一次
+    /// account: 共享的股票账户
+    /// bar: 当天的 K 线
+    /// target: 策略和仓位模型给出的目标仓位
+    /// operate_query: 交易记录
+    /// reject_query: 未能成交的订单
+    pub fn execute(
+        &mut self,
+        account: &mut ST_Account,
+        bar: &StockPriceInfo,
+        target: Option<TargetPosition>,
+        operate_query: &mut Vec<OperateRecord>,
+        reject_query: &mut Vec<OperateRecord>,
+    ) {
+        let code = bar.ts_code.clone();
+        let hold = account.hold_available(code.clone());
+        // 止损优先于策略信号，策略同时给出清仓信号时不再禁止之后的买入
+        if let Some(reason) = self.check_stop(&code, hold, bar.close) {
+            execute_with_reason(
+                account,
+                bar,
+                TargetPosition::Flat,
+                &reason,
+                operate_query,
+                reject_query,
+            );
+            if target.is_none_or(|target| target.shares() > 0) {
+                self.stopped.insert(code, self.day);
+            }
+            return;
+        }
+        let target = match target {
+            Some(target) => target,
+            None => return,
+        };
+        if self.check_stopped(&code, &target) && target.shares() > hold {
+            let reason = "止损后等待策略清仓信号或冷却结束，不再买入".to_string();
+            reject_buy(account, bar, hold, target.shares(), reason, reject_query);
+            return;
+        }
+        if target.shares() <= hold {
+            execute_target(account, bar, target, operate_query, reject_query);
+            return;
+        }
+        match self.limit_buy(account, bar, hold, target.shares()) {
+            (_, None) => {
+                execute_target(account, bar, target, operate_query, reject_query);
+            }
+            (limited, Some(reason)) if limited <= hold => {
+                reject_buy(account, bar, hold, target.shares(), reason, reject_query);
+            }
+            (limited, Some(reason)) => {
+                execute_with_reason(
+                    account,
+                    bar,
+                    TargetPosition::Shares(limited),
+                    &reason,
+                    operate_query,
+                    reject_query,
+                );
+            }
+        }
+        self.on_filled(account, bar, hold);
+    }
+}
+
+/// 记录被风控拦截的买入
+fn reject_buy(
+    account: &ST_Account,
+    bar: &StockPriceInfo,
+    hold: usize,
+    target: usize,
+    reason: String,
+    reject_query: &mut Vec<OperateRecord>,
+) {
+    let close = bar.close.unwrap_or(0.0);
+    reject_query.push(OperateRecord {
+        code: bar.ts_code.clone(),
+        order_type: OrderType::Buy,
+        hold,
+        assets: account.latest_assets(),
+        operate_num: target - hold,
+        close,
+        operate_date: bar.trade_date.clone(),
+        price: close,
+        fee: 0.0,
+        reason: Some(reason),
+    });
+}
+
+/// 按目标仓位下单，并把风控原因写入本次生成的交易记录或未成交订单
+fn execute_with_reason(
+    account: &mut ST_Account,
+    bar: &StockPriceInfo,
+    target: TargetPosition,
+    reason: &str,
+    operate_query: &mut Vec<OperateRecord>,
+    reject_query: &mut Vec<OperateRecord>,
+) {
+    let (operate_len, reject_len) = (operate_query.len(), reject_query.len());
+    execute_target(account, bar, target, operate_query, reject_query);
+    for record in operate_query[operate_len..]
+        .iter_mut()
+        .chain(reject_query[reject_len..].iter_mut())
+    {
+        record.reason = Some(match record.reason.take() {
+            Some(other) => format!("{}；{}", reason, other),
+            None => reason.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::{mock_account, mock_bar};
+
+    const CODE_A: &str = "600000.SH";
+    const CODE_B: &str = "600036.SH";
+
+    /// 回测过程中的账户、风控和交易记录
+    struct Harness {
+        account: ST_Account,
+        risk: RiskManager,
+        operate: Vec<OperateRecord>,
+        reject: Vec<OperateRecord>,
+    }
+
+    impl Harness {
+        fn new(config: RiskConfig) -> Self {
+            let industry_map = HashMap::from([
+                (CODE_A.to_string(), "银行".to_string()),
+                (CODE_B.to_string(), "银行".to_string()),
+            ]);
+            Harness {
+                account: mock_account(&[], 100000.0),
+                risk: RiskManager::new(config, industry_map),
+                operate: vec![],
+                reject: vec![],
+            }
+        }
+
+        /// 进入新的交易日，orders 为每只股票当天的 K 线和目标仓位
+        fn day(&mut self, orders: &[(&StockPriceInfo, Option<TargetPosition>)]) {
+            self.account.new_trading_day();
+            for (bar, _) in orders {
+                self.account
+                    .mark_price(bar.ts_code.clone(), bar.close.unwrap());
+            }
+            let bars: Vec<&StockPriceInfo> = orders.iter().map(|(bar, _)| *bar).collect();
+            self.risk.on_day(
+                &mut self.account,
+                &bars,
+                &mut self.operate,
+                &mut self.reject,
+            );
+            for (bar, target) in orders {
+                self.risk.execute(
+                    &mut self.account,
+                    bar,
+                    *target,
+                    &mut self.operate,
+                    &mut self.reject,
+                );
+            }
+        }
+
+        fn hold(&self, code: &str) -> usize {
+            self.account.hold_available(code.to_string())
+        }
+
+        fn last_reason(list: &[OperateRecord]) -> String {
+            list.last()
+                .and_then(|record| record.reason.clone())
+                .unwrap_or_default()
+        }
+    }
+
+    fn buy(shares: usize) -> Option<TargetPosition> {
+        Some(TargetPosition::Shares(shares))
+    }
+
+    #[test]
+    fn trailing_stop_uses_entry_close() {
+        let mut harness = Harness::new(RiskConfig {
+            trailing_stop: Some(0.1),
+            ..RiskConfig::default()
+        });
+        harness.day(&[(&mock_bar(CODE_A, 0, 10.0), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+        // 开仓当天的收盘价 10 元即为最高价，次日跌到 9 元触发止损
+        harness.day(&[(&mock_bar(CODE_A, 1, 9.0), None)]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        assert!(Harness::last_reason(&harness.operate).contains("移动止损"));
+    }
+
+    #[test]
+    fn trailing_stop_follows_peak() {
+        let mut harness = Harness::new(RiskConfig {
+            trailing_stop: Some(0.1),
+            ..RiskConfig::default()
+        });
+        harness.day(&[(&mock_bar(CODE_A, 0, 10.0), buy(1000))]);
+        harness.day(&[(&mock_bar(CODE_A, 1, 10.5), None)]);
+        harness.day(&[(&mock_bar(CODE_A, 2, 9.5), None)]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+        // 9.4 元低于最高价 10.5 元回撤 10% 的 9.45 元
+        harness.day(&[(&mock_bar(CODE_A, 3, 9.4), None)]);
+        assert_eq!(harness.hold(CODE_A), 0);
+    }
+
+    #[test]
+    fn time_stop_closes_after_bars() {
+        let mut harness = Harness::new(RiskConfig {
+            time_stop: Some(2),
+            ..RiskConfig::default()
+        });
+        harness.day(&[(&mock_bar(CODE_A, 0, 10.0), buy(1000))]);
+        harness.day(&[(&mock_bar(CODE_A, 1, 10.0), None)]);
+        harness.day(&[(&mock_bar(CODE_A, 2, 10.0), None)]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+        harness.day(&[(&mock_bar(CODE_A, 3, 10.0), None)]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        assert!(Harness::last_reason(&harness.operate).contains("时间止损"));
+    }
+
+    #[test]
+    fn no_reentry_until_strategy_is_flat() {
+        let mut harness = Harness::new(RiskConfig {
+            trailing_stop: Some(0.1),
+            ..RiskConfig::default()
+        });
+        harness.day(&[(&mock_bar(CODE_A, 0, 10.0), buy(1000))]);
+        harness.day(&[(&mock_bar(CODE_A, 1, 8.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        // 策略仍认为持仓，要求加仓到目标股数
+        harness.day(&[(&mock_bar(CODE_A, 2, 8.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        assert!(Harness::last_reason(&harness.reject).contains("止损后"));
+        // 策略给出清仓信号后可以再次买入
+        harness.day(&[(&mock_bar(CODE_A, 3, 8.5), Some(TargetPosition::Flat))]);
+        harness.day(&[(&mock_bar(CODE_A, 4, 8.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+    }
+
+    #[test]
+    fn stop_cooldown_allows_reentry() {
+        let mut harness = Harness::new(RiskConfig {
+            trailing_stop: Some(0.1),
+            stop_cooldown: Some(2),
+            ..RiskConfig::default()
+        });
+        harness.day(&[(&mock_bar(CODE_A, 0, 10.0), buy(1000))]);
+        harness.day(&[(&mock_bar(CODE_A, 1, 8.5), None)]);
+        harness.day(&[(&mock_bar(CODE_A, 2, 8.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        harness.day(&[(&mock_bar(CODE_A, 3, 8.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+    }
+
+    #[test]
+    fn drawdown_breaker_halts_buying() {
+        let mut harness = Harness::new(RiskConfig {
+            max_drawdown: Some(0.2),
+            cooldown: 2,
+            ..RiskConfig::default()
+        });
+        harness.day(&[(&mock_bar(CODE_A, 0, 10.0), buy(9000))]);
+        // 总资产约 10000 + 9000 * 7.5 = 77500，回撤超过 20%
+        harness.day(&[(&mock_bar(CODE_A, 1, 7.5), None)]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        assert!(Harness::last_reason(&harness.operate).contains("熔断"));
+        harness.day(&[(&mock_bar(CODE_A, 2, 7.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 0);
+        assert!(Harness::last_reason(&harness.reject).contains("熔断"));
+        // 冷却结束后可以开仓
+        harness.day(&[(&mock_bar(CODE_A, 3, 7.5), buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+    }
+
+    #[test]
+    fn max_positions_blocks_new_codes() {
+        let mut harness = Harness::new(RiskConfig {
+            max_positions: Some(1),
+            ..RiskConfig::default()
+        });
+        let (bar_a, bar_b) = (mock_bar(CODE_A, 0, 10.0), mock_bar(CODE_B, 0, 10.0));
+        harness.day(&[(&bar_a, buy(1000)), (&bar_b, buy(1000))]);
+        assert_eq!(harness.hold(CODE_A), 1000);
+        assert_eq!(harness.hold(CODE_B), 0);
+        assert!(Harness::last_reason(&harness.reject).contains("最大持仓数"));
+        // 已持有的股票仍可加仓
+        let bar_a = mock_bar(CODE_A, 1, 10.0);
+        harness.day(&[(&bar_a, buy(2000))]);
+        assert_eq!(harness.hold(CODE_A), 2000);
+    }
+
+    #[test]
+    fn industry_cap_limits_exposure() {
+        let mut harness = Harness::new(RiskConfig {
+            industry_cap: Some(0.3),
+            ..RiskConfig::default()
+        });
+        let (bar_a, bar_b) = (mock_bar(CODE_A, 0, 10.0), mock_bar(CODE_B, 0, 10.0));
+        // 同一行业上限 30000 元，A 买入 20000 元后 B 只能买入 1000 股
+        harness.day(&[(&bar_a, buy(2000)), (&bar_b, buy(2000))]);
+        assert_eq!(harness.hold(CODE_A), 2000);
+        assert_eq!(harness.hold(CODE_B), 1000);
+        assert!(Harness::last_reason(&harness.operate).contains("行业"));
+        // 达到上限后不再买入
+        let bar_b = mock_bar(CODE_B, 1, 10.0);
+        harness.day(&[(&bar_b, buy(2000))]);
+        assert_eq!(harness.hold(CODE_B), 1000);
+        assert!(Harness::last_reason(&harness.reject).contains("行业"));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::db::schema::{rps_values, stock_daily_info, stock_info_list};
+use crate::AppErrorEnum;
+use chrono::{Duration, NaiveDate};
+use diesel::{ExpressionMethods, QueryDsl};
+use rocket::serde::Serialize;
+use rocket_db_pools::diesel::{AsyncMysqlConnection, RunQueryDsl};
+use std::collections::{HashMap, HashSet};
+
+/*
+* 基于 rps_values 的选股条件
+*   1. 强度持续：最近 K 个有 RPS 数据的交易日 RPS 均不低于 X
+*   2. 强度突破：当天 RPS 不低于 X，而上一个有 RPS 数据的交易日低于 X（或不在榜单中）
+*   3. 强度新高：当天 RPS 不低于之前 N 个有 RPS 数据的交易日的最高值，
+*      同时收盘价距离 52 周最高价不超过 P%
+*
+* rps_values 只保存了排名靠前的股票，不在榜单中的股票视为低于阈值
+*/
+
+// 定义一个通用的 Result 类型，默认错误类型为 AppErrorEnum
+type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
+
+#[derive(Debug, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct RpsScreenItem {
+    ts_code: String,
+    name: Option<String>,
+    rps: Option<f64>,
+    increase: Option<f64>,
+}
+
+/// 获取截止日期当天或之前最近 count 个有 RPS 数据的日期，按日期倒序
+async fn get_rps_dates(
+    conn: &mut AsyncMysqlConnection,
+    date: &str,
+    count: usize,
+) -> Result<Vec<String>> {
+    let date_list = rps_values::table
+        .select(rps_values::trade_date)
+        .filter(rps_values::trade_date.le(date.to_string()))
+        .distinct()
+        .order(rps_values::trade_date.desc())
+        .limit(count as i64)
+        .load::<Option<String>>(conn)
+        .await?;
+    Ok(date_list.into_iter().flatten().collect())
+}
+
+/// 获取某一天 RPS 不低于 threshold 的股票，按 RPS 倒序
+async fn get_rps_above(
+    conn: &mut AsyncMysqlConnection,
+    date: &str,
+    threshold: f64,
+) -> Result<Vec<RpsScreenItem>> {
+    Ok(stock_info_list::table
+        .inner_join(rps_values::table)
+        .filter(rps_values::trade_date.eq(date.to_string()))
+        .filter(rps_values::rps.ge(threshold))
+        .select((
+            rps_values::ts_code,
+            stock_info_list::name,
+            rps_values::rps,
+            rps_values::increase,
+        ))
+        .order(rps_values::rps.desc())
+        .load(conn)
+        .await?)
+}
+
+/// 获取若干天内每只股票的 RPS，(股票代码, 日期) -> rps
+async fn get_rps_map(
+    conn: &mut AsyncMysqlConnection,
+    date_list: &[String],
+) -> Result<HashMap<(String, String), f64>> {
+    let rps_list = rps_values::table
+        .filter(rps_values::trade_date.eq_any(date_list.to_vec()))
+        .select((rps_values::ts_code, rps_values::trade_date, rps_values::rps))
+        .load::<(String, Option<String>, Option<f64>)>(conn)
+        .await?;
+    Ok(rps_list
+        .into_iter()
+        .filter_map(|(ts_code, trade_date, rps)| Some(((ts_code, trade_date?), rps?)))
+        .collect())
+}
+
+/// RPS 连续 days 个交易日不低于 threshold
+/// date：截止日期
+/// threshold：RPS 阈值
+/// days：连续的交易日数量
+pub async fn screen_rps_persist(
+    conn: &mut AsyncMysqlConnection,
+    date: String,
+    threshold: f64,
+    days: usize,
+) -> Result<Vec<RpsScreenItem>> {
+    let date_list = get_rps_dates(conn, &date, days.max(1)).await?;
+    if date_list.len() < days.max(1) || date_list[0] != date {
+        return Ok(vec![]);
+    }
+    let rps_map = get_rps_map(conn, &date_list).await?;
+    let today_list = get_rps_above(conn, &date_list[0], threshold).await?;
+    Ok(today_list
+        .into_iter()
+        .filter(|stock| {
+            date_list.iter().all(|trade_date| {
+                rps_map
+                    .get(&(stock.ts_code.clone(), trade_date.to_string()))
+                    .is_some_and(|rps| *rps >= threshold)
+            })
+        })
+        .collect())
+}
+
+/// RPS 当天向上突破 threshold
+/// date：日期
+/// threshold：RPS 阈值
+pub async fn screen_rps_cross(
+    conn: &mut AsyncMysqlConnection,
+    date: String,
+    threshold: f64,
+) -> Result<Vec<RpsScreenItem>> {
+    let date_list = get_rps_dates(conn, &date, 2).await?;
+    if date_list.is_empty() || date_list[0] != date {
+        return Ok(vec![]);
+    }
+    let rps_map = get_rps_map(conn, &date_list).await?;
+    let today_list = get_rps_above(conn, &date, threshold).await?;
+    Ok(today_list
+        .into_iter()
+        .filter(|stock| match date_list.get(1) {
+            Some(prev_date) => rps_map
+                .get(&(stock.ts_code.clone(), prev_date.to_string()))
+                .is_none_or(|rps| *rps < threshold),
+            None => true,
+        })
+        .collect())
+}
+
+/// RPS 创 days 日新高，且收盘价距离 52 周最高价不超过 within 个百分点
+/// date：日期
+/// days：RPS 新高的回看天数
+/// within：距离 52 周最高价的百分比
+pub async fn screen_rps_new_high(
+    conn: &mut AsyncMysqlConnection,
+    date: String,
+    days: usize,
+    within: f64,
+) -> Result<Vec<RpsScreenItem>> {
+    let date_list = get_rps_dates(conn, &date, days + 1).await?;
+    if date_list.is_empty() || date_list[0] != date {
+        return Ok(vec![]);
+    }
+    let rps_map = get_rps_map(conn, &date_list).await?;
+    // 当天在榜的股票即为候选
+    let today_list = get_rps_above(conn, &date, 0.0).await?;
+    let rps_high_list: Vec<RpsScreenItem> = today_list
+        .into_iter()
+        .filter(|stock| {
+            let today_rps = stock.rps.unwrap_or(0.0);
+            date_list[1..].iter().all(|trade_date| {
+                rps_map
+                    .get(&(stock.ts_code.clone(), trade_date.to_string()))
+                    .is_none_or(|rps| *rps <= today_rps)
+            })
+        })
+        .collect();
+    if rps_high_list.is_empty() {
+        return Ok(vec![]);
+    }
+    // 52 周内的最高价和截止日期的收盘价
+    let format = "%Y%m%d";
+    let start_date = match NaiveDate::parse_from_str(&date, format) {
+        Ok(cur_date) => (cur_date - Duration::weeks(52)).format(format).to_string(),
+        Err(_) => return Ok(vec![]),
+    };
+    let code_list: Vec<String> = rps_high_list
+        .iter()
+        .map(|stock| stock.ts_code.clone())
+        .collect();
+    let price_list = stock_daily_info::table
+        .filter(stock_daily_info::ts_code.eq_any(code_list))
+        .filter(stock_daily_info::trade_date.ge(start_date))
+        .filter(stock_daily_info::trade_date.le(date))
+        .order((
+            stock_daily_info::ts_code.asc(),
+            stock_daily_info::trade_date.asc(),
+        ))
+        .select((
+            stock_daily_info::ts_code,
+            stock_daily_info::close,
+            stock_daily_info::high,
+        ))
+        .load::<(String, Option<f64>, Option<f64>)>(conn)
+        .await?;
+    // 股票代码 -> (最新收盘价, 52 周最高价)
+    let mut price_map: HashMap<String, (f64, f64)> = HashMap::new();
+    for (ts_code, close, high) in price_list {
+        let price = price_map.entry(ts_code).or_insert((0.0, 0.0));
+        if let Some(close) = close {
+            price.0 = close;
+        }
+        price.1 = price.1.max(high.unwrap_or(0.0));
+    }
+    let near_high: HashSet<String> = price_map
+        .into_iter()
+        .filter(|(_, (close, high))| *high > 0.0 && *close >= high * (1.0 - within / 100.0))
+        .map(|(ts_code, _)| ts_code)
+        .collect();
+    Ok(rps_high_list
+        .into_iter()
+        .filter(|stock| near_high.contains(&stock.ts_code))
+        .collect())
+}
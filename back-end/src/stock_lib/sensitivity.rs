@@ -0,0 +1,320 @@
+use crate::stock_lib::optimizer::ParamScore;
+use crate::stock_lib::strategy::{ParamSpec, StrategyParams};
+use rayon::prelude::*;
+use rocket::serde::Serialize;
+
+/*
+* 参数敏感性分析
+*   参数搜索回测过的全部参数组合构成参数云，用它分析目标得分对参数的敏感程度，避免选中孤立的尖峰
+*   1. 邻域：每个参数按搜索范围归一化到 [0, 1]，与某组参数每个维度的距离都不超过 NEIGHBOUR_RADIUS 的参数组合
+*   2. 邻域得分：邻域内全部参数组合（含自身）得分的均值、标准差和最小值，均值接近最优得分说明最优参数附近是平台而不是尖峰
+*   3. 百分位：最优参数邻域均值在参数云全部得分中的百分位排名
+*   4. 稳健参数：邻域均值最高的一组参数，可以替代单点最优的参数
+*      每个候选参数都要遍历整个参数云，只在得分最高的 MAX_ROBUST_CANDIDATES 组参数中选，避免参数云很大时耗时平方增长
+*   5. 得分曲线和曲面：把参数按取值分组，统计每组的平均得分，供前端画单参数曲线和两两参数的热力图（如 N1 × N2）
+*/
+
+/// 邻域半径，占参数搜索范围的比例
+const NEIGHBOUR_RADIUS: f64 = 0.1;
+/// 画曲线和热力图时每个参数最多分成的组数
+const MAX_BINS: usize = 20;
+/// 选稳健参数时最多计算邻域的参数组数
+const MAX_ROBUST_CANDIDATES: usize = 1000;
+
+/// 单个参数的得分曲线
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ParamMarginal {
+    pub name: String,             // 参数名
+    pub values: Vec<f64>,         // 每组的参数取值（组中点）
+    pub scores: Vec<Option<f64>>, // 每组的平均得分，没有回测过的组为空
+}
+
+/// 两个参数的得分曲面
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ParamSurface {
+    pub x: String,                     // 横轴参数名
+    pub y: String,                     // 纵轴参数名
+    pub x_values: Vec<f64>,            // 横轴每组的参数取值
+    pub y_values: Vec<f64>,            // 纵轴每组的参数取值
+    pub scores: Vec<Vec<Option<f64>>>, // scores[y][x]：每个格子的平均得分
+}
+
+/// 最优参数附近的稳健性报告
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RobustnessReport {
+    pub best_score: f64,               // 最优参数的得分
+    pub neighbour_count: usize,        // 最优参数邻域内的参数组数（含自身）
+    pub neighbour_mean: f64,           // 邻域平均得分
+    pub neighbour_std: f64,            // 邻域得分标准差
+    pub neighbour_min: f64,            // 邻域最低得分
+    pub percentile_rank: f64,          // 邻域平均得分在参数云中的百分位（0 ~ 100）
+    pub robust_param: StrategyParams,  // 邻域平均得分最高的参数
+    pub robust_score: f64,             // 稳健参数的邻域平均得分
+    pub marginals: Vec<ParamMarginal>, // 单参数得分曲线
+    pub surfaces: Vec<ParamSurface>,   // 两两参数的得分曲面
+}
+
+/// 参数的分组方式
+struct Bins {
+    min: f64,
+    width: f64,
+    count: usize,
+    integer: bool,
+}
+
+impl Bins {
+    /// 整数参数取值不多时每个取值一组，否则等分为 MAX_BINS 组
+    fn new(spec: &ParamSpec) -> Self {
+        let range = spec.max - spec.min;
+        if spec.integer && range <= MAX_BINS as f64 {
+            Bins {
+                min: spec.min,
+                width: 1.0,
+                count: (range.max(1.0)) as usize,
+                integer: true,
+            }
+        } else {
+            Bins {
+                min: spec.min,
+                width: range.max(f64::EPSILON) / MAX_BINS as f64,
+                count: MAX_BINS,
+                integer: false,
+            }
+        }
+    }
+    /// 参数值所在的组
+    fn index(&self, value: f64) -> usize {
+        (((value - self.min) / self.width).floor().max(0.0) as usize).min(self.count - 1)
+    }
+    /// 每组的参数取值
+    fn values(&self) -> Vec<f64> {
+        (0..self.count)
+            .map(|index| {
+                if self.integer {
+                    self.min + index as f64
+                } else {
+                    self.min + (index as f64 + 0.5) * self.width
+                }
+            })
+            .collect()
+    }
+}
+
+/// 计算均值和标准差
+fn col_mean_std(list: &[f64]) -> (f64, f64) {
+    let len = list.len().max(1) as f64;
+    let mean = list.iter().sum::<f64>() / len;
+    let var = list.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / len;
+    (mean, var.sqrt())
+}
+
+/// 每组平均得分
+fn col_bin_mean(sum: &[f64], count: &[usize]) -> Vec<Option<f64>> {
+    sum.iter()
+        .zip(count.iter())
+        .map(|(sum, count)| {
+            if *count > 0 {
+                Some(sum / *count as f64)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// 计算参数云的稳健性报告，参数云为空时返回 None
+/// cloud: 参数搜索回测过的全部参数及得分
+/// best: 最优参数
+/// param_space: 参与搜索的参数
+pub fn col_robustness(
+    cloud: &[ParamScore],
+    best: &StrategyParams,
+    param_space: &[ParamSpec],
+) -> Option<RobustnessReport> {
+    if cloud.is_empty() {
+        return None;
+    }
+    // 只有范围大于 0 的参数才参与邻域和曲面计算
+    let specs: Vec<&ParamSpec> = param_space
+        .iter()
+        .filter(|spec| spec.max > spec.min)
+        .collect();
+    let normalize = |params: &StrategyParams| -> Vec<f64> {
+        specs
+            .iter()
+            .map(|spec| {
+                let value = params.get(&spec.name).copied().unwrap_or(spec.default);
+                (value - spec.min) / (spec.max - spec.min)
+            })
+            .collect()
+    };
+    let points: Vec<Vec<f64>> = cloud.iter().map(|point| normalize(&point.params)).collect();
+    let neighbour_scores = |center: &[f64]| -> Vec<f64> {
+        points
+            .iter()
+            .zip(cloud.iter())
+            .filter(|(point, _)| {
+                point
+                    .iter()
+                    .zip(center.iter())
+                    .all(|(a, b)| (a - b).abs() <= NEIGHBOUR_RADIUS)
+            })
+            .map(|(_, point)| point.score)
+            .collect()
+    };
+    // 最优参数的邻域
+    let best_center = normalize(best);
+    let best_neighbours = neighbour_scores(&best_center);
+    let best_score = cloud
+        .iter()
+        .find(|point| point.params == *best)
+        .map(|point| point.score)
+        .unwrap_or(f64::NAN);
+    let (neighbour_mean, neighbour_std) = col_mean_std(&best_neighbours);
+    let neighbour_min = best_neighbours
+        .iter()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let percentile_rank = cloud
+        .iter()
+        .filter(|point| point.score <= neighbour_mean)
+        .count() as f64
+        / cloud.len() as f64
+        * 100.0;
+    // 稳健参数：在得分最高的候选参数中选邻域平均得分最高的，得分相同时取先回测的参数
+    let mut candidate_list: Vec<usize> = (0..cloud.len()).collect();
+    candidate_list.sort_by(|a, b| {
+        cloud[*b]
+            .score
+            .partial_cmp(&cloud[*a].score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    candidate_list.truncate(MAX_ROBUST_CANDIDATES);
+    candidate_list.sort();
+    let robust_list: Vec<(usize, f64)> = candidate_list
+        .par_iter()
+        .map(|index| (*index, col_mean_std(&neighbour_scores(&points[*index])).0))
+        .collect();
+    let (robust_index, robust_score) =
+        robust_list
+            .iter()
+            .fold(robust_list[0], |best, (index, score)| {
+                if *score > best.1 {
+                    (*index, *score)
+                } else {
+                    best
+                }
+            });
+    // 单参数曲线和两两参数曲面
+    let bins: Vec<Bins> = specs.iter().map(|spec| Bins::new(spec)).collect();
+    let bin_index: Vec<Vec<usize>> = cloud
+        .iter()
+        .map(|point| {
+            specs
+                .iter()
+                .zip(bins.iter())
+                .map(|(spec, bins)| {
+                    bins.index(
+                        point
+                            .params
+                            .get(&spec.name)
+                            .copied()
+                            .unwrap_or(spec.default),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let marginals = specs
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            let mut sum = vec![0.0; bins[i].count];
+            let mut count = vec![0; bins[i].count];
+            for (index, point) in bin_index.iter().zip(cloud.iter()) {
+                sum[index[i]] += point.score;
+                count[index[i]] += 1;
+            }
+            ParamMarginal {
+                name: spec.name.clone(),
+                values: bins[i].values(),
+                scores: col_bin_mean(&sum, &count),
+            }
+        })
+        .collect();
+    let mut surfaces = vec![];
+    for i in 0..specs.len() {
+        for j in i + 1..specs.len() {
+            let (x_count, y_count) = (bins[i].count, bins[j].count);
+            let mut sum = vec![0.0; x_count * y_count];
+            let mut count = vec![0; x_count * y_count];
+            for (index, point) in bin_index.iter().zip(cloud.iter()) {
+                sum[index[j] * x_count + index[i]] += point.score;
+                count[index[j] * x_count + index[i]] += 1;
+            }
+            surfaces.push(ParamSurface {
+                x: specs[i].name.clone(),
+                y: specs[j].name.clone(),
+                x_values: bins[i].values(),
+                y_values: bins[j].values(),
+                scores: col_bin_mean(&sum, &count)
+                    .chunks(x_count)
+                    .map(|row| row.to_vec())
+                    .collect(),
+            });
+        }
+    }
+    Some(RobustnessReport {
+        best_score,
+        neighbour_count: best_neighbours.len(),
+        neighbour_mean,
+        neighbour_std,
+        neighbour_min,
+        percentile_rank,
+        robust_param: cloud[robust_index].params.clone(),
+        robust_score,
+        marginals,
+        surfaces,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::metrics::BacktestMetrics;
+
+    fn point(n: f64, score: f64) -> ParamScore {
+        ParamScore {
+            params: StrategyParams::from([("n".to_string(), n)]),
+            score,
+            metrics: BacktestMetrics::default(),
+        }
+    }
+
+    #[test]
+    fn robust_param_prefers_plateau_over_spike() {
+        let space = vec![ParamSpec::new("n", 0.0, 100.0, true, 0.0)];
+        // n = 10 是孤立的尖峰，n 在 60 ~ 80 之间是得分稍低的平台，其余参数得分为 0
+        let mut cloud: Vec<ParamScore> = (0..100)
+            .map(|n| {
+                let score = match n {
+                    10 => 10.0,
+                    60..=80 => 5.0,
+                    _ => 0.0,
+                };
+                point(n as f64, score)
+            })
+            .collect();
+        // 大量低分参数不会进入候选，也不影响结果
+        cloud.extend((0..MAX_ROBUST_CANDIDATES).map(|_| point(0.0, -1.0)));
+        let best = cloud[10].params.clone();
+        let report = col_robustness(&cloud, &best, &space).unwrap();
+        assert_eq!(report.best_score, 10.0);
+        assert_eq!(report.robust_param["n"], 70.0);
+        assert_eq!(report.robust_score, 5.0);
+        assert!(report.neighbour_mean < 1.0);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::stock_trade::TradeRules;
+use crate::stock_lib::strategy::turtle::col_atr;
+use crate::stock_lib::strategy::{BarContext, Column, IndicatorCache, TargetPosition};
+use rocket::serde::{Deserialize, Serialize};
+
+/*
+* 仓位管理
+*   策略只决定开仓、加减仓和清仓的时机，目标持股数不为 0 时由仓位模型重新计算，每次回测可以单独选择
+*   1. 策略自身：沿用策略给出的股数，如海龟策略的 1% 资产 / ATR
+*   2. 固定股数：每次持有 shares 股
+*   3. 固定金额：持有 amount 元对应的股数
+*   4. 资产比例：持有总资产 * percent 对应的股数
+*   5. 波动率目标：仓位比例 = 目标年化波动率 / 最近 period 天的年化波动率
+*   6. ATR 风险：股数 = 总资产 * risk / ATR，即价格波动一个 ATR 时亏损 risk 比例的资产
+*   7. 凯利公式：仓位比例 = fraction * (胜率 - (1 - 胜率) / 盈亏比)，胜率和盈亏比按本次回测已平仓的交易统计，
+*      平仓次数不足 min_trades 时沿用策略给出的股数；每笔交易的收益按开仓和平仓当天的收盘价估算
+*   所有模型的持仓市值都不超过总资产 * max_weight，目标持股数按交易单位向下取整
+*/
+
+/// 仓位模型
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum SizerKind {
+    #[default]
+    Strategy, // 沿用策略给出的股数
+    FixedShares {
+        shares: usize, // 持有股数
+    },
+    FixedCash {
+        amount: f64, // 持有金额
+    },
+    PercentEquity {
+        percent: f64, // 持仓市值占总资产的比例
+    },
+    VolatilityTarget {
+        target: f64,   // 目标年化波动率，如 0.15
+        period: usize, // 计算波动率的天数
+    },
+    AtrRisk {
+        risk: f64,     // 价格波动一个 ATR 时亏损的资产比例，如 0.01
+        period: usize, // ATR 周期
+    },
+    Kelly {
+        fraction: f64,     // 凯利比例的折扣，如 0.5 为半凯利
+        min_trades: usize, // 开始使用凯利公式所需的最少平仓次数
+    },
+}
+
+/// 仓位模型设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct SizerConfig {
+    pub method: SizerKind, // 仓位模型，默认沿用策略给出的股数
+    pub max_weight: f64,   // 单只股票持仓市值占总资产的上限，默认 1.0
+}
+
+impl Default for SizerConfig {
+    fn default() -> Self {
+        SizerConfig {
+            method: SizerKind::Strategy,
+            max_weight: 1.0,
+        }
+    }
+}
+
+/// 计算年化波动率：最近 period 天日收益率的标准差 * sqrt(252)，数据不足时为空
+pub fn col_volatility(df_stock: &[StockPriceInfo], period: usize) -> Vec<Option<f64>> {
+    let returns: Vec<Option<f64>> = df_stock
+        .iter()
+        .enumerate()
+        .map(|(index, stock)| {
+            let close = stock.close?;
+            let pre_close = df_stock.get(index.checked_sub(1)?)?.close?;
+            if pre_close > 0.0 {
+                Some(close / pre_close - 1.0)
+            } else {
+                None
+            }
+        })
+        .collect();
+    (0..df_stock.len())
+        .map(|index| {
+            if period < 2 || index < period {
+                return None;
+            }
+            let window: Vec<f64> = returns[index + 1 - period..=index]
+                .iter()
+                .flatten()
+                .copied()
+                .collect();
+            if window.len() < 2 {
+                return None;
+            }
+            let mean = window.iter().sum::<f64>() / window.len() as f64;
+            let var =
+                window.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (window.len() - 1) as f64;
+            Some(var.sqrt() * 252f64.sqrt())
+        })
+        .collect()
+}
+
+/// 单只股票的仓位模型，每次回测新建一个
+pub struct PositionSizer {
+    config: SizerConfig,
+    rules: TradeRules,       // 交易规则，用于按交易单位取整
+    column: Option<Column>,  // 波动率或 ATR，其他模型为空
+    last_hold: usize,        // 上一根 K 线的持股数
+    last_close: f64,         // 上一根 K 线的收盘价
+    entry_price: f64,        // 当前持仓的开仓价
+    trade_returns: Vec<f64>, // 已平仓交易的收益率
+}
+
+impl PositionSizer {
+    /// 创建仓位模型，用到的指标从缓存中读取
+    /// config: 仓位模型设置
+    /// rules: 交易规则
+    /// df_stock: 回测用的全部 K 线
+    /// cache: 指标缓存，与策略共用
+    pub fn new(
+        config: SizerConfig,
+        rules: TradeRules,
+        df_stock: &[StockPriceInfo],
+        cache: &IndicatorCache,
+    ) -> Self {
+        let column = match config.method {
+            SizerKind::VolatilityTarget { period, .. } => {
+                Some(cache.get_or_insert(format!("volatility_{}", period), || {
+                    col_volatility(df_stock, period)
+                }))
+            }
+            SizerKind::AtrRisk { period, .. } => {
+                Some(cache.get_or_insert(format!("atr_{}", period), || col_atr(df_stock, period)))
+            }
+            _ => None,
+        };
+        PositionSizer {
+            config,
+            rules,
+            column,
+            last_hold: 0,
+            last_close: 0.0,
+            entry_price: 0.0,
+            trade_returns: vec![],
+        }
+    }
+
+    /// 记录开仓和平仓，用于统计凯利公式的胜率和盈亏比
+    fn observe(&mut self, ctx: &BarContext) {
+        if self.last_hold == 0 && ctx.hold > 0 {
+            self.entry_price = self.last_close;
+        } else if self.last_hold > 0 && ctx.hold == 0 && self.entry_price > 0.0 {
+            self.trade_returns
+                .push(self.last_close / self.entry_price - 1.0);
+        }
+        self.last_hold = ctx.hold;
+        self.last_close = ctx.bar.close.unwrap_or(self.last_close);
+    }
+
+    /// 按已平仓交易计算凯利仓位比例，平仓次数不足时返回 None
+    fn kelly_weight(&self, fraction: f64, min_trades: usize) -> Option<f64> {
+        if self.trade_returns.is_empty() || self.trade_returns.len() < min_trades {
+            return None;
+        }
+        let wins: Vec<f64> = self
+            .trade_returns
+            .iter()
+            .copied()
+            .filter(|r| *r > 0.0)
+            .collect();
+        let losses: Vec<f64> = self
+            .trade_returns
+            .iter()
+            .copied()
+            .filter(|r| *r <= 0.0)
+            .collect();
+        let win_rate = wins.len() as f64 / self.trade_returns.len() as f64;
+        let kelly = if wins.is_empty() {
+            0.0
+        } else if losses.is_empty() {
+            1.0
+        } else {
+            let avg_win = wins.iter().sum::<f64>() / wins.len() as f64;
+            let avg_loss = -losses.iter().sum::<f64>() / losses.len() as f64;
+            if avg_loss > 0.0 {
+                win_rate - (1.0 - win_rate) / (avg_win / avg_loss)
+            } else {
+                win_rate
+            }
+        };
+        Some((kelly * fraction).max(0.0))
+    }
+
+    /// 按仓位模型重新计算策略给出的目标仓位，每根 K 线都要调用一次
+    /// ctx: 策略看到的行情和账户状态
+    /// target: 策略给出的目标仓位
+    pub fn size(
+        &mut self,
+        ctx: &BarContext,
+        target: Option<TargetPosition>,
+    ) -> Option<TargetPosition> {
+        self.observe(ctx);
+        let shares = match target? {
+            TargetPosition::Shares(shares) if shares > 0 => shares,
+            target => return Some(target),
+        };
+        let close = ctx.bar.close.filter(|close| *close > 0.0)?;
+        let assets = ctx.assets.max(0.0);
+        let column_value = self
+            .column
+            .as_ref()
+            .and_then(|column| column.get(ctx.index).copied().flatten())
+            .filter(|value| *value > 0.0);
+        let sized = match self.config.method {
+            SizerKind::Strategy => shares as f64,
+            SizerKind::FixedShares { shares } => shares as f64,
+            SizerKind::FixedCash { amount } => amount / close,
+            SizerKind::PercentEquity { percent } => assets * percent / close,
+            // 没有波动率或 ATR 的 K 线维持当前仓位
+            SizerKind::VolatilityTarget { target, .. } => assets * target / column_value? / close,
+            SizerKind::AtrRisk { risk, .. } => assets * risk / column_value?,
+            SizerKind::Kelly {
+                fraction,
+                min_trades,
+            } => match self.kelly_weight(fraction, min_trades) {
+                Some(weight) => assets * weight / close,
+                None => shares as f64,
+            },
+        };
+        let max_shares = assets * self.config.max_weight.max(0.0) / close;
+        let shares = sized.min(max_shares).max(0.0).floor() as usize;
+        Some(TargetPosition::Shares(
+            self.rules.round_buy(&ctx.bar.ts_code, shares),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::{mock_bar, mock_series};
+
+    const CODE: &str = "600000.SH";
+
+    fn sizer(method: SizerKind, max_weight: f64, df_stock: &[StockPriceInfo]) -> PositionSizer {
+        PositionSizer::new(
+            SizerConfig { method, max_weight },
+            TradeRules::default(),
+            df_stock,
+            &IndicatorCache::default(),
+        )
+    }
+
+    /// 总资产 assets、空仓时在第 index 根 K 线上的目标持股数
+    fn size_at(
+        sizer: &mut PositionSizer,
+        bar: &StockPriceInfo,
+        index: usize,
+        assets: f64,
+        target: Option<TargetPosition>,
+    ) -> Option<TargetPosition> {
+        let ctx = BarContext {
+            index,
+            bar,
+            hold: 0,
+            cash: assets,
+            assets,
+        };
+        sizer.size(&ctx, target)
+    }
+
+    fn size_once(method: SizerKind, max_weight: f64, bar: &StockPriceInfo, assets: f64) -> usize {
+        let mut sizer = sizer(method, max_weight, std::slice::from_ref(bar));
+        size_at(
+            &mut sizer,
+            bar,
+            0,
+            assets,
+            Some(TargetPosition::Shares(100)),
+        )
+        .unwrap()
+        .shares()
+    }
+
+    #[test]
+    fn max_weight_caps_position() {
+        let bar = mock_bar(CODE, 0, 10.0);
+        let percent = SizerKind::PercentEquity { percent: 0.8 };
+        assert_eq!(size_once(percent.clone(), 1.0, &bar, 100000.0), 8000);
+        assert_eq!(size_once(percent, 0.3, &bar, 100000.0), 3000);
+        let fixed = SizerKind::FixedShares { shares: 50000 };
+        assert_eq!(size_once(fixed, 0.5, &bar, 100000.0), 5000);
+    }
+
+    #[test]
+    fn result_is_whole_lots() {
+        let bar = mock_bar(CODE, 0, 10.0);
+        let fixed_cash = SizerKind::FixedCash { amount: 15050.0 };
+        assert_eq!(size_once(fixed_cash, 1.0, &bar, 100000.0), 1500);
+        // 策略给出的股数也按整手取整
+        let mut strategy = sizer(SizerKind::Strategy, 1.0, std::slice::from_ref(&bar));
+        let target = size_at(
+            &mut strategy,
+            &bar,
+            0,
+            100000.0,
+            Some(TargetPosition::Shares(1234)),
+        );
+        assert_eq!(target, Some(TargetPosition::Shares(1200)));
+        // 科创板不足 200 股时为 0
+        let star_bar = mock_bar("688001.SH", 0, 10.0);
+        let fixed_cash = SizerKind::FixedCash { amount: 1500.0 };
+        assert_eq!(size_once(fixed_cash.clone(), 1.0, &star_bar, 100000.0), 0);
+        let fixed_cash = SizerKind::FixedCash { amount: 2505.0 };
+        assert_eq!(size_once(fixed_cash, 1.0, &star_bar, 100000.0), 250);
+    }
+
+    #[test]
+    fn result_is_never_negative() {
+        let bar = mock_bar(CODE, 0, 10.0);
+        let percent = SizerKind::PercentEquity { percent: 0.5 };
+        assert_eq!(size_once(percent, 1.0, &bar, -5000.0), 0);
+        let percent = SizerKind::PercentEquity { percent: -0.5 };
+        assert_eq!(size_once(percent, 1.0, &bar, 100000.0), 0);
+        let fixed_cash = SizerKind::FixedCash { amount: 10000.0 };
+        assert_eq!(size_once(fixed_cash, -1.0, &bar, 100000.0), 0);
+    }
+
+    #[test]
+    fn flat_and_none_pass_through() {
+        let bar = mock_bar(CODE, 0, 10.0);
+        let mut sizer = sizer(
+            SizerKind::PercentEquity { percent: 0.5 },
+            1.0,
+            std::slice::from_ref(&bar),
+        );
+        let flat = size_at(&mut sizer, &bar, 0, 1000.0, Some(TargetPosition::Flat));
+        assert_eq!(flat, Some(TargetPosition::Flat));
+        assert_eq!(size_at(&mut sizer, &bar, 0, 1000.0, None), None);
+    }
+
+    #[test]
+    fn atr_risk_sizes_by_atr() {
+        // 收盘价不变，每天的真实波幅都是 10 * 4% = 0.4 元
+        let df_stock = mock_series(CODE, &[10.0; 30]);
+        let method = SizerKind::AtrRisk {
+            risk: 0.01,
+            period: 14,
+        };
+        let mut sizer = sizer(method, 1.0, &df_stock);
+        // 100000 * 1% / 0.4 = 2500 股
+        let target = size_at(
+            &mut sizer,
+            &df_stock[20],
+            20,
+            100000.0,
+            Some(TargetPosition::Shares(100)),
+        );
+        assert_eq!(target, Some(TargetPosition::Shares(2500)));
+    }
+
+    #[test]
+    fn volatility_target_scales_by_volatility() {
+        let close_list: Vec<f64> = (0..30)
+            .map(|day| if day % 2 == 0 { 10.0 } else { 10.2 })
+            .collect();
+        let df_stock = mock_series(CODE, &close_list);
+        let volatility = col_volatility(&df_stock, 10)[20].unwrap();
+        let method = SizerKind::VolatilityTarget {
+            target: 0.15,
+            period: 10,
+        };
+        let mut sizer = sizer(method, 1.0, &df_stock);
+        let target = size_at(
+            &mut sizer,
+            &df_stock[20],
+            20,
+            100000.0,
+            Some(TargetPosition::Shares(100)),
+        );
+        let expect = (100000.0 * 0.15 / volatility / 10.0).floor() as usize / 100 * 100;
+        assert_eq!(target, Some(TargetPosition::Shares(expect)));
+        // 波动率数据不足时维持当前仓位
+        let target = size_at(
+            &mut sizer,
+            &df_stock[0],
+            0,
+            100000.0,
+            Some(TargetPosition::Shares(100)),
+        );
+        assert_eq!(target, None);
+    }
+}
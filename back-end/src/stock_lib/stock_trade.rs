@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap};
 use std::usize;
 
 use crate::db::connection::Db;
@@ -6,28 +6,143 @@ use rocket::serde::{Deserialize, Serialize};
 use rocket_db_pools::Connection;
 
 use crate::db::stock_info::StockPriceInfo;
-use crate::stock_lib::get_stock_rps_list::get_local_stock_price_data;
-use rand::Rng;
-use ta::indicators::{AverageTrueRange as ATR, Maximum, Minimum};
-use ta::{DataItem, Next};
+use crate::stock_lib::cost_model::CostModel;
+use crate::stock_lib::engine::{ExecutionConfig, ExecutionEngine, OrderEvent};
+use crate::stock_lib::get_stock_rps_list::{get_local_stock_price_data, get_local_stock_rps_data};
+use crate::stock_lib::metrics::{col_metrics, BacktestMetrics};
+use crate::stock_lib::missing_bar::{prepare_bars, MissingBarPolicy};
+use crate::stock_lib::optimizer::{col_top_k, create_optimizer, OptimizeConfig, ParamScore};
+use crate::stock_lib::sensitivity::{col_robustness, RobustnessReport};
+use crate::stock_lib::sizer::{PositionSizer, SizerConfig};
+use crate::stock_lib::strategy::{
+    create_strategy, BarContext, ParamSpec, Strategy, StrategyData, StrategyParams, TargetPosition,
+};
+use crate::AppErrorEnum;
+use rayon::prelude::*;
+use rocket::tokio::task;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 // 股票交易类
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
-enum OrderType {
+pub enum OrderType {
     Buy,  // 买入
     Sell, // 卖出
 }
-const DEFAULT_COMMISSION_COEFFICIENT: f64 = 0.00025; // 佣金系数, 默认 万2.5(float类型 0.00025)
-const DEFAULT_TAX_COEFFICIENT: f64 = 0.001; // 印花税系数, 默认千 1 (float类型 0.001)
+pub const DEFAULT_COMMISSION_COEFFICIENT: f64 = 0.00025; // 佣金系数, 默认 万2.5(float类型 0.00025)
+pub const DEFAULT_TAX_COEFFICIENT: f64 = 0.001; // 印花税系数, 默认千 1 (float类型 0.001)
+
+/// 股票所属板块，决定涨跌幅限制
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Board {
+    Main,    // 主板，涨跌幅 10%
+    ChiNext, // 创业板，涨跌幅 20%
+    Star,    // 科创板，涨跌幅 20%
+    Beijing, // 北交所，涨跌幅 30%
+}
+
+impl Board {
+    /// 根据股票代码判断板块，如 688001.SH、300750.SZ、830799.BJ
+    pub fn from_code(code: &str) -> Self {
+        if code.ends_with(".BJ") {
+            Board::Beijing
+        } else if code.starts_with("688") || code.starts_with("689") {
+            Board::Star
+        } else if code.starts_with("300") || code.starts_with("301") {
+            Board::ChiNext
+        } else {
+            Board::Main
+        }
+    }
+    /// 涨跌幅限制比例
+    pub fn limit_percent(&self) -> f64 {
+        match self {
+            Board::Main => 0.1,
+            Board::ChiNext | Board::Star => 0.2,
+            Board::Beijing => 0.3,
+        }
+    }
+}
+
+/// A 股交易规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct TradeRules {
+    pub t_plus_one: bool,     // 当天买入的股票次日才能卖出
+    pub lot_size: usize,      // 每手股数，买入必须是整手，默认 100
+    pub star_min_buy: usize,  // 科创板单笔最少买入股数，默认 200
+    pub star_lot_size: usize, // 科创板超过最少股数后的递增单位，默认 1
+    pub price_limit: bool,    // 涨停时不能买入，跌停时不能卖出
+}
+
+impl Default for TradeRules {
+    fn default() -> Self {
+        TradeRules {
+            t_plus_one: true,
+            lot_size: 100,
+            star_min_buy: 200,
+            star_lot_size: 1,
+            price_limit: true,
+        }
+    }
+}
+
+impl TradeRules {
+    /// 按交易单位向下取整后的买入股数，不足最少买入股数时返回 0
+    pub(crate) fn round_buy(&self, code: &str, amount: usize) -> usize {
+        if Board::from_code(code) == Board::Star {
+            if amount < self.star_min_buy {
+                return 0;
+            }
+            let step = self.star_lot_size.max(1);
+            self.star_min_buy + (amount - self.star_min_buy) / step * step
+        } else {
+            let step = self.lot_size.max(1);
+            amount / step * step
+        }
+    }
+    /// 按交易单位向下取整后的卖出股数，一次卖出全部可卖股数时允许零股
+    fn round_sell(&self, code: &str, amount: usize, sellable: usize) -> usize {
+        if amount >= sellable {
+            return sellable;
+        }
+        let step = if Board::from_code(code) == Board::Star {
+            self.star_lot_size.max(1)
+        } else {
+            self.lot_size.max(1)
+        };
+        amount / step * step
+    }
+}
+
+/// 根据昨收价和涨跌幅比例计算涨跌停价，保留两位小数
+fn col_limit_price(pre_close: f64, percent: f64) -> (f64, f64) {
+    let up = (pre_close * (1.0 + percent) * 100.0).round() / 100.0;
+    let down = (pre_close * (1.0 - percent) * 100.0).round() / 100.0;
+    (up, down)
+}
+
+/// 回测运行设置：下单方式、仓位模型和缺失 K 线的处理方式，不属于账户状态
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    // 事件驱动引擎的下单方式，为空时以信号当天的收盘价成交
+    pub execution: Option<ExecutionConfig>,
+    // 仓位模型，为空时沿用策略给出的股数
+    pub sizer: Option<SizerConfig>,
+    // 缺失 K 线的处理方式
+    pub missing_bar: MissingBarPolicy,
+}
 
 /// 股票账户类
 #[derive(Clone)]
-struct ST_Account {
-    hold: HashMap<String, usize>, // 初始持有股票数
-    cash: f64,                    // 初始现金
-    commission_coefficient: f64,  // 佣金系数, 默认 万2.5(float类型 0.00025)
-    tax_coefficient: f64,         // 印花税系数, 默认千 1 (float类型 0.001)
+pub struct ST_Account {
+    hold: HashMap<String, usize>,      // 初始持有股票数
+    cash: f64,                         // 初始现金
+    cost_model: CostModel,             // 交易成本模型
+    last_price: HashMap<String, f64>,  // 每只股票的最新价格，用于计算持仓市值
+    rules: TradeRules,                 // 交易规则
+    today_buy: HashMap<String, usize>, // 当天买入的股数，T+1 规则下当天不能卖出
 }
 
 impl ST_Account {
@@ -45,8 +160,185 @@ impl ST_Account {
         ST_Account {
             hold: init_hold,
             cash: init_cash,
-            commission_coefficient: commission_coeff,
-            tax_coefficient: tax_coeff,
+            cost_model: CostModel {
+                commission_coeff,
+                tax_coeff,
+                ..CostModel::default()
+            },
+            last_price: HashMap::new(),
+            rules: TradeRules::default(),
+            today_buy: HashMap::new(),
+        }
+    }
+    /// 设置交易规则
+    /// rules: 交易规则
+    pub fn with_rules(mut self, rules: TradeRules) -> Self {
+        self.rules = rules;
+        self
+    }
+    /// 交易规则
+    pub fn rules(&self) -> &TradeRules {
+        &self.rules
+    }
+    /// 设置交易成本模型
+    /// cost_model: 交易成本模型
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = cost_model;
+        self
+    }
+    /// 生成一个只有现金的新账户，沿用交易规则和成本模型
+    /// cash: 现金
+    pub fn empty_with_cash(&self, cash: f64) -> Self {
+        ST_Account {
+            hold: HashMap::new(),
+            cash,
+            cost_model: self.cost_model.clone(),
+            last_price: HashMap::new(),
+            rules: self.rules.clone(),
+            today_buy: HashMap::new(),
+        }
+    }
+    /// 计算含滑点的成交价
+    /// bar: 成交当天的 K 线
+    /// amount: 成交股数
+    /// order_type: 买入或卖出
+    pub fn fill_price(&self, bar: &StockPriceInfo, amount: usize, order_type: &OrderType) -> f64 {
+        self.cost_model.fill_price(bar, amount, order_type)
+    }
+    /// 以指定价格为基准计算含滑点的成交价，如开盘价、限价
+    /// bar: 成交当天的 K 线
+    /// base_price: 不含滑点的成交价
+    /// amount: 成交股数
+    /// order_type: 买入或卖出
+    pub fn fill_price_from(
+        &self,
+        bar: &StockPriceInfo,
+        base_price: f64,
+        amount: usize,
+        order_type: &OrderType,
+    ) -> f64 {
+        self.cost_model
+            .fill_price_from(bar, base_price, amount, order_type)
+    }
+    /// 进入新的交易日，前一天买入的股票变为可卖
+    pub fn new_trading_day(&mut self) {
+        self.today_buy.clear();
+    }
+    /// 获取可卖出的股票数，T+1 规则下不包括当天买入的股数
+    /// code: 股票代码
+    pub fn sellable(&self, code: String) -> usize {
+        let today_buy = if self.rules.t_plus_one {
+            self.today_buy.get(&code).copied().unwrap_or(0)
+        } else {
+            0
+        };
+        self.hold_available(code).saturating_sub(today_buy)
+    }
+    /// 按交易单位向下取整，不检查现金和涨跌停，卖出时全部可卖股数允许零股
+    /// code: 股票代码
+    /// amount: 委托股数
+    /// order_type: 买入或卖出
+    pub fn round_lot(&self, code: &str, amount: usize, order_type: &OrderType) -> usize {
+        match order_type {
+            OrderType::Buy => self.rules.round_buy(code, amount),
+            OrderType::Sell => self
+                .rules
+                .round_sell(code, amount, self.sellable(code.to_string())),
+        }
+    }
+    /// 按交易规则、可用现金和可卖股数检查以收盘价成交的订单
+    /// 返回实际能成交的股数，股数被调整时附带原因；完全不能成交时返回原因
+    /// bar: 下单当天的 K 线
+    /// amount: 委托股数
+    /// order_type: 买入或卖出
+    pub fn check_order(
+        &self,
+        bar: &StockPriceInfo,
+        amount: usize,
+        order_type: &OrderType,
+    ) -> Result<(usize, Option<String>), String> {
+        self.check_order_at(bar, bar.close.unwrap_or(0.0), amount, order_type)
+    }
+    /// 按交易规则、可用现金和可卖股数检查以指定价格成交的订单，返回值与 check_order 相同
+    /// bar: 成交当天的 K 线
+    /// base_price: 不含滑点的成交价，用于判断涨跌停和估算买入金额
+    /// amount: 委托股数
+    /// order_type: 买入或卖出
+    pub fn check_order_at(
+        &self,
+        bar: &StockPriceInfo,
+        base_price: f64,
+        amount: usize,
+        order_type: &OrderType,
+    ) -> Result<(usize, Option<String>), String> {
+        let code = &bar.ts_code;
+        // 涨跌停限制
+        if self.rules.price_limit {
+            if let Some(pre_close) = bar.pre_close {
+                let (limit_up, limit_down) =
+                    col_limit_price(pre_close, Board::from_code(code).limit_percent());
+                match order_type {
+                    OrderType::Buy if base_price >= limit_up - 0.005 => {
+                        return Err(format!("涨停价 {} 无法买入", limit_up));
+                    }
+                    OrderType::Sell if base_price <= limit_down + 0.005 => {
+                        return Err(format!("跌停价 {} 无法卖出", limit_down));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        match order_type {
+            OrderType::Buy => {
+                // 按委托股数的成交价估算，股数减少时滑点只会更小
+                let price = self.fill_price_from(bar, base_price, amount, order_type);
+                let affordable = self.cost_model.max_affordable(self.cash, price);
+                let (clip_num, mut reason) = if affordable < amount {
+                    (affordable, Some("现金不足"))
+                } else {
+                    (amount, None)
+                };
+                let operate_num = self.rules.round_buy(code, clip_num);
+                if operate_num == 0 {
+                    return Err(format!(
+                        "{}，委托 {} 股不足一个交易单位",
+                        reason.unwrap_or("整手调整后"),
+                        amount
+                    ));
+                }
+                if operate_num < amount && reason.is_none() {
+                    reason = Some("按交易单位取整");
+                }
+                Ok((
+                    operate_num,
+                    reason.map(|r| format!("{}，委托 {} 股调整为 {} 股", r, amount, operate_num)),
+                ))
+            }
+            OrderType::Sell => {
+                let sellable = self.sellable(code.clone());
+                if sellable == 0 {
+                    return Err(if self.hold_available(code.clone()) == 0 {
+                        "没有持仓可以卖出".to_string()
+                    } else {
+                        "T+1 规则下当天买入的股票不能卖出".to_string()
+                    });
+                }
+                let operate_num = self.rules.round_sell(code, amount, sellable);
+                if operate_num == 0 {
+                    return Err(format!("委托 {} 股不足一个交易单位", amount));
+                }
+                let reason = if amount > sellable {
+                    Some("可卖股数不足")
+                } else if operate_num < amount {
+                    Some("按交易单位取整")
+                } else {
+                    None
+                };
+                Ok((
+                    operate_num,
+                    reason.map(|r| format!("{}，委托 {} 股调整为 {} 股", r, amount, operate_num)),
+                ))
+            }
         }
     }
     /// 获取初始持有股票数
@@ -58,27 +350,63 @@ impl ST_Account {
             0
         }
     }
+    /// 获取持有的全部股票代码
+    pub fn hold_codes(&self) -> Vec<String> {
+        self.hold.keys().cloned().collect()
+    }
     /// 获取初始现金
     pub fn cash_available(&self) -> f64 {
         self.cash
     }
-    /// 获取最新资产
-    pub fn latest_assets(&self, price: f64) -> f64 {
+    /// 更新股票的最新价格，停牌的股票沿用上一次的价格
+    /// code: 股票代码
+    /// price: 最新价格
+    pub fn mark_price(&mut self, code: String, price: f64) {
+        self.last_price.insert(code, price);
+    }
+    /// 获取持仓市值（扣除卖出费用）
+    /// code: 股票代码
+    pub fn hold_value(&self, code: String) -> f64 {
+        let price = self.last_price.get(&code).copied().unwrap_or(0.0);
+        let value = self.hold_available(code) as f64 * price;
+        value - self.cost_model.fee(value, &OrderType::Sell)
+    }
+    /// 获取最新资产，每只持仓按各自的最新价格计算市值
+    pub fn latest_assets(&self) -> f64 {
         let asset = self.cash;
         // 计算持有股票的市值
         // fold 函数的第一个参数是初始值, 第二个参数是一个闭包, 闭包的第一个参数是初始值, 第二个参数是迭代的值
-        self.hold.values().fold(asset, |acc, v| {
-            acc + *v as f64 * price * (1.0 - self.commission_coefficient - self.tax_coefficient)
-        })
+        self.hold
+            .keys()
+            .fold(asset, |acc, code| acc + self.hold_value(code.clone()))
     }
-    pub fn send_order(&mut self, code: String, amount: usize, price: f64, order_type: OrderType) {
+    /// 按成交价下单，返回本次交易的费用
+    /// code: 股票代码
+    /// amount: 成交股数
+    /// price: 成交价
+    /// order_type: 买入或卖出
+    pub fn send_order(
+        &mut self,
+        code: String,
+        amount: usize,
+        price: f64,
+        order_type: OrderType,
+    ) -> f64 {
+        // 买入不超过现金能承受的股数，卖出不超过持有股数
+        let amount = match order_type {
+            OrderType::Buy => amount.min(self.cost_model.max_affordable(self.cash, price)),
+            OrderType::Sell => amount.min(self.hold_available(code.clone())),
+        };
+        if amount == 0 {
+            return 0.0;
+        }
+        let value = price * amount as f64;
+        let fee = self.cost_model.fee(value, &order_type);
         match order_type {
             OrderType::Buy => {
                 // 更新资金剩余
-                self.cash = self.cash
-                    - price
-                        * amount as f64
-                        * (1.0 + self.commission_coefficient + self.tax_coefficient);
+                self.cash = self.cash - value - fee;
+                *self.today_buy.entry(code.clone()).or_insert(0) += amount;
                 // 更新股票持有数量
                 if let Some(hold) = self.hold.get(&code) {
                     self.hold.insert(code, hold + amount);
@@ -88,13 +416,10 @@ impl ST_Account {
             }
             OrderType::Sell => {
                 // 更新资金剩余
-                self.cash = self.cash
-                    + price
-                        * amount as f64
-                        * (1.0 - self.commission_coefficient - self.tax_coefficient);
+                self.cash = self.cash + value - fee;
                 // 更新股票持有数量
                 if let Some(hold) = self.hold.get(&code) {
-                    if amount == *hold {
+                    if amount >= *hold {
                         self.hold.remove(&code);
                     } else {
                         self.hold.insert(code, hold - amount);
@@ -102,414 +427,720 @@ impl ST_Account {
                 }
             }
         }
+        fee
     }
 }
 
+/// 单只股票的回测结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SimulateResult {
+    pub df_stock: Vec<TradeResult>,           // 每日行情、指标和总资产
+    pub operate_record: Vec<OperateRecord>,   // 交易记录
+    pub reject_record: Vec<OperateRecord>,    // 因交易规则未能成交的订单
+    pub best_param: StrategyParams,           // 最优参数
+    pub metrics: BacktestMetrics,             // 最优参数的绩效指标
+    pub top_params: Vec<ParamScore>,          // 得分最高的几组参数，按得分从高到低排列
+    pub cloud: Vec<ParamScore>,               // 参数搜索回测过的全部参数及绩效指标
+    pub robustness: Option<RobustnessReport>, // 最优参数附近的稳健性，参数云为空时为空
+    pub order_events: Vec<OrderEvent>,        // 订单事件，只在使用事件驱动引擎时生成
+    pub stats: OptimizeStats,                 // 参数搜索的耗时和吞吐量
+}
+
+/// 参数搜索的统计信息
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OptimizeStats {
+    pub optimizer: String, // 优化方法
+    pub iterations: usize, // 回测次数
+    pub elapsed_ms: f64,   // 耗时（毫秒）
+    pub throughput: f64,   // 每秒回测次数
+}
+
+/// 生成参与优化的参数空间：请求中的范围覆盖策略默认范围，固定参数不参与搜索
+/// strategy_name: 策略名称
+/// params: 固定的策略参数
+/// param_ranges: 参数名 -> (下限, 上限)
+pub fn col_param_space(
+    strategy_name: &str,
+    params: &StrategyParams,
+    param_ranges: &HashMap<String, (f64, f64)>,
+) -> Result<Vec<ParamSpec>, AppErrorEnum> {
+    let strategy = create_strategy(strategy_name, &StrategyData::default())
+        .ok_or_else(|| AppErrorEnum::InvalidParam(format!("未知的策略: {}", strategy_name)))?;
+    Ok(strategy
+        .param_space()
+        .into_iter()
+        .filter(|spec| !params.contains_key(&spec.name))
+        .map(|mut spec| {
+            if let Some((min, max)) = param_ranges.get(&spec.name) {
+                spec.min = *min;
+                spec.max = *max;
+            }
+            spec
+        })
+        .collect())
+}
+
 /// 模拟股票交易
 /// codes: 股票代码, 数组，必填
-/// init_cash: 初始现金，必填
-/// commission_coeff: 佣金系数，选填
-/// tax_coeff: 印花税系数，选填
+/// st_account: 初始账户，包含初始现金、费率和交易规则
+/// run_config: 回测运行设置
+/// strategy_name: 策略名称，必填
+/// params: 固定的策略参数，不参与参数优化
+/// param_ranges: 参与参数优化的范围，参数名 -> (下限, 上限)，未指定时使用策略的默认范围
+/// config: 参数优化设置
+#[allow(clippy::too_many_arguments)]
 pub async fn simulate_stock_trade(
-    mut db: Connection<Db>,
+    db: &mut Connection<Db>,
     codes: Vec<String>,
-    init_cash: f64,
-    commission_coeff: Option<f64>,
-    tax_coeff: Option<f64>,
-    n1_range: Option<(usize, usize)>,
-    n2_range: Option<(usize, usize)>,
-    win_range: Option<(f64, f64)>,
-    loss_range: Option<(f64, f64)>,
-    adjust_range: Option<(usize, usize)>,
-) -> HashMap<
-    String,
-    (
-        (Vec<TradeResult>, Vec<OperateRecord>),
-        (
-            Option<usize>,
-            Option<usize>,
-            Option<f64>,
-            Option<f64>,
-            Option<usize>,
-        ),
-    ),
-> {
-    // 初始化持有股票数
-    let init_hold: HashMap<String, usize> =
-        codes.clone().into_iter().map(|code| (code, 0)).collect();
-    // 初始化佣金系数和印花税系数
-    let commission_coeff = commission_coeff.unwrap_or(DEFAULT_COMMISSION_COEFFICIENT);
-    let tax_coeff = tax_coeff.unwrap_or(DEFAULT_TAX_COEFFICIENT);
-    // 初始化股票账户
-    let st_account: ST_Account = ST_Account::new(init_hold, init_cash, commission_coeff, tax_coeff);
-    let mut code_map: HashMap<
-        String,
-        (
-            (Vec<TradeResult>, Vec<OperateRecord>),
-            (
-                Option<usize>,
-                Option<usize>,
-                Option<f64>,
-                Option<f64>,
-                Option<usize>,
-            ),
-        ),
-    > = HashMap::new();
+    st_account: ST_Account,
+    run_config: &RunConfig,
+    strategy_name: &str,
+    params: StrategyParams,
+    param_ranges: HashMap<String, (f64, f64)>,
+    config: &OptimizeConfig,
+) -> Result<HashMap<String, SimulateResult>, AppErrorEnum> {
+    let param_space = col_param_space(strategy_name, &params, &param_ranges)?;
+    let mut code_map: HashMap<String, SimulateResult> = HashMap::new();
     // 模拟交易
     for code in codes {
-        let df_stock = get_local_stock_price_data(&mut db, code.clone())
-            .await
-            .expect("获取数据错误");
-        let result: (
-            (Vec<TradeResult>, Vec<OperateRecord>),
-            (
-                Option<usize>,
-                Option<usize>,
-                Option<f64>,
-                Option<f64>,
-                Option<usize>,
-            ),
-        ) = cal_ndayavg_mc(
-            10000,
+        let df_stock = prepare_bars(
+            &code,
+            get_local_stock_price_data(db, code.clone()).await?,
+            run_config.missing_bar,
+        )?;
+        let strategy_data = StrategyData {
+            rps: Arc::new(get_local_stock_rps_data(db, code.clone()).await?),
+            ..StrategyData::default()
+        };
+        // 参数搜索是 CPU 密集型任务，放到阻塞线程中执行，避免占用异步运行时
+        let (config, account, run_config, strategy_name, param_space, params) = (
+            config.clone(),
             st_account.clone(),
-            df_stock,
-            n1_range,
-            n2_range,
-            win_range,
-            loss_range,
-            adjust_range,
+            run_config.clone(),
+            strategy_name.to_string(),
+            param_space.clone(),
+            params.clone(),
         );
-        code_map.insert(code, result);
-    }
-    code_map
-}
-#[derive(Debug)]
-struct TradeSignal {
-    code: String,          // 股票代码
-    date: Option<String>,  // 交易日期
-    open: Option<f64>,     // 开盘价
-    close: Option<f64>,    // 收盘价
-    high: Option<f64>,     // 最高价
-    low: Option<f64>,      // 最低价
-    volume: Option<f64>,   // 成交量
-    signal: Option<usize>, // 交易信号
-    n1_high: Option<f64>,  // N1 日最高价
-    n2_low: Option<f64>,   // N2 日最低价
-    atr_14: Option<f64>,   // ATR 14 日
-}
-
-fn col_trade_signal(
-    df_stock: &Vec<StockPriceInfo>,
-    n1: Option<usize>,
-    n2: Option<usize>,
-    period: Option<usize>,
-    n_win: Option<f64>,
-    n_loss: Option<f64>,
-) -> Vec<TradeSignal> {
-    let mut result: Vec<TradeSignal> = df_stock
-        .into_iter()
-        .map(|stock| TradeSignal {
-            code: stock.ts_code.clone(),
-            date: stock.trade_date.clone(),
-            open: stock.open,
-            close: stock.close,
-            high: stock.high,
-            low: stock.low,
-            volume: stock.vol,
-            signal: None,
-            n1_high: None,
-            n2_low: None,
-            atr_14: None,
+        let result = task::spawn_blocking(move || {
+            cal_ndayavg_mc(
+                &config,
+                &account,
+                &run_config,
+                &df_stock,
+                &strategy_name,
+                &strategy_data,
+                &param_space,
+                &params,
+            )
         })
-        .collect();
-
-    let n1_high = n1.unwrap_or(14);
-    let n2_low = n2.unwrap_or(2);
-    let period = period.unwrap_or(14);
-    let n_win = n_win.unwrap_or(2.0);
-    let n_loss = n_loss.unwrap_or(0.8);
-    // N1 日最高价
-    let mut max = Maximum::new(n1_high).unwrap();
-    // N2 日最低价
-    let mut min = Minimum::new(n2_low).unwrap();
-
-    for stock in result.iter_mut() {
-        stock.n1_high = Some(max.next(stock.high.unwrap_or(0.0)));
-        stock.n2_low = Some(min.next(stock.low.unwrap_or(0.0)));
-    }
-    let mut n1_queue: VecDeque<Option<f64>> = result.iter().map(|v| v.n1_high).collect();
-    // 整体数据向右移动一位
-    n1_queue.push_front(n1_queue.front().unwrap().clone());
-    n1_queue.pop_back();
-    for (index, today) in result.iter_mut().enumerate() {
-        today.n1_high = n1_queue[index];
-    }
-    let mut n2_queue: VecDeque<Option<f64>> = result.iter().map(|v| v.n2_low).collect();
-    // 整体数据向右移动一位
-    n2_queue.push_front(n2_queue.front().unwrap().clone());
-    n2_queue.pop_back();
-    for (index, today) in result.iter_mut().enumerate() {
-        today.n2_low = n2_queue[index];
-    }
-    // ATR 计算
-    let mut atr = ATR::new(period).unwrap();
-    // 创建了一个 result 的不可变引用, 后续又创建了一个可变引用, 这是不允许的
-    // 因此使用 collect 方法将 result 转换为可变的 Vec, 消费掉 data 的所有权
-    let data: Vec<DataItem> = result
-        .iter()
-        .map(|stock| {
-            let res = match DataItem::builder()
-                .open(stock.open.unwrap_or(0.0))
-                .high(stock.high.unwrap_or(0.0))
-                .low(stock.low.unwrap_or(0.0))
-                .close(stock.close.unwrap_or(0.0))
-                .volume(stock.volume.unwrap_or(0.0))
-                .build()
-            {
-                Ok(res) => res,
-                Err(e) => {
-                    println!("{:#?}", e);
-                    DataItem::builder().build().unwrap()
-                }
-            };
-            res
-        })
-        .collect();
-
-    // ATR 计算
-    for (index, di) in data.into_iter().enumerate() {
-        if index < result.len() - 1 {
-            // atr 数据右移一位
-            result[index + 1].atr_14 = Some(atr.next(&di));
-        }
-    }
-    result[0].atr_14 = result[1].atr_14;
-    // 所有右移操作都是为了让今天的信号根据昨天的数据进行计算
-    // 买入价
-    let mut buy_price = 0.0;
-    for today in result.iter_mut() {
-        // 今日收盘价大于 N1 日最高价
-        if today.close.unwrap() > today.n1_high.unwrap() {
-            buy_price = today.close.unwrap_or(0.0) as f64;
-            today.signal = Some(1);
-        } else if buy_price > 0.0
-            && today.close.unwrap() < buy_price
-            && (today.close.unwrap() < buy_price - today.atr_14.unwrap() * n_loss)
-        {
-            // 今日收盘价小于买入价且小买入价减去 N2 日最低价的 ATR 乘以 n_loss
-            buy_price = 0.0;
-            today.signal = Some(0);
-        } else if buy_price > 0.0
-            && today.close.unwrap() > buy_price
-            && today.close.unwrap() > buy_price + today.atr_14.unwrap() * n_win
-        {
-            // 今日收盘价大于买入价且大于买入价加上 N1 日最高价的 ATR 乘以 n_win
-            buy_price = 0.0;
-            today.signal = Some(0);
-        } else if today.close.unwrap() < today.n2_low.unwrap() {
-            buy_price = 0.0;
-            today.signal = Some(0);
-        }
-    }
-    let mut signal_queue: VecDeque<Option<usize>> = result.iter().map(|v| v.signal).collect();
-    // 整体数据向右移动一位
-    signal_queue.push_front(None);
-    signal_queue.pop_back();
-    for (index, today) in result.iter_mut().enumerate() {
-        today.signal = signal_queue[index];
+        .await??;
+        code_map.insert(code, result);
     }
-    result
+    Ok(code_map)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct TradeResult {
-    code: String,              // 股票代码
-    date: Option<String>,      // 交易日期
-    open: Option<f64>,         // 开盘价
-    close: Option<f64>,        // 收盘价
-    high: Option<f64>,         // 最高价
-    low: Option<f64>,          // 最低价
-    volume: Option<f64>,       // 成交量
-    signal: Option<usize>,     // 交易信号
-    n1_high: Option<f64>,      // N1 日最高价
-    n2_low: Option<f64>,       // N2 日最低价
-    atr_14: Option<f64>,       // ATR 14 日
-    total_assets: Option<f64>, // 总资产
+    pub code: String,          // 股票代码
+    pub date: Option<String>,  // 交易日期
+    pub open: Option<f64>,     // 开盘价
+    pub close: Option<f64>,    // 收盘价
+    pub high: Option<f64>,     // 最高价
+    pub low: Option<f64>,      // 最低价
+    pub volume: Option<f64>,   // 成交量
+    pub signal: Option<usize>, // 交易信号，1 买入/加仓，0 卖出/减仓
+    pub hold: usize,           // 收盘后持股数
+    #[serde(flatten)]
+    pub indicators: BTreeMap<String, Option<f64>>, // 策略指标，如 n1_high、atr_14
+    pub total_assets: Option<f64>, // 总资产
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct OperateRecord {
-    order_type: OrderType,
-    hold: usize,
-    assets: f64,
-    operate_num: usize,
-    close: f64,
-    operate_date: Option<String>,
+    pub code: String,
+    pub order_type: OrderType,
+    pub hold: usize,
+    pub assets: f64,
+    pub operate_num: usize,
+    pub close: f64,
+    pub operate_date: Option<String>,
+    pub price: f64,             // 成交价，包含滑点
+    pub fee: f64,               // 佣金、过户费和印花税合计
+    pub reason: Option<String>, // 订单被调整或未能成交的原因
 }
-/// adjust_hold: 动态持仓买入/卖出波动线
-fn simulate_trade(
-    df_stock: Vec<TradeSignal>,
-    account: &mut ST_Account,
-    adjust_hold: Option<usize>,
-) -> (Vec<TradeResult>, Vec<OperateRecord>) {
-    let mut has_buy = false;
-    let mut operate_query: Vec<OperateRecord> = vec![];
 
-    let mut df_stock: Vec<TradeResult> = df_stock
-        .into_iter()
-        .map(|stock| TradeResult {
-            code: stock.code,
-            date: stock.date,
-            open: stock.open,
-            close: stock.close,
-            high: stock.high,
-            low: stock.low,
-            volume: stock.volume,
-            signal: stock.signal,
-            n1_high: stock.n1_high,
-            n2_low: stock.n2_low,
-            atr_14: stock.atr_14,
-            total_assets: None,
-        })
-        .collect();
-    for today in df_stock.iter_mut() {
-        if today.signal.unwrap_or(0) == 1 && !has_buy {
-            // 买入信号
-            has_buy = true;
-            account.send_order(
-                today.code.clone(),
-                (account.cash_available() * 0.01 / today.atr_14.unwrap_or(1.0)).floor() as usize,
-                today.close.unwrap(),
-                OrderType::Buy,
-            );
-            operate_query.push(OperateRecord {
-                order_type: OrderType::Buy,
-                hold: account.hold_available(today.code.clone()),
-                assets: account.latest_assets(today.close.unwrap()),
-                operate_num: (account.cash_available() * 0.01 / today.atr_14.unwrap_or(1.0)).floor()
-                    as usize,
-                operate_date: today.date.clone(),
-                close: today.close.unwrap(),
-            });
-        } else if today.signal.unwrap_or(0) == 0 && has_buy {
-            // 卖出信号
-            has_buy = false;
-            let operate_num = account.hold_available(today.code.clone());
-            account.send_order(
-                today.code.clone(),
-                operate_num,
-                today.close.unwrap(),
-                OrderType::Sell,
-            );
-            operate_query.push(OperateRecord {
-                order_type: OrderType::Sell,
-                hold: account.hold_available(today.code.clone()),
-                assets: account.latest_assets(today.close.unwrap()),
+/// 按目标仓位与当前持仓的差值下单，以当日收盘价加滑点成交，返回交易信号
+/// account: 股票账户
+/// today: 当日 K 线
+/// target: 策略给出的目标仓位
+/// operate_query: 交易记录
+/// reject_query: 因交易规则未能成交的订单
+pub fn execute_target(
+    account: &mut ST_Account,
+    today: &StockPriceInfo,
+    target: TargetPosition,
+    operate_query: &mut Vec<OperateRecord>,
+    reject_query: &mut Vec<OperateRecord>,
+) -> Option<usize> {
+    let close = today.close?;
+    let hold = account.hold_available(today.ts_code.clone());
+    let target_num = target.shares();
+    let (order_type, operate_num, signal) = if target_num > hold {
+        (OrderType::Buy, target_num - hold, 1)
+    } else if target_num < hold {
+        (OrderType::Sell, hold - target_num, 0)
+    } else {
+        return None;
+    };
+    let (operate_num, reason) = match account.check_order(today, operate_num, &order_type) {
+        Ok(checked) => checked,
+        Err(reason) => {
+            reject_query.push(OperateRecord {
+                code: today.ts_code.clone(),
+                order_type,
+                hold,
+                assets: account.latest_assets(),
                 operate_num,
-                operate_date: today.date.clone(),
-                close: today.close.unwrap(),
+                operate_date: today.trade_date.clone(),
+                close,
+                price: close,
+                fee: 0.0,
+                reason: Some(reason),
             });
+            return None;
         }
-        // 动态计算持仓的股票数量
-        if has_buy {
-            let posit_num_wave = (account.latest_assets(today.close.unwrap()) * 0.01
-                / today.atr_14.unwrap())
-            .floor() as usize;
-            // 波动后加仓
-            if posit_num_wave
-                > (account.hold_available(today.code.clone()) + adjust_hold.unwrap_or(0))
-            {
-                let operate_num = posit_num_wave - account.hold_available(today.code.clone());
-                account.send_order(
-                    today.code.clone(),
-                    operate_num,
-                    today.close.unwrap(),
-                    OrderType::Buy,
-                );
-                operate_query.push(OperateRecord {
-                    order_type: OrderType::Buy,
-                    hold: account.hold_available(today.code.clone()),
-                    assets: account.latest_assets(today.close.unwrap()),
-                    operate_num,
-                    operate_date: today.date.clone(),
-                    close: today.close.unwrap(),
-                });
+    };
+    let price = account.fill_price(today, operate_num, &order_type);
+    let fee = account.send_order(
+        today.ts_code.clone(),
+        operate_num,
+        price,
+        order_type.clone(),
+    );
+    operate_query.push(OperateRecord {
+        code: today.ts_code.clone(),
+        order_type,
+        hold: account.hold_available(today.ts_code.clone()),
+        assets: account.latest_assets(),
+        operate_num,
+        operate_date: today.trade_date.clone(),
+        close,
+        price,
+        fee,
+        reason,
+    });
+    Some(signal)
+}
+
+/// 单次回测的结果
+#[derive(Debug, Default)]
+pub(crate) struct TradeRun {
+    pub(crate) trade_result: Vec<TradeResult>, // 每日明细，只在 detail 为 true 时生成
+    pub(crate) total_assets: Vec<f64>,         // 每日总资产
+    pub(crate) position_value: Vec<f64>,       // 每日持仓市值
+    pub(crate) operate_record: Vec<OperateRecord>, // 交易记录
+    pub(crate) reject_record: Vec<OperateRecord>, // 未能成交的订单
+    pub(crate) order_events: Vec<OrderEvent>,  // 订单事件，只在使用事件驱动引擎时生成
+}
+
+/// 按策略给出的目标仓位逐日模拟交易
+/// 没有设置下单方式时以信号当天的收盘价成交，否则由事件驱动引擎在之后的 K 线上撮合
+/// run_config: 回测运行设置
+/// sizer: 仓位模型，为空时沿用策略给出的股数
+/// start: 从第 start 根 K 线开始交易，之前的 K 线只用于计算指标，不生成净值和明细
+/// detail: 是否生成每日明细，参数搜索时只需要净值曲线计算指标
+fn simulate_trade(
+    df_stock: &[StockPriceInfo],
+    strategy: &mut dyn Strategy,
+    account: &mut ST_Account,
+    run_config: &RunConfig,
+    mut sizer: Option<PositionSizer>,
+    start: usize,
+    detail: bool,
+) -> TradeRun {
+    let mut run = TradeRun {
+        total_assets: Vec::with_capacity(df_stock.len().saturating_sub(start)),
+        position_value: Vec::with_capacity(df_stock.len().saturating_sub(start)),
+        ..TradeRun::default()
+    };
+    let mut engine = run_config.execution.clone().map(ExecutionEngine::new);
+    for (index, today) in df_stock.iter().enumerate().skip(start) {
+        // 没有收盘价的 K 线无法估值和成交，直接跳过，回测前应先按 MissingBarPolicy 清洗
+        let close = match today.close {
+            Some(close) => close,
+            None => continue,
+        };
+        account.new_trading_day();
+        account.mark_price(today.ts_code.clone(), close);
+        // 先用今天的 K 线撮合之前的订单，策略看到的是成交后的持仓
+        let filled = engine.as_mut().and_then(|engine| {
+            engine.match_orders(
+                account,
+                today,
+                &mut run.operate_record,
+                &mut run.reject_record,
+            )
+        });
+        let ctx = BarContext {
+            index,
+            bar: today,
+            hold: account.hold_available(today.ts_code.clone()),
+            cash: account.cash_available(),
+            assets: account.latest_assets(),
+        };
+        let mut target = strategy.on_bar(&ctx);
+        if let Some(sizer) = sizer.as_mut() {
+            target = sizer.size(&ctx, target);
+        }
+        let signal = match engine.as_mut() {
+            Some(engine) => {
+                if let Some(target) = target {
+                    engine.submit(account, today, target);
+                }
+                filled
             }
-            // 波动后减仓
-            if posit_num_wave
-                < (account.hold_available(today.code.clone()) - adjust_hold.unwrap_or(0))
-            {
-                let operate_num = account.hold_available(today.code.clone()) - posit_num_wave;
-                account.send_order(
-                    today.code.clone(),
-                    operate_num,
-                    today.close.unwrap(),
-                    OrderType::Sell,
+            None => target.and_then(|target| {
+                execute_target(
+                    account,
+                    today,
+                    target,
+                    &mut run.operate_record,
+                    &mut run.reject_record,
+                )
+            }),
+        };
+        let hold = account.hold_available(today.ts_code.clone());
+        let total_assets = account.latest_assets();
+        run.total_assets.push(total_assets);
+        run.position_value.push(hold as f64 * close);
+        if detail {
+            run.trade_result.push(TradeResult {
+                code: today.ts_code.clone(),
+                date: today.trade_date.clone(),
+                open: today.open,
+                close: today.close,
+                high: today.high,
+                low: today.low,
+                volume: today.vol,
+                signal,
+                hold,
+                indicators: strategy
+                    .indicators(index)
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect(),
+                total_assets: Some(total_assets),
+            });
+        }
+    }
+    if let Some(engine) = engine {
+        run.order_events = engine.events;
+    }
+    run
+}
+
+/// 用一组参数回测一次并计算绩效指标
+/// start: 开始交易的 K 线下标，之前的 K 线只用于计算指标
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_params(
+    df_stock: &[StockPriceInfo],
+    strategy_name: &str,
+    strategy_data: &StrategyData,
+    account: &ST_Account,
+    run_config: &RunConfig,
+    params: &StrategyParams,
+    start: usize,
+    detail: bool,
+) -> (TradeRun, BacktestMetrics) {
+    let mut strategy = create_strategy(strategy_name, strategy_data).expect("策略不存在");
+    let mut new_account = account.clone();
+    strategy.init(params, df_stock);
+    let sizer = run_config.sizer.as_ref().map(|config| {
+        PositionSizer::new(
+            config.clone(),
+            account.rules().clone(),
+            df_stock,
+            &strategy_data.cache,
+        )
+    });
+    let run = simulate_trade(
+        df_stock,
+        strategy.as_mut(),
+        &mut new_account,
+        run_config,
+        sizer,
+        start,
+        detail,
+    );
+    let metrics = col_metrics(
+        &run.total_assets,
+        &run.position_value,
+        &run.operate_record,
+        account.latest_assets(),
+    );
+    (run, metrics)
+}
+
+/// 一次参数搜索的结果
+pub(crate) struct OptimizeRun {
+    pub(crate) top_params: Vec<ParamScore>, // 得分最高的几组参数
+    pub(crate) cloud: Vec<ParamScore>,      // 回测过的全部参数，未开启 keep_cloud 时为空
+    pub(crate) stats: OptimizeStats,        // 耗时和吞吐量
+}
+
+// 用设置的优化器搜索参数，返回按优化目标得分从高到低排列的前 top_k 组参数
+// 优化器决定每一批回测的参数，同一批参数用 rayon 并行回测，得分相同时取先回测的参数，保证结果可以复现
+// 参数空间为空时（全部参数固定）只回测一次
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn optimize_params(
+    config: &OptimizeConfig,
+    account: &ST_Account,
+    run_config: &RunConfig,
+    df_stock: &[StockPriceInfo],
+    strategy_name: &str,
+    strategy_data: &StrategyData,
+    param_space: &[ParamSpec],
+    fixed_params: &StrategyParams,
+) -> Result<OptimizeRun, AppErrorEnum> {
+    let start = Instant::now();
+    // 每只股票都用同一个种子，单独回测和多只股票一起回测的结果一致
+    let mut optimizer = create_optimizer(config);
+    // 按回测的先后顺序记录参数云
+    let cloud: Mutex<Vec<ParamScore>> = Mutex::new(vec![]);
+    let evaluate = |params_list: &[StrategyParams]| -> Vec<f64> {
+        let metrics_list: Vec<BacktestMetrics> = params_list
+            .par_iter()
+            .map(|params| {
+                let (_, metrics) = run_params(
+                    df_stock,
+                    strategy_name,
+                    strategy_data,
+                    account,
+                    run_config,
+                    params,
+                    0,
+                    false,
                 );
-                operate_query.push(OperateRecord {
-                    order_type: OrderType::Sell,
-                    hold: account.hold_available(today.code.clone()),
-                    assets: account.latest_assets(today.close.unwrap()),
-                    operate_num,
-                    operate_date: today.date.clone(),
-                    close: today.close.unwrap(),
+                metrics
+            })
+            .collect();
+        let scores: Vec<f64> = metrics_list
+            .iter()
+            .map(|metrics| config.objective.score(metrics))
+            .collect();
+        if config.keep_cloud {
+            let mut cloud = cloud.lock().unwrap();
+            for ((params, metrics), score) in params_list.iter().zip(metrics_list).zip(&scores) {
+                cloud.push(ParamScore {
+                    params: params.clone(),
+                    score: *score,
+                    metrics,
                 });
             }
         }
+        scores
+    };
+    let scores = optimizer.optimize(param_space, fixed_params, &evaluate)?;
+    let n = scores.len();
+    let top_params: Vec<ParamScore> = col_top_k(scores, config.top_k.max(1))
+        .into_par_iter()
+        .map(|(params, score)| {
+            let (_, metrics) = run_params(
+                df_stock,
+                strategy_name,
+                strategy_data,
+                account,
+                run_config,
+                &params,
+                0,
+                false,
+            );
+            ParamScore {
+                params,
+                score,
+                metrics,
+            }
+        })
+        .collect();
+    let elapsed = start.elapsed().as_secs_f64();
+    let stats = OptimizeStats {
+        optimizer: optimizer.name().to_string(),
+        iterations: n,
+        elapsed_ms: elapsed * 1000.0,
+        throughput: if elapsed > 0.0 {
+            n as f64 / elapsed
+        } else {
+            0.0
+        },
+    };
+    Ok(OptimizeRun {
+        top_params,
+        cloud: cloud.into_inner().unwrap(),
+        stats,
+    })
+}
 
-        today.total_assets = Some(account.latest_assets(today.close.unwrap()));
-    }
-    (df_stock, operate_query)
+/// 搜索最优参数，并用得分最高的参数再回测一次生成每日明细
+/// df_stock: 已按 MissingBarPolicy 清洗过的 K 线
+#[allow(clippy::too_many_arguments)]
+pub fn cal_ndayavg_mc(
+    config: &OptimizeConfig,
+    account: &ST_Account,
+    run_config: &RunConfig,
+    df_stock: &[StockPriceInfo],
+    strategy_name: &str,
+    strategy_data: &StrategyData,
+    param_space: &[ParamSpec],
+    fixed_params: &StrategyParams,
+) -> Result<SimulateResult, AppErrorEnum> {
+    let OptimizeRun {
+        top_params,
+        cloud,
+        stats,
+    } = optimize_params(
+        config,
+        account,
+        run_config,
+        df_stock,
+        strategy_name,
+        strategy_data,
+        param_space,
+        fixed_params,
+    )?;
+    let best_param = top_params
+        .first()
+        .map(|top| top.params.clone())
+        .unwrap_or_else(|| fixed_params.clone());
+    // 最优参数再回测一次生成每日明细
+    let (run, metrics) = run_params(
+        df_stock,
+        strategy_name,
+        strategy_data,
+        account,
+        run_config,
+        &best_param,
+        0,
+        true,
+    );
+    let robustness = col_robustness(&cloud, &best_param, param_space);
+    Ok(SimulateResult {
+        df_stock: run.trade_result,
+        operate_record: run.operate_record,
+        reject_record: run.reject_record,
+        order_events: run.order_events,
+        best_param,
+        metrics,
+        robustness,
+        top_params,
+        cloud,
+        stats,
+    })
 }
 
-// 蒙特卡洛算法模拟最优参数
-fn cal_ndayavg_mc(
-    n: usize,
-    account: ST_Account,
-    df_stock: Vec<StockPriceInfo>,
-    n1_range: Option<(usize, usize)>,
-    n2_range: Option<(usize, usize)>,
-    win_range: Option<(f64, f64)>,
-    loss_range: Option<(f64, f64)>,
-    adjust_range: Option<(usize, usize)>,
-) -> (
-    (Vec<TradeResult>, Vec<OperateRecord>),
-    (
-        Option<usize>,
-        Option<usize>,
-        Option<f64>,
-        Option<f64>,
-        Option<usize>,
-    ),
-) {
-    let (n1_min, n1_max) = n1_range.unwrap_or((5, 20));
-    let (n2_min, n2_max) = n2_range.unwrap_or((1, 15));
-    let (win_min, win_max) = win_range.unwrap_or((1.5, 2.5));
-    let (loss_min, loss_max) = loss_range.unwrap_or((0.5, 1.5));
-    let (adjust_min, adjust_max) = adjust_range.unwrap_or((0, 100));
-    let mut max_total = 0.0;
-    let mut best_param = (None, None, None, None, None);
-    let mut simulate_result = (vec![], vec![]);
-    for _ in 0..n {
-        let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
-        let n1 = rng.gen_range(n1_min..n1_max);
-        let n2 = rng.gen_range(n2_min..n2_max);
-        let win = rng.gen_range(win_min..win_max);
-        let loss = rng.gen_range(loss_min..loss_max);
-        let adjust = rng.gen_range(adjust_min..adjust_max);
-        let mut new_account = account.clone();
-        let new_df_stock = df_stock.clone();
-        let df_stock_trade_signal =
-            col_trade_signal(&new_df_stock, Some(n1), Some(n2), Some(14), None, None);
-        let result = simulate_trade(df_stock_trade_signal, &mut new_account, Some(adjust));
-        let total_assets = result.0.last().unwrap().total_assets.unwrap();
-        if max_total < total_assets {
-            max_total = total_assets;
-            best_param = (Some(n1), Some(n2), Some(win), Some(loss), Some(adjust));
-            simulate_result = result;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::test_util::{assert_close, mock_account, mock_bar};
+
+    const MAIN: &str = "600000.SH";
+    const CHINEXT: &str = "300750.SZ";
+    const STAR: &str = "688001.SH";
+
+    /// 昨收价 10 元的 K 线
+    fn limit_bar(code: &str, close: f64) -> StockPriceInfo {
+        StockPriceInfo {
+            pre_close: Some(10.0),
+            ..mock_bar(code, 0, close)
         }
     }
-    (simulate_result, best_param)
+
+    #[test]
+    fn t_plus_one_blocks_same_day_sell() {
+        let mut account = mock_account(&[], 100000.0);
+        let bar = mock_bar(MAIN, 0, 10.0);
+        let (mut operate, mut reject) = (vec![], vec![]);
+        execute_target(
+            &mut account,
+            &bar,
+            TargetPosition::Shares(1000),
+            &mut operate,
+            &mut reject,
+        );
+        assert_eq!(account.hold_available(MAIN.to_string()), 1000);
+        assert_eq!(account.sellable(MAIN.to_string()), 0);
+        let reason = account
+            .check_order(&bar, 1000, &OrderType::Sell)
+            .unwrap_err();
+        assert!(reason.contains("T+1"));
+        // 当天买入后立即清仓，卖单被拒绝，持仓不变
+        execute_target(
+            &mut account,
+            &bar,
+            TargetPosition::Flat,
+            &mut operate,
+            &mut reject,
+        );
+        assert_eq!(operate.len(), 1);
+        assert_eq!(reject.len(), 1);
+        assert_eq!(account.hold_available(MAIN.to_string()), 1000);
+        // 次日可以卖出
+        account.new_trading_day();
+        assert_eq!(
+            account.check_order(&bar, 1000, &OrderType::Sell),
+            Ok((1000, None))
+        );
+    }
+
+    #[test]
+    fn t_plus_one_can_be_disabled() {
+        let mut account = mock_account(&[], 100000.0).with_rules(TradeRules {
+            t_plus_one: false,
+            ..TradeRules::default()
+        });
+        let bar = mock_bar(MAIN, 0, 10.0);
+        account.send_order(MAIN.to_string(), 1000, 10.0, OrderType::Buy);
+        assert_eq!(
+            account.check_order(&bar, 1000, &OrderType::Sell),
+            Ok((1000, None))
+        );
+    }
+
+    #[test]
+    fn buy_rounds_down_to_lot() {
+        let account = mock_account(&[], 100000.0);
+        let bar = mock_bar(MAIN, 0, 10.0);
+        let (operate_num, reason) = account.check_order(&bar, 150, &OrderType::Buy).unwrap();
+        assert_eq!(operate_num, 100);
+        assert!(reason.unwrap().contains("按交易单位取整"));
+        assert_eq!(
+            account.check_order(&bar, 300, &OrderType::Buy),
+            Ok((300, None))
+        );
+        assert!(account.check_order(&bar, 50, &OrderType::Buy).is_err());
+    }
+
+    #[test]
+    fn star_board_requires_min_buy() {
+        let account = mock_account(&[], 100000.0);
+        let bar = mock_bar(STAR, 0, 10.0);
+        assert!(account.check_order(&bar, 150, &OrderType::Buy).is_err());
+        assert_eq!(
+            account.check_order(&bar, 200, &OrderType::Buy),
+            Ok((200, None))
+        );
+        // 超过 200 股后以 1 股为单位递增
+        assert_eq!(
+            account.check_order(&bar, 251, &OrderType::Buy),
+            Ok((251, None))
+        );
+    }
+
+    #[test]
+    fn sell_rounds_to_lot_unless_selling_all() {
+        let account = mock_account(&[(MAIN, 250)], 0.0);
+        let bar = mock_bar(MAIN, 0, 10.0);
+        let (operate_num, reason) = account.check_order(&bar, 150, &OrderType::Sell).unwrap();
+        assert_eq!(operate_num, 100);
+        assert!(reason.unwrap().contains("按交易单位取整"));
+        // 一次卖出全部持仓时允许零股
+        assert_eq!(
+            account.check_order(&bar, 250, &OrderType::Sell),
+            Ok((250, None))
+        );
+        let (operate_num, reason) = account.check_order(&bar, 300, &OrderType::Sell).unwrap();
+        assert_eq!(operate_num, 250);
+        assert!(reason.unwrap().contains("可卖股数不足"));
+        assert!(account.check_order(&bar, 50, &OrderType::Sell).is_err());
+    }
+
+    #[test]
+    fn price_limit_depends_on_board() {
+        let account = mock_account(&[(MAIN, 1000), (CHINEXT, 1000)], 100000.0);
+        // 主板涨跌幅 10%
+        let reason = account
+            .check_order(&limit_bar(MAIN, 11.0), 100, &OrderType::Buy)
+            .unwrap_err();
+        assert!(reason.contains("涨停"));
+        let reason = account
+            .check_order(&limit_bar(MAIN, 9.0), 100, &OrderType::Sell)
+            .unwrap_err();
+        assert!(reason.contains("跌停"));
+        assert!(account
+            .check_order(&limit_bar(MAIN, 10.9), 100, &OrderType::Buy)
+            .is_ok());
+        // 跌停时可以买入，涨停时可以卖出
+        assert!(account
+            .check_order(&limit_bar(MAIN, 9.0), 100, &OrderType::Buy)
+            .is_ok());
+        assert!(account
+            .check_order(&limit_bar(MAIN, 11.0), 100, &OrderType::Sell)
+            .is_ok());
+        // 创业板涨跌幅 20%
+        assert!(account
+            .check_order(&limit_bar(CHINEXT, 11.0), 100, &OrderType::Buy)
+            .is_ok());
+        assert!(account
+            .check_order(&limit_bar(CHINEXT, 12.0), 100, &OrderType::Buy)
+            .is_err());
+        assert!(account
+            .check_order(&limit_bar(CHINEXT, 8.0), 100, &OrderType::Sell)
+            .is_err());
+        // 关闭涨跌停限制
+        let account = account.with_rules(TradeRules {
+            price_limit: false,
+            ..TradeRules::default()
+        });
+        assert!(account
+            .check_order(&limit_bar(MAIN, 11.0), 100, &OrderType::Buy)
+            .is_ok());
+    }
+
+    #[test]
+    fn board_from_code() {
+        assert_eq!(Board::from_code(MAIN), Board::Main);
+        assert_eq!(Board::from_code(CHINEXT), Board::ChiNext);
+        assert_eq!(Board::from_code(STAR), Board::Star);
+        assert_eq!(Board::from_code("830799.BJ"), Board::Beijing);
+    }
+
+    #[test]
+    fn oversize_buy_is_clipped_to_affordable_lots() {
+        let mut account = mock_account(&[], 10000.0);
+        let bar = mock_bar(MAIN, 0, 10.0);
+        let (mut operate, mut reject) = (vec![], vec![]);
+        execute_target(
+            &mut account,
+            &bar,
+            TargetPosition::Shares(2000),
+            &mut operate,
+            &mut reject,
+        );
+        // 现金加费用最多买入 999 股，按整手取整为 900 股
+        assert!(reject.is_empty());
+        assert_eq!(operate[0].operate_num, 900);
+        assert!(operate[0].reason.as_ref().unwrap().contains("现金不足"));
+        assert_eq!(account.hold_available(MAIN.to_string()), 900);
+        assert_close(account.cash_available(), 10000.0 - 9000.0 - 5.0 - 0.09);
+    }
+
+    #[test]
+    fn send_order_clips_to_cash_and_hold() {
+        let mut account = mock_account(&[(MAIN, 300)], 10000.0);
+        // 买入不超过现金能承受的股数
+        account.send_order(STAR.to_string(), 2000, 10.0, OrderType::Buy);
+        assert_eq!(account.hold_available(STAR.to_string()), 999);
+        assert!(account.cash_available() >= 0.0);
+        // 卖出不超过持有股数，清仓后移除持仓
+        let fee = account.send_order(MAIN.to_string(), 500, 10.0, OrderType::Sell);
+        assert_close(fee, 5.0 + 0.03 + 3.0);
+        assert_eq!(account.hold_available(MAIN.to_string()), 0);
+        assert_eq!(account.hold_codes(), vec![STAR.to_string()]);
+        // 没有持仓时不成交，也不收费用
+        let fee = account.send_order(MAIN.to_string(), 100, 10.0, OrderType::Sell);
+        assert_close(fee, 0.0);
+    }
 }
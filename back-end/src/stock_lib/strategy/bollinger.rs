@@ -0,0 +1,103 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::strategy::{
+    col_position_shares, get_param, BarContext, IndicatorCache, ParamSpec, Strategy, StrategyData,
+    StrategyParams, TargetPosition,
+};
+use std::sync::Arc;
+use ta::indicators::{SimpleMovingAverage as SMA, StandardDeviation as SD};
+use ta::Next;
+
+/// 布林带均值回归策略
+/// 昨日收盘价跌破下轨买入，回到中轨上方清仓
+#[derive(Debug, Default)]
+pub struct BollingerReversionStrategy {
+    cache: Arc<IndicatorCache>, // 指标缓存
+    close: Vec<Option<f64>>,    // 收盘价
+    average: Vec<Option<f64>>,  // 中轨
+    upper: Vec<Option<f64>>,    // 上轨
+    lower: Vec<Option<f64>>,    // 下轨
+    position: f64,              // 买入时的仓位比例
+}
+
+impl BollingerReversionStrategy {
+    pub fn new(data: &StrategyData) -> Self {
+        BollingerReversionStrategy {
+            cache: data.cache.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 计算收盘价的均值和标准差，数据不足 period 根时为 None
+/// 布林带的上下轨 = 均值 ± 标准差 * 倍数，只有均值和标准差需要缓存
+pub fn col_mean_std(bars: &[StockPriceInfo], period: usize) -> Vec<Option<(f64, f64)>> {
+    let (mut sma, mut sd) = match (SMA::new(period), SD::new(period)) {
+        (Ok(sma), Ok(sd)) => (sma, sd),
+        _ => return vec![None; bars.len()],
+    };
+    let mut count = 0;
+    bars.iter()
+        .map(|bar| {
+            let close = bar.close?;
+            count += 1;
+            let mean = sma.next(close);
+            let std = sd.next(close);
+            (count >= period).then_some((mean, std))
+        })
+        .collect()
+}
+
+impl Strategy for BollingerReversionStrategy {
+    fn name(&self) -> &'static str {
+        "bollinger_reversion"
+    }
+    fn param_space(&self) -> Vec<ParamSpec> {
+        vec![
+            ParamSpec::new("period", 10.0, 40.0, true, 20.0),
+            ParamSpec::new("multiplier", 1.5, 3.0, false, 2.0),
+            ParamSpec::new("position", 0.5, 1.0, false, 0.95),
+        ]
+    }
+    fn init(&mut self, params: &StrategyParams, bars: &[StockPriceInfo]) {
+        let period = get_param(params, "period", 20.0) as usize;
+        let multiplier = get_param(params, "multiplier", 2.0);
+        self.position = get_param(params, "position", 0.95);
+        self.close = bars.iter().map(|bar| bar.close).collect();
+        let mean_std = self
+            .cache
+            .get_or_insert(format!("mean_std_{}", period), || {
+                col_mean_std(bars, period)
+            });
+        self.average = mean_std.iter().map(|v| v.map(|(mean, _)| mean)).collect();
+        self.upper = mean_std
+            .iter()
+            .map(|v| v.map(|(mean, std)| mean + std * multiplier))
+            .collect();
+        self.lower = mean_std
+            .iter()
+            .map(|v| v.map(|(mean, std)| mean - std * multiplier))
+            .collect();
+    }
+    fn on_bar(&mut self, ctx: &BarContext) -> Option<TargetPosition> {
+        // 用昨天的收盘价和布林带给出今天的信号
+        let prev = ctx.index.checked_sub(1)?;
+        let close = self.close[prev]?;
+        if ctx.hold == 0 && close < self.lower[prev]? {
+            return Some(TargetPosition::Shares(col_position_shares(
+                ctx,
+                self.position,
+            )));
+        }
+        if ctx.hold > 0 && close > self.average[prev]? {
+            return Some(TargetPosition::Flat);
+        }
+        None
+    }
+    fn indicators(&self, index: usize) -> Vec<(&'static str, Option<f64>)> {
+        vec![
+            ("boll_mid", self.average[index]),
+            ("boll_upper", self.upper[index]),
+            ("boll_lower", self.lower[index]),
+        ]
+    }
+}
@@ -0,0 +1,84 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::strategy::{
+    col_cross, col_position_shares, get_param, BarContext, Column, Cross, IndicatorCache,
+    ParamSpec, Strategy, StrategyData, StrategyParams, TargetPosition,
+};
+use std::sync::Arc;
+use ta::indicators::SimpleMovingAverage as SMA;
+use ta::Next;
+
+/// 双均线交叉策略
+/// 短期均线上穿长期均线（金叉）买入，下穿（死叉）清仓
+#[derive(Debug, Default)]
+pub struct MaCrossStrategy {
+    cache: Arc<IndicatorCache>, // 指标缓存
+    fast_ma: Column,            // 短期均线
+    slow_ma: Column,            // 长期均线
+    position: f64,              // 买入时的仓位比例
+}
+
+impl MaCrossStrategy {
+    pub fn new(data: &StrategyData) -> Self {
+        MaCrossStrategy {
+            cache: data.cache.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 计算收盘价的简单移动平均，数据不足 period 根或收盘价缺失时为 None
+pub fn col_sma(bars: &[StockPriceInfo], period: usize) -> Vec<Option<f64>> {
+    let mut sma = match SMA::new(period) {
+        Ok(sma) => sma,
+        Err(_) => return vec![None; bars.len()],
+    };
+    let mut count = 0;
+    bars.iter()
+        .map(|bar| {
+            let close = bar.close?;
+            count += 1;
+            let value = sma.next(close);
+            (count >= period).then_some(value)
+        })
+        .collect()
+}
+
+impl Strategy for MaCrossStrategy {
+    fn name(&self) -> &'static str {
+        "ma_cross"
+    }
+    fn param_space(&self) -> Vec<ParamSpec> {
+        vec![
+            ParamSpec::new("fast", 3.0, 20.0, true, 5.0),
+            ParamSpec::new("slow", 10.0, 60.0, true, 20.0),
+            ParamSpec::new("position", 0.5, 1.0, false, 0.95),
+        ]
+    }
+    fn init(&mut self, params: &StrategyParams, bars: &[StockPriceInfo]) {
+        let fast = get_param(params, "fast", 5.0) as usize;
+        let slow = get_param(params, "slow", 20.0) as usize;
+        self.fast_ma = self
+            .cache
+            .get_or_insert(format!("sma_{}", fast), || col_sma(bars, fast));
+        self.slow_ma = self
+            .cache
+            .get_or_insert(format!("sma_{}", slow), || col_sma(bars, slow));
+        self.position = get_param(params, "position", 0.95);
+    }
+    fn on_bar(&mut self, ctx: &BarContext) -> Option<TargetPosition> {
+        match col_cross(&self.fast_ma, &self.slow_ma, ctx.index)? {
+            Cross::Above if ctx.hold == 0 => Some(TargetPosition::Shares(col_position_shares(
+                ctx,
+                self.position,
+            ))),
+            Cross::Below if ctx.hold > 0 => Some(TargetPosition::Flat),
+            _ => None,
+        }
+    }
+    fn indicators(&self, index: usize) -> Vec<(&'static str, Option<f64>)> {
+        vec![
+            ("fast_ma", self.fast_ma[index]),
+            ("slow_ma", self.slow_ma[index]),
+        ]
+    }
+}
@@ -0,0 +1,94 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::strategy::{
+    col_cross, col_position_shares, get_param, BarContext, Cross, IndicatorCache, ParamSpec,
+    Strategy, StrategyData, StrategyParams, TargetPosition,
+};
+use std::sync::Arc;
+use ta::indicators::MovingAverageConvergenceDivergence as MACD;
+use ta::Next;
+
+/// MACD 的 (DIF, DEA) 两列
+type MacdLine = (Vec<Option<f64>>, Vec<Option<f64>>);
+
+/// MACD 信号线交叉策略
+/// DIF（macd）上穿 DEA（signal）买入，下穿清仓
+#[derive(Debug, Default)]
+pub struct MacdStrategy {
+    cache: Arc<IndicatorCache>, // 指标缓存
+    line: Arc<MacdLine>,        // (DIF, DEA)
+    position: f64,              // 买入时的仓位比例
+}
+
+impl MacdStrategy {
+    pub fn new(data: &StrategyData) -> Self {
+        MacdStrategy {
+            cache: data.cache.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 计算 MACD 的 DIF 和 DEA，慢线和信号线都走完一个周期前为 None
+pub fn col_macd(bars: &[StockPriceInfo], fast: usize, slow: usize, signal: usize) -> MacdLine {
+    let mut dif = vec![None; bars.len()];
+    let mut dea = vec![None; bars.len()];
+    let mut macd = match MACD::new(fast, slow, signal) {
+        Ok(macd) => macd,
+        Err(_) => return (dif, dea),
+    };
+    // 慢线和信号线都走完一个周期后指标才有意义
+    let warm_up = slow + signal;
+    let mut count = 0;
+    for (index, bar) in bars.iter().enumerate() {
+        if let Some(close) = bar.close {
+            count += 1;
+            let output = macd.next(close);
+            if count >= warm_up {
+                dif[index] = Some(output.macd);
+                dea[index] = Some(output.signal);
+            }
+        }
+    }
+    (dif, dea)
+}
+
+impl Strategy for MacdStrategy {
+    fn name(&self) -> &'static str {
+        "macd"
+    }
+    fn param_space(&self) -> Vec<ParamSpec> {
+        vec![
+            ParamSpec::new("fast", 8.0, 16.0, true, 12.0),
+            ParamSpec::new("slow", 20.0, 32.0, true, 26.0),
+            ParamSpec::new("signal", 5.0, 12.0, true, 9.0),
+            ParamSpec::new("position", 0.5, 1.0, false, 0.95),
+        ]
+    }
+    fn init(&mut self, params: &StrategyParams, bars: &[StockPriceInfo]) {
+        let fast = get_param(params, "fast", 12.0) as usize;
+        let slow = get_param(params, "slow", 26.0) as usize;
+        let signal = get_param(params, "signal", 9.0) as usize;
+        self.position = get_param(params, "position", 0.95);
+        self.line = self
+            .cache
+            .get_or_insert(format!("macd_{}_{}_{}", fast, slow, signal), || {
+                col_macd(bars, fast, slow, signal)
+            });
+    }
+    fn on_bar(&mut self, ctx: &BarContext) -> Option<TargetPosition> {
+        match col_cross(&self.line.0, &self.line.1, ctx.index)? {
+            Cross::Above if ctx.hold == 0 => Some(TargetPosition::Shares(col_position_shares(
+                ctx,
+                self.position,
+            ))),
+            Cross::Below if ctx.hold > 0 => Some(TargetPosition::Flat),
+            _ => None,
+        }
+    }
+    fn indicators(&self, index: usize) -> Vec<(&'static str, Option<f64>)> {
+        vec![
+            ("macd", self.line.0[index]),
+            ("macd_signal", self.line.1[index]),
+        ]
+    }
+}
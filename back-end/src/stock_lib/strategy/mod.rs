@@ -0,0 +1,203 @@
+pub mod bollinger;
+pub mod ma_cross;
+pub mod macd;
+pub mod rps_momentum;
+pub mod turtle;
+
+use crate::db::stock_info::StockPriceInfo;
+use rocket::serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+pub use bollinger::BollingerReversionStrategy;
+pub use ma_cross::MaCrossStrategy;
+pub use macd::MacdStrategy;
+pub use rps_momentum::RpsMomentumStrategy;
+pub use turtle::TurtleChannelStrategy;
+
+/// 策略参数：参数名 -> 参数值，整数参数同样用 f64 表示
+pub type StrategyParams = HashMap<String, f64>;
+
+/// 默认使用的策略
+pub const DEFAULT_STRATEGY: &str = "turtle_channel";
+
+/// 内置的全部策略名称
+pub const STRATEGY_NAMES: [&str; 5] = [
+    "turtle_channel",
+    "ma_cross",
+    "macd",
+    "bollinger_reversion",
+    "rps_momentum",
+];
+
+/// 策略除 K 线以外需要用到的数据，回测前按股票加载一次
+#[derive(Debug, Clone, Default)]
+pub struct StrategyData {
+    pub rps: Arc<HashMap<String, f64>>, // 交易日期 -> rps，来自 rps_values
+    pub cache: Arc<IndicatorCache>,     // 指标缓存，同一只股票的所有回测共享
+}
+
+/// 指标缓存
+/// 参数搜索时同一只股票会用不同的参数回测上万次，而指标只取决于个别参数（如均线周期），
+/// 按 "指标名_参数值" 缓存计算结果，相同参数值的指标只计算一次，可以在多个线程间共享
+#[derive(Default)]
+pub struct IndicatorCache {
+    columns: RwLock<HashMap<String, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl fmt::Debug for IndicatorCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self
+            .columns
+            .read()
+            .map(|columns| columns.len())
+            .unwrap_or(0);
+        write!(f, "IndicatorCache {{ len: {} }}", len)
+    }
+}
+
+impl IndicatorCache {
+    /// 读取缓存的指标，不存在时调用 compute 计算并写入缓存
+    /// key: 指标名和参数值，如 sma_20
+    /// compute: 计算指标
+    pub fn get_or_insert<T, F>(&self, key: String, compute: F) -> Arc<T>
+    where
+        T: Any + Send + Sync,
+        F: FnOnce() -> T,
+    {
+        if let Ok(columns) = self.columns.read() {
+            if let Some(value) = columns
+                .get(&key)
+                .and_then(|value| value.clone().downcast().ok())
+            {
+                return value;
+            }
+        }
+        // 多个线程可能同时计算同一个指标，结果相同，后写入的覆盖先写入的即可
+        let value = Arc::new(compute());
+        if let Ok(mut columns) = self.columns.write() {
+            columns.insert(key, value.clone());
+        }
+        value
+    }
+}
+
+/// 指标的一列数据，与 K 线一一对应
+pub type Column = Arc<Vec<Option<f64>>>;
+
+/// 策略的一个可调参数及其搜索范围
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ParamSpec {
+    pub name: String,
+    pub min: f64,      // 搜索范围下限（含）
+    pub max: f64,      // 搜索范围上限（不含）
+    pub integer: bool, // 是否为整数参数
+    pub default: f64,  // 不参与搜索时的默认值
+}
+
+impl ParamSpec {
+    pub fn new(name: &str, min: f64, max: f64, integer: bool, default: f64) -> Self {
+        ParamSpec {
+            name: name.to_string(),
+            min,
+            max,
+            integer,
+            default,
+        }
+    }
+}
+
+/// 策略在某根 K 线收盘后能看到的行情和账户状态
+pub struct BarContext<'a> {
+    pub index: usize,            // 当前 K 线的下标
+    pub bar: &'a StockPriceInfo, // 当前 K 线
+    pub hold: usize,             // 当前持股数
+    pub cash: f64,               // 可用现金
+    pub assets: f64,             // 按当前收盘价计算的总资产
+}
+
+/// 策略给出的目标仓位，回测引擎根据它与当前持仓的差值下单
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetPosition {
+    Flat,          // 清仓
+    Shares(usize), // 持有指定股数
+}
+
+impl TargetPosition {
+    /// 目标持股数
+    pub fn shares(&self) -> usize {
+        match self {
+            TargetPosition::Flat => 0,
+            TargetPosition::Shares(shares) => *shares,
+        }
+    }
+}
+
+/// 交易策略
+/// 回测时先用参数和全部 K 线调用一次 init 预计算指标，再按日期顺序对每根 K 线调用 on_bar
+pub trait Strategy: Send {
+    /// 策略名称
+    fn name(&self) -> &'static str;
+    /// 参数空间，供参数优化使用
+    fn param_space(&self) -> Vec<ParamSpec>;
+    /// 用参数和全部 K 线初始化策略
+    fn init(&mut self, params: &StrategyParams, bars: &[StockPriceInfo]);
+    /// 每根 K 线收盘后调用，返回 None 表示维持当前仓位
+    fn on_bar(&mut self, ctx: &BarContext) -> Option<TargetPosition>;
+    /// 第 index 根 K 线上的指标值，会写入回测结果便于画图
+    fn indicators(&self, _index: usize) -> Vec<(&'static str, Option<f64>)> {
+        vec![]
+    }
+}
+
+/// 读取参数，参数不存在时使用默认值
+pub fn get_param(params: &StrategyParams, name: &str, default: f64) -> f64 {
+    params.get(name).copied().unwrap_or(default)
+}
+
+/// 两条指标线在某根 K 线上的交叉
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cross {
+    Above, // 上穿
+    Below, // 下穿
+}
+
+/// 判断 fast 与 slow 在第 index - 1 根 K 线上是否交叉
+/// 和海龟策略一样只用昨天及之前的数据给出今天的信号
+pub fn col_cross(fast: &[Option<f64>], slow: &[Option<f64>], index: usize) -> Option<Cross> {
+    if index < 2 {
+        return None;
+    }
+    let (prev_fast, prev_slow) = (fast[index - 2]?, slow[index - 2]?);
+    let (cur_fast, cur_slow) = (fast[index - 1]?, slow[index - 1]?);
+    if prev_fast <= prev_slow && cur_fast > cur_slow {
+        Some(Cross::Above)
+    } else if prev_fast >= prev_slow && cur_fast < cur_slow {
+        Some(Cross::Below)
+    } else {
+        None
+    }
+}
+
+/// 按仓位比例计算目标持股数：总资产 * position / 收盘价
+pub fn col_position_shares(ctx: &BarContext, position: f64) -> usize {
+    match ctx.bar.close {
+        Some(close) if close > 0.0 => (ctx.assets * position / close).floor() as usize,
+        _ => ctx.hold,
+    }
+}
+
+/// 根据名称创建策略，名称不存在时返回 None
+pub fn create_strategy(name: &str, data: &StrategyData) -> Option<Box<dyn Strategy>> {
+    match name {
+        "turtle_channel" => Some(Box::new(TurtleChannelStrategy::new(data))),
+        "ma_cross" => Some(Box::new(MaCrossStrategy::new(data))),
+        "macd" => Some(Box::new(MacdStrategy::new(data))),
+        "bollinger_reversion" => Some(Box::new(BollingerReversionStrategy::new(data))),
+        "rps_momentum" => Some(Box::new(RpsMomentumStrategy::new(data))),
+        _ => None,
+    }
+}
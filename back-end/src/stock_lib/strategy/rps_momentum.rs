@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::strategy::{
+    col_position_shares, get_param, BarContext, Column, IndicatorCache, ParamSpec, Strategy,
+    StrategyData, StrategyParams, TargetPosition,
+};
+use ta::indicators::SimpleMovingAverage as SMA;
+use ta::Next;
+
+/// RPS 动量策略
+/// 平滑后的 RPS 不低于 entry 时买入，低于 exit 时清仓，
+/// 只有进入 rps_values 榜单的强势股才会被买入，不在榜单中的交易日 RPS 视为 0
+#[derive(Debug, Default)]
+pub struct RpsMomentumStrategy {
+    rps_map: Arc<HashMap<String, f64>>, // 交易日期 -> rps
+    cache: Arc<IndicatorCache>,         // 指标缓存
+    rps_ma: Column,                     // 平滑后的 rps
+    entry: f64,                         // 买入阈值
+    exit: f64,                          // 清仓阈值
+    position: f64,                      // 买入时的仓位比例
+}
+
+impl RpsMomentumStrategy {
+    pub fn new(data: &StrategyData) -> Self {
+        RpsMomentumStrategy {
+            rps_map: data.rps.clone(),
+            cache: data.cache.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 计算 RPS 的简单移动平均，不在榜单中的交易日 RPS 视为 0
+/// rps_map: 交易日期 -> rps
+pub fn col_rps_sma(
+    bars: &[StockPriceInfo],
+    rps_map: &HashMap<String, f64>,
+    period: usize,
+) -> Vec<Option<f64>> {
+    let mut sma = match SMA::new(period) {
+        Ok(sma) => sma,
+        Err(_) => return vec![None; bars.len()],
+    };
+    bars.iter()
+        .enumerate()
+        .map(|(index, bar)| {
+            let rps = bar
+                .trade_date
+                .as_ref()
+                .and_then(|date| rps_map.get(date))
+                .copied()
+                .unwrap_or(0.0);
+            let value = sma.next(rps);
+            (index + 1 >= period).then_some(value)
+        })
+        .collect()
+}
+
+impl Strategy for RpsMomentumStrategy {
+    fn name(&self) -> &'static str {
+        "rps_momentum"
+    }
+    fn param_space(&self) -> Vec<ParamSpec> {
+        vec![
+            ParamSpec::new("period", 1.0, 10.0, true, 3.0),
+            ParamSpec::new("entry", 80.0, 99.0, false, 90.0),
+            ParamSpec::new("exit", 50.0, 90.0, false, 80.0),
+            ParamSpec::new("position", 0.5, 1.0, false, 0.95),
+        ]
+    }
+    fn init(&mut self, params: &StrategyParams, bars: &[StockPriceInfo]) {
+        let period = get_param(params, "period", 3.0) as usize;
+        self.entry = get_param(params, "entry", 90.0);
+        self.exit = get_param(params, "exit", 80.0);
+        self.position = get_param(params, "position", 0.95);
+        let rps_map = self.rps_map.clone();
+        self.rps_ma = self.cache.get_or_insert(format!("rps_sma_{}", period), || {
+            col_rps_sma(bars, &rps_map, period)
+        });
+    }
+    fn on_bar(&mut self, ctx: &BarContext) -> Option<TargetPosition> {
+        // 用昨天的 RPS 给出今天的信号
+        let rps = self.rps_ma[ctx.index.checked_sub(1)?]?;
+        if ctx.hold == 0 && rps >= self.entry {
+            return Some(TargetPosition::Shares(col_position_shares(
+                ctx,
+                self.position,
+            )));
+        }
+        if ctx.hold > 0 && rps < self.exit {
+            return Some(TargetPosition::Flat);
+        }
+        None
+    }
+    fn indicators(&self, index: usize) -> Vec<(&'static str, Option<f64>)> {
+        vec![("rps_ma", self.rps_ma[index])]
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::strategy::{
+    get_param, BarContext, IndicatorCache, ParamSpec, Strategy, StrategyData, StrategyParams,
+    TargetPosition,
+};
+use ta::indicators::{AverageTrueRange as ATR, Maximum, Minimum};
+use ta::{DataItem, Next};
+
+/// 海龟通道突破策略
+/// 收盘价突破 N1 日最高价买入，跌破 N2 日最低价或触发 ATR 止盈止损卖出，
+/// 持仓期间按 1% 资产 / ATR 动态调整仓位，变化超过 adjust 股才调仓
+#[derive(Debug, Default)]
+pub struct TurtleChannelStrategy {
+    cache: Arc<IndicatorCache>,    // 指标缓存
+    signal_list: Vec<TradeSignal>, // 预先计算好的信号和指标
+    adjust_hold: usize,            // 动态持仓买入/卖出波动线
+    last_signal: Option<usize>,    // 最近一次出现的买卖信号
+}
+
+impl TurtleChannelStrategy {
+    pub fn new(data: &StrategyData) -> Self {
+        TurtleChannelStrategy {
+            cache: data.cache.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Strategy for TurtleChannelStrategy {
+    fn name(&self) -> &'static str {
+        "turtle_channel"
+    }
+    fn param_space(&self) -> Vec<ParamSpec> {
+        vec![
+            ParamSpec::new("n1", 5.0, 20.0, true, 14.0),
+            ParamSpec::new("n2", 1.0, 15.0, true, 2.0),
+            ParamSpec::new("win", 1.5, 2.5, false, 2.0),
+            ParamSpec::new("loss", 0.5, 1.5, false, 0.8),
+            ParamSpec::new("adjust", 0.0, 100.0, true, 0.0),
+        ]
+    }
+    fn init(&mut self, params: &StrategyParams, bars: &[StockPriceInfo]) {
+        let n1 = get_param(params, "n1", 14.0) as usize;
+        let n2 = get_param(params, "n2", 2.0) as usize;
+        let period = get_param(params, "period", 14.0) as usize;
+        let n1_high = self
+            .cache
+            .get_or_insert(format!("n1_high_{}", n1), || col_channel_high(bars, n1));
+        let n2_low = self
+            .cache
+            .get_or_insert(format!("n2_low_{}", n2), || col_channel_low(bars, n2));
+        let atr = self
+            .cache
+            .get_or_insert(format!("atr_{}", period), || col_atr(bars, period));
+        self.signal_list = col_trade_signal(
+            bars,
+            &n1_high,
+            &n2_low,
+            &atr,
+            get_param(params, "win", 2.0),
+            get_param(params, "loss", 0.8),
+        );
+        self.adjust_hold = get_param(params, "adjust", 0.0) as usize;
+        self.last_signal = None;
+    }
+    fn on_bar(&mut self, ctx: &BarContext) -> Option<TargetPosition> {
+        let today = &self.signal_list[ctx.index];
+        if today.signal.is_some() {
+            self.last_signal = today.signal;
+        }
+        // 是否持仓以账户的实际持股为准，订单被交易规则拒绝时不会误判
+        let holding = ctx.hold > 0;
+        if today.signal == Some(1) && !holding {
+            // 买入信号
+            let atr = today.atr_14.filter(|atr| *atr > 0.0).unwrap_or(1.0);
+            let buy_num = (ctx.cash.max(0.0) * 0.01 / atr).floor() as usize;
+            return Some(TargetPosition::Shares(ctx.hold + buy_num));
+        } else if self.last_signal == Some(0) && holding {
+            // 卖出信号，卖单被拒绝（如跌停）时之后每天继续卖出，直到清仓
+            return Some(TargetPosition::Flat);
+        }
+        // 动态计算持仓的股票数量
+        if let Some(atr) = today
+            .atr_14
+            .filter(|atr| holding && self.last_signal == Some(1) && *atr > 0.0)
+        {
+            let posit_num_wave = (ctx.assets * 0.01 / atr).floor() as usize;
+            // 波动后加仓
+            if posit_num_wave > ctx.hold.saturating_add(self.adjust_hold) {
+                return Some(TargetPosition::Shares(posit_num_wave));
+            }
+            // 波动后减仓，调整阈值大于持仓时不减仓
+            if ctx.hold > self.adjust_hold && posit_num_wave < ctx.hold - self.adjust_hold {
+                return Some(TargetPosition::Shares(posit_num_wave));
+            }
+        }
+        None
+    }
+    fn indicators(&self, index: usize) -> Vec<(&'static str, Option<f64>)> {
+        let today = &self.signal_list[index];
+        vec![
+            ("n1_high", today.n1_high),
+            ("n2_low", today.n2_low),
+            ("atr_14", today.atr_14),
+        ]
+    }
+}
+
+#[derive(Debug)]
+struct TradeSignal {
+    close: Option<f64>,    // 收盘价
+    signal: Option<usize>, // 交易信号
+    n1_high: Option<f64>,  // N1 日最高价
+    n2_low: Option<f64>,   // N2 日最低价
+    atr_14: Option<f64>,   // ATR 14 日
+}
+
+/// 整体数据向右移动一位，第一根 K 线沿用自身的值，让今天的信号根据昨天的数据进行计算
+fn shift_right(mut list: Vec<Option<f64>>) -> Vec<Option<f64>> {
+    if let Some(first) = list.first().copied() {
+        list.insert(0, first);
+        list.pop();
+    }
+    list
+}
+
+/// 计算 N 日最高价，已右移一位
+pub fn col_channel_high(df_stock: &[StockPriceInfo], n: usize) -> Vec<Option<f64>> {
+    let mut max = match Maximum::new(n) {
+        Ok(max) => max,
+        Err(_) => return vec![None; df_stock.len()],
+    };
+    shift_right(
+        df_stock
+            .iter()
+            .map(|stock| Some(max.next(stock.high.unwrap_or(0.0))))
+            .collect(),
+    )
+}
+
+/// 计算 N 日最低价，已右移一位
+pub fn col_channel_low(df_stock: &[StockPriceInfo], n: usize) -> Vec<Option<f64>> {
+    let mut min = match Minimum::new(n) {
+        Ok(min) => min,
+        Err(_) => return vec![None; df_stock.len()],
+    };
+    shift_right(
+        df_stock
+            .iter()
+            .map(|stock| Some(min.next(stock.low.unwrap_or(0.0))))
+            .collect(),
+    )
+}
+
+/// 计算 ATR，已右移一位
+pub fn col_atr(df_stock: &[StockPriceInfo], period: usize) -> Vec<Option<f64>> {
+    let mut atr = match ATR::new(period) {
+        Ok(atr) => atr,
+        Err(_) => return vec![None; df_stock.len()],
+    };
+    let atr_list = df_stock
+        .iter()
+        .map(|stock| {
+            let close = stock.close.unwrap_or(0.0);
+            let di = DataItem::builder()
+                .open(stock.open.unwrap_or(0.0))
+                .high(stock.high.unwrap_or(0.0))
+                .low(stock.low.unwrap_or(0.0))
+                .close(close)
+                .volume(stock.vol.unwrap_or(0.0))
+                .build()
+                .or_else(|_| {
+                    // 数据不合法（如最高价低于最低价）时按一字线处理，参数搜索中会反复调用，不逐条打印
+                    DataItem::builder()
+                        .open(close)
+                        .high(close)
+                        .low(close)
+                        .close(close)
+                        .volume(0.0)
+                        .build()
+                });
+            di.ok().map(|di| atr.next(&di))
+        })
+        .collect();
+    shift_right(atr_list)
+}
+
+/// 根据预先计算好的通道和 ATR 给出交易信号
+/// n1_high、n2_low、atr：已右移一位的指标，可以在不同的止盈止损参数间复用
+fn col_trade_signal(
+    df_stock: &[StockPriceInfo],
+    n1_high: &[Option<f64>],
+    n2_low: &[Option<f64>],
+    atr: &[Option<f64>],
+    n_win: f64,
+    n_loss: f64,
+) -> Vec<TradeSignal> {
+    let mut result: Vec<TradeSignal> = df_stock
+        .iter()
+        .enumerate()
+        .map(|(index, stock)| TradeSignal {
+            close: stock.close,
+            signal: None,
+            n1_high: n1_high[index],
+            n2_low: n2_low[index],
+            atr_14: atr[index],
+        })
+        .collect();
+    // 所有右移操作都是为了让今天的信号根据昨天的数据进行计算
+    // 买入价
+    let mut buy_price = 0.0;
+    // 没有收盘价的 K 线不产生信号，缺少 ATR 时不触发止盈止损，缺少通道时不触发突破
+    for today in result.iter_mut() {
+        let close = match today.close {
+            Some(close) => close,
+            None => continue,
+        };
+        // 今日收盘价大于 N1 日最高价
+        if today.n1_high.is_some_and(|n1_high| close > n1_high) {
+            buy_price = close;
+            today.signal = Some(1);
+        } else if buy_price > 0.0
+            && close < buy_price
+            && today
+                .atr_14
+                .is_some_and(|atr| close < buy_price - atr * n_loss)
+        {
+            // 今日收盘价小于买入价且小买入价减去 N2 日最低价的 ATR 乘以 n_loss
+            buy_price = 0.0;
+            today.signal = Some(0);
+        } else if buy_price > 0.0
+            && close > buy_price
+            && today
+                .atr_14
+                .is_some_and(|atr| close > buy_price + atr * n_win)
+        {
+            // 今日收盘价大于买入价且大于买入价加上 N1 日最高价的 ATR 乘以 n_win
+            buy_price = 0.0;
+            today.signal = Some(0);
+        } else if today.n2_low.is_some_and(|n2_low| close < n2_low) {
+            buy_price = 0.0;
+            today.signal = Some(0);
+        }
+    }
+    let mut signal_queue: VecDeque<Option<usize>> = result.iter().map(|v| v.signal).collect();
+    // 整体数据向右移动一位
+    signal_queue.push_front(None);
+    signal_queue.pop_back();
+    for (index, today) in result.iter_mut().enumerate() {
+        today.signal = signal_queue[index];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_lib::stock_trade::{execute_target, OperateRecord, ST_Account};
+    use crate::stock_lib::test_util::{mock_account, mock_bar};
+
+    const CODE: &str = "600000.SH";
+
+    /// 按给定的信号逐日回测，返回账户和被拒绝的订单，ATR 固定为 0.5，调仓阈值为 1000 股
+    /// bars: (收盘价, 昨收价, 信号)
+    fn run(bars: &[(f64, f64, Option<usize>)]) -> (ST_Account, Vec<OperateRecord>) {
+        let bar_list: Vec<StockPriceInfo> = bars
+            .iter()
+            .enumerate()
+            .map(|(day, (close, pre_close, _))| StockPriceInfo {
+                pre_close: Some(*pre_close),
+                ..mock_bar(CODE, day, *close)
+            })
+            .collect();
+        let mut strategy = TurtleChannelStrategy {
+            signal_list: bars
+                .iter()
+                .map(|(close, _, signal)| TradeSignal {
+                    close: Some(*close),
+                    signal: *signal,
+                    n1_high: None,
+                    n2_low: None,
+                    atr_14: Some(0.5),
+                })
+                .collect(),
+            adjust_hold: 1000,
+            ..Default::default()
+        };
+        let mut account = mock_account(&[], 100000.0);
+        let (mut operate, mut reject) = (vec![], vec![]);
+        for (index, today) in bar_list.iter().enumerate() {
+            account.new_trading_day();
+            account.mark_price(CODE.to_string(), today.close.unwrap());
+            let ctx = BarContext {
+                index,
+                bar: today,
+                hold: account.hold_available(CODE.to_string()),
+                cash: account.cash_available(),
+                assets: account.latest_assets(),
+            };
+            if let Some(target) = strategy.on_bar(&ctx) {
+                execute_target(&mut account, today, target, &mut operate, &mut reject);
+            }
+        }
+        (account, reject)
+    }
+
+    #[test]
+    fn rejected_sell_is_retried() {
+        // 第 3 天跌停卖出被拒绝，第 4 天重新卖出
+        let (account, reject) = run(&[
+            (10.0, 10.0, Some(1)),
+            (10.0, 10.0, None),
+            (9.0, 10.0, Some(0)),
+            (9.0, 9.0, None),
+        ]);
+        assert_eq!(reject.len(), 1);
+        assert!(reject[0].reason.as_ref().unwrap().contains("跌停"));
+        assert_eq!(account.hold_available(CODE.to_string()), 0);
+    }
+
+    #[test]
+    fn rejected_buy_does_not_add_later() {
+        // 第 1 天涨停买入被拒绝，之后没有买入信号时不按 ATR 加仓
+        let (account, reject) = run(&[
+            (11.0, 10.0, Some(1)),
+            (11.0, 11.0, None),
+            (11.0, 11.0, None),
+        ]);
+        assert_eq!(reject.len(), 1);
+        assert!(reject[0].reason.as_ref().unwrap().contains("涨停"));
+        assert_eq!(account.hold_available(CODE.to_string()), 0);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::stock_trade::{
+    ST_Account, DEFAULT_COMMISSION_COEFFICIENT, DEFAULT_TAX_COEFFICIENT,
+};
+use chrono::{Duration, NaiveDate};
+use std::collections::HashMap;
+
+/*
+* 单元测试共用的 K 线和账户构造函数
+*/
+
+/// 构造一根 K 线：开盘价、昨收价等于收盘价，最高价和最低价上下浮动 2%，成交量 10000 手
+/// code: 股票代码
+/// day: 从 2024-01-01 开始的第几天
+/// close: 收盘价
+pub(crate) fn mock_bar(code: &str, day: usize, close: f64) -> StockPriceInfo {
+    let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap() + Duration::days(day as i64);
+    StockPriceInfo {
+        ts_code: code.to_string(),
+        trade_date: Some(date.format("%Y%m%d").to_string()),
+        open: Some(close),
+        close: Some(close),
+        high: Some(close * 1.02),
+        low: Some(close * 0.98),
+        pre_close: Some(close),
+        vol: Some(10000.0),
+        change: Some(0.0),
+        pct_chg: Some(0.0),
+        amount: Some(close * 10000.0),
+    }
+}
+
+/// 按收盘价序列构造连续的 K 线
+/// code: 股票代码
+/// close_list: 每天的收盘价
+pub(crate) fn mock_series(code: &str, close_list: &[f64]) -> Vec<StockPriceInfo> {
+    close_list
+        .iter()
+        .enumerate()
+        .map(|(day, close)| mock_bar(code, day, *close))
+        .collect()
+}
+
+/// 生成默认费率和交易规则的账户
+/// hold: 初始持有股票数
+/// cash: 初始现金
+pub(crate) fn mock_account(hold: &[(&str, usize)], cash: f64) -> ST_Account {
+    ST_Account::new(
+        hold.iter()
+            .map(|(code, num)| (code.to_string(), *num))
+            .collect::<HashMap<String, usize>>(),
+        cash,
+        DEFAULT_COMMISSION_COEFFICIENT,
+        DEFAULT_TAX_COEFFICIENT,
+    )
+}
+
+/// 浮点数近似相等
+pub(crate) fn assert_close(left: f64, right: f64) {
+    assert!(
+        (left - right).abs() < 1e-6,
+        "left: {}, right: {}",
+        left,
+        right
+    );
+}
@@ -0,0 +1,289 @@
+use crate::db::connection::Db;
+use crate::db::stock_info::StockPriceInfo;
+use crate::stock_lib::engine::OrderEvent;
+use crate::stock_lib::get_stock_rps_list::{get_local_stock_price_data, get_local_stock_rps_data};
+use crate::stock_lib::metrics::{col_metrics, BacktestMetrics};
+use crate::stock_lib::missing_bar::{check_history, prepare_bars};
+use crate::stock_lib::optimizer::OptimizeConfig;
+use crate::stock_lib::stock_trade::{
+    col_param_space, optimize_params, run_params, OperateRecord, RunConfig, ST_Account, TradeResult,
+};
+use crate::stock_lib::strategy::{ParamSpec, StrategyData, StrategyParams};
+use crate::AppErrorEnum;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::task;
+use rocket_db_pools::Connection;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/*
+* 滚动窗口（walk-forward）验证
+*   把历史行情切成连续的窗口，每个窗口先在样本内区间搜索最优参数，再用这组参数回测紧接着的样本外区间
+*   1. 滚动窗口：样本内区间长度固定，随窗口向后平移；锚定窗口：样本内区间始终从第一根 K 线开始
+*   2. 样本外区间首尾相接，拼接后得到完整的样本外净值曲线，绩效指标按拼接后的净值计算
+*   3. 样本外回测时，之前的 K 线只用于计算指标，交易从样本外区间的第一天开始
+*   4. 每个窗口结束时持仓按收盘价折算为现金（不计平仓费用），作为下一个窗口的初始资金
+*   5. 参数稳定性：各窗口最优参数的均值、标准差和变异系数，变异系数越小参数越稳定
+*   6. 效率：样本外得分均值 / 样本内得分均值，越接近 1 说明样本内的表现越能延续到样本外
+*/
+
+// 定义一个通用的 Result 类型，默认错误类型为 AppErrorEnum
+type Result<T, E = AppErrorEnum> = std::result::Result<T, E>;
+
+/// 滚动窗口设置，长度的单位为 K 线根数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct WalkForwardConfig {
+    pub in_sample: usize,  // 样本内区间长度，默认 250，约一年
+    pub out_sample: usize, // 样本外区间长度，也是窗口平移的步长，默认 60，约一个季度
+    pub anchored: bool,    // 是否锚定样本内区间的起点
+    #[serde(skip)]
+    pub optimize: OptimizeConfig, // 每个样本内区间的参数优化设置，由请求中的优化参数生成
+}
+
+impl Default for WalkForwardConfig {
+    fn default() -> Self {
+        WalkForwardConfig {
+            in_sample: 250,
+            out_sample: 60,
+            anchored: false,
+            optimize: OptimizeConfig::default(),
+        }
+    }
+}
+
+/// 单个窗口的优化和验证结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WalkForwardWindow {
+    pub in_sample_start: Option<String>,     // 样本内开始日期
+    pub in_sample_end: Option<String>,       // 样本内结束日期
+    pub out_sample_start: Option<String>,    // 样本外开始日期
+    pub out_sample_end: Option<String>,      // 样本外结束日期
+    pub params: StrategyParams,              // 样本内的最优参数
+    pub in_sample_score: f64,                // 样本内得分
+    pub out_sample_score: f64,               // 样本外得分
+    pub in_sample_metrics: BacktestMetrics,  // 样本内绩效指标
+    pub out_sample_metrics: BacktestMetrics, // 样本外绩效指标
+}
+
+/// 单个参数在各窗口间的稳定性
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ParamStability {
+    pub name: String, // 参数名
+    pub mean: f64,    // 均值
+    pub std: f64,     // 标准差
+    pub cv: f64,      // 变异系数：标准差 / |均值|
+    pub min: f64,     // 最小值
+    pub max: f64,     // 最大值
+}
+
+/// 滚动窗口验证结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WalkForwardResult {
+    pub windows: Vec<WalkForwardWindow>,    // 各窗口的结果
+    pub df_stock: Vec<TradeResult>,         // 拼接后的样本外每日明细
+    pub operate_record: Vec<OperateRecord>, // 样本外交易记录
+    pub reject_record: Vec<OperateRecord>,  // 样本外未能成交的订单
+    pub order_events: Vec<OrderEvent>,      // 样本外订单事件，只在使用事件驱动引擎时生成
+    pub metrics: BacktestMetrics,           // 拼接后样本外净值的绩效指标
+    pub stability: Vec<ParamStability>,     // 参数稳定性
+    pub efficiency: f64,                    // 样本外得分均值 / 样本内得分均值
+}
+
+/// 计算各窗口最优参数的稳定性
+fn col_stability(windows: &[WalkForwardWindow]) -> Vec<ParamStability> {
+    let mut names: Vec<&String> = windows
+        .iter()
+        .flat_map(|window| window.params.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| {
+            let values: Vec<f64> = windows
+                .iter()
+                .filter_map(|window| window.params.get(name).copied())
+                .collect();
+            let len = values.len().max(1) as f64;
+            let mean = values.iter().sum::<f64>() / len;
+            let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / len).sqrt();
+            ParamStability {
+                name: name.clone(),
+                mean,
+                std,
+                cv: if mean != 0.0 { std / mean.abs() } else { 0.0 },
+                min: values.iter().copied().fold(f64::INFINITY, f64::min),
+                max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            }
+        })
+        .collect()
+}
+
+/// 对一只股票做滚动窗口验证
+/// wf_config: 滚动窗口和参数优化设置
+/// account: 初始账户
+/// run_config: 回测运行设置
+#[allow(clippy::too_many_arguments)]
+pub fn cal_walk_forward(
+    wf_config: &WalkForwardConfig,
+    account: &ST_Account,
+    run_config: &RunConfig,
+    df_stock: &[StockPriceInfo],
+    strategy_name: &str,
+    strategy_data: &StrategyData,
+    param_space: &[ParamSpec],
+    fixed_params: &StrategyParams,
+) -> Result<WalkForwardResult> {
+    if wf_config.in_sample == 0 || wf_config.out_sample == 0 {
+        return Err(AppErrorEnum::InvalidParam(
+            "样本内和样本外区间长度必须大于 0".to_string(),
+        ));
+    }
+    // 至少要有一根样本外 K 线
+    check_history(
+        df_stock.first().map_or("", |bar| bar.ts_code.as_str()),
+        df_stock.len(),
+        wf_config.in_sample + 1,
+    )?;
+    let config = &wf_config.optimize;
+    let init_cash = account.latest_assets();
+    let date_of = |index: usize| df_stock[index].trade_date.clone();
+    let mut windows = vec![];
+    let mut df_result: Vec<TradeResult> = vec![];
+    let mut total_assets: Vec<f64> = vec![];
+    let mut position_value: Vec<f64> = vec![];
+    let mut operate_record: Vec<OperateRecord> = vec![];
+    let mut reject_record: Vec<OperateRecord> = vec![];
+    let mut order_events: Vec<OrderEvent> = vec![];
+    let mut cash = init_cash;
+    let mut out_start = wf_config.in_sample;
+    while out_start < df_stock.len() {
+        let in_start = if wf_config.anchored {
+            0
+        } else {
+            out_start - wf_config.in_sample
+        };
+        let out_end = (out_start + wf_config.out_sample).min(df_stock.len());
+        // 指标缓存按参数值缓存整段行情的指标，每段行情都要用新的缓存
+        let slice_data = || StrategyData {
+            rps: strategy_data.rps.clone(),
+            ..StrategyData::default()
+        };
+        // 样本内只能看到样本内区间的行情
+        let top_params = optimize_params(
+            config,
+            account,
+            run_config,
+            &df_stock[in_start..out_start],
+            strategy_name,
+            &slice_data(),
+            param_space,
+            fixed_params,
+        )?
+        .top_params;
+        let best = top_params
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppErrorEnum::InvalidParam("样本内区间没有可用的参数".to_string()))?;
+        // 样本外用窗口开始前的全部行情计算指标，从样本外第一天开始交易
+        let window_account = account.empty_with_cash(cash);
+        let (run, out_metrics) = run_params(
+            &df_stock[in_start..out_end],
+            strategy_name,
+            &slice_data(),
+            &window_account,
+            run_config,
+            &best.params,
+            out_start - in_start,
+            true,
+        );
+        cash = run.total_assets.last().copied().unwrap_or(cash);
+        windows.push(WalkForwardWindow {
+            in_sample_start: date_of(in_start),
+            in_sample_end: date_of(out_start - 1),
+            out_sample_start: date_of(out_start),
+            out_sample_end: date_of(out_end - 1),
+            params: best.params,
+            in_sample_score: best.score,
+            out_sample_score: config.objective.score(&out_metrics),
+            in_sample_metrics: best.metrics,
+            out_sample_metrics: out_metrics,
+        });
+        df_result.extend(run.trade_result);
+        total_assets.extend(run.total_assets);
+        position_value.extend(run.position_value);
+        operate_record.extend(run.operate_record);
+        reject_record.extend(run.reject_record);
+        order_events.extend(run.order_events);
+        out_start = out_end;
+    }
+    let metrics = col_metrics(&total_assets, &position_value, &operate_record, init_cash);
+    let len = windows.len().max(1) as f64;
+    let in_score = windows.iter().map(|w| w.in_sample_score).sum::<f64>() / len;
+    let out_score = windows.iter().map(|w| w.out_sample_score).sum::<f64>() / len;
+    Ok(WalkForwardResult {
+        stability: col_stability(&windows),
+        efficiency: if in_score != 0.0 {
+            out_score / in_score
+        } else {
+            0.0
+        },
+        windows,
+        df_stock: df_result,
+        operate_record,
+        reject_record,
+        order_events,
+        metrics,
+    })
+}
+
+/// 对一只股票做滚动窗口验证
+/// code: 股票代码
+/// account: 初始账户
+/// run_config: 回测运行设置
+/// strategy_name: 策略名称
+/// params: 固定的策略参数，不参与参数优化
+/// param_ranges: 参与参数优化的范围，参数名 -> (下限, 上限)
+/// wf_config: 滚动窗口和参数优化设置
+#[allow(clippy::too_many_arguments)]
+pub async fn simulate_walk_forward(
+    mut db: Connection<Db>,
+    code: String,
+    account: ST_Account,
+    run_config: RunConfig,
+    strategy_name: &str,
+    params: StrategyParams,
+    param_ranges: HashMap<String, (f64, f64)>,
+    wf_config: &WalkForwardConfig,
+) -> Result<WalkForwardResult> {
+    let param_space = col_param_space(strategy_name, &params, &param_ranges)?;
+    let df_stock = prepare_bars(
+        &code,
+        get_local_stock_price_data(&mut db, code.clone()).await?,
+        run_config.missing_bar,
+    )?;
+    let strategy_data = StrategyData {
+        rps: Arc::new(get_local_stock_rps_data(&mut db, code).await?),
+        ..StrategyData::default()
+    };
+    // 每个窗口都要做一次参数搜索，放到阻塞线程中执行
+    let (wf_config, strategy_name) = (wf_config.clone(), strategy_name.to_string());
+    let result = task::spawn_blocking(move || {
+        cal_walk_forward(
+            &wf_config,
+            &account,
+            &run_config,
+            &df_stock,
+            &strategy_name,
+            &strategy_data,
+            &param_space,
+            &params,
+        )
+    })
+    .await??;
+    Ok(result)
+}
@@ -0,0 +1,201 @@
+//! 缺失 K 线的处理：空序列、单根 K 线和中间有缺失的序列
+//! 运行：cargo test --test missing_bar
+use back_end::db::stock_info::StockPriceInfo;
+use back_end::stock_lib::missing_bar::{
+    fill_missing_bars, prepare_bars, MissingBarPolicy, MIN_HISTORY_BARS,
+};
+use back_end::stock_lib::optimizer::OptimizeConfig;
+use back_end::stock_lib::stock_trade::{
+    cal_ndayavg_mc, col_param_space, RunConfig, ST_Account, DEFAULT_COMMISSION_COEFFICIENT,
+    DEFAULT_TAX_COEFFICIENT,
+};
+use back_end::stock_lib::strategy::{
+    create_strategy, BarContext, StrategyData, StrategyParams, STRATEGY_NAMES,
+};
+use back_end::AppErrorEnum;
+use std::collections::HashMap;
+
+const CODE: &str = "000001.SZ";
+
+/// 构造一根 K 线，close 为空时其他价格字段也为空
+fn mock_bar(day: usize, close: Option<f64>) -> StockPriceInfo {
+    StockPriceInfo {
+        ts_code: CODE.to_string(),
+        trade_date: Some(format!("2024{:04}", day)),
+        open: close,
+        close,
+        high: close.map(|close| close * 1.02),
+        low: close.map(|close| close * 0.98),
+        pre_close: close,
+        vol: close.map(|_| 10000.0),
+        change: close.map(|_| 0.0),
+        pct_chg: close.map(|_| 0.0),
+        amount: close.map(|close| close * 10000.0),
+    }
+}
+
+/// 先涨后跌的 60 根 K 线，gaps 中的下标没有收盘价
+fn mock_series(gaps: &[usize]) -> Vec<StockPriceInfo> {
+    (0..60)
+        .map(|day| {
+            let trend = if day < 30 { day } else { 60 - day };
+            let close = 10.0 + trend as f64 * 0.3;
+            mock_bar(day, (!gaps.contains(&day)).then_some(close))
+        })
+        .collect()
+}
+
+fn new_account() -> ST_Account {
+    ST_Account::new(
+        HashMap::new(),
+        100000.0,
+        DEFAULT_COMMISSION_COEFFICIENT,
+        DEFAULT_TAX_COEFFICIENT,
+    )
+}
+
+fn assert_insufficient(bars: Vec<StockPriceInfo>, policy: MissingBarPolicy, expect_bars: usize) {
+    match prepare_bars(CODE, bars, policy) {
+        Err(AppErrorEnum::InsufficientHistory {
+            code,
+            bars,
+            required,
+        }) => {
+            assert_eq!(code, CODE);
+            assert_eq!(bars, expect_bars);
+            assert_eq!(required, MIN_HISTORY_BARS);
+        }
+        other => panic!(
+            "expected InsufficientHistory, got {:?}",
+            other.map(|v| v.len())
+        ),
+    }
+}
+
+#[test]
+fn empty_series_is_insufficient() {
+    assert!(fill_missing_bars(vec![], MissingBarPolicy::Skip).is_empty());
+    assert_insufficient(vec![], MissingBarPolicy::Skip, 0);
+    assert_insufficient(vec![], MissingBarPolicy::ForwardFill, 0);
+}
+
+#[test]
+fn single_bar_is_insufficient() {
+    assert_insufficient(vec![mock_bar(0, Some(10.0))], MissingBarPolicy::Skip, 1);
+    // 只有一根有收盘价的 K 线，之后的缺失 K 线跳过后仍然不足
+    assert_insufficient(
+        vec![mock_bar(0, Some(10.0)), mock_bar(1, None)],
+        MissingBarPolicy::Skip,
+        1,
+    );
+    // 全部缺失时前值填充也无从填起
+    assert_insufficient(
+        vec![mock_bar(0, None), mock_bar(1, None)],
+        MissingBarPolicy::ForwardFill,
+        0,
+    );
+}
+
+#[test]
+fn skip_drops_bars_without_close() {
+    let bars = prepare_bars(CODE, mock_series(&[0, 10, 11]), MissingBarPolicy::Skip).unwrap();
+    assert_eq!(bars.len(), 57);
+    assert!(bars.iter().all(|bar| bar.close.is_some()));
+    let dates: Vec<&str> = bars
+        .iter()
+        .filter_map(|bar| bar.trade_date.as_deref())
+        .collect();
+    assert!(!dates.contains(&"20240010"));
+    assert!(!dates.contains(&"20240011"));
+}
+
+#[test]
+fn forward_fill_uses_previous_close() {
+    let raw = mock_series(&[0, 10, 11]);
+    let prev_close = raw[9].close;
+    let bars = prepare_bars(CODE, raw, MissingBarPolicy::ForwardFill).unwrap();
+    // 第一根 K 线之前没有收盘价可以填充，被丢弃
+    assert_eq!(bars.len(), 59);
+    let filled: Vec<&StockPriceInfo> = bars
+        .iter()
+        .filter(|bar| matches!(bar.trade_date.as_deref(), Some("20240010" | "20240011")))
+        .collect();
+    assert_eq!(filled.len(), 2);
+    for bar in filled {
+        assert_eq!(bar.close, prev_close);
+        assert_eq!(bar.open, prev_close);
+        assert_eq!(bar.high, prev_close);
+        assert_eq!(bar.low, prev_close);
+        assert_eq!(bar.vol, Some(0.0));
+    }
+}
+
+#[test]
+fn missing_ohlc_is_filled_from_close() {
+    let mut bar = mock_bar(0, Some(10.0));
+    bar.open = None;
+    bar.high = None;
+    bar.low = None;
+    let bars = fill_missing_bars(vec![bar], MissingBarPolicy::Skip);
+    assert_eq!(bars[0].open, Some(10.0));
+    assert_eq!(bars[0].high, Some(10.0));
+    assert_eq!(bars[0].low, Some(10.0));
+}
+
+#[test]
+fn strategies_do_not_panic_on_gaps() {
+    // 不清洗直接交给策略，空的收盘价和指标都不能导致 panic
+    let bars = mock_series(&[0, 1, 10, 11, 59]);
+    for name in STRATEGY_NAMES {
+        let data = StrategyData::default();
+        let mut strategy = create_strategy(name, &data).unwrap();
+        strategy.init(&StrategyParams::new(), &bars);
+        for (index, bar) in bars.iter().enumerate() {
+            let ctx = BarContext {
+                index,
+                bar,
+                hold: 0,
+                cash: 100000.0,
+                assets: 100000.0,
+            };
+            strategy.on_bar(&ctx);
+            strategy.indicators(index);
+        }
+    }
+}
+
+#[test]
+fn backtest_runs_on_gap_series() {
+    let config = OptimizeConfig {
+        iterations: 8,
+        seed: 1,
+        ..OptimizeConfig::default()
+    };
+    let params = StrategyParams::new();
+    let param_space = col_param_space("turtle_channel", &params, &HashMap::new()).unwrap();
+    let raw = mock_series(&[0, 10, 11, 59]);
+    for bars in [
+        // 不清洗时，没有收盘价的 K 线在回测中被跳过
+        raw.clone(),
+        prepare_bars(CODE, raw.clone(), MissingBarPolicy::Skip).unwrap(),
+        prepare_bars(CODE, raw, MissingBarPolicy::ForwardFill).unwrap(),
+    ] {
+        let result = cal_ndayavg_mc(
+            &config,
+            &new_account(),
+            &RunConfig::default(),
+            &bars,
+            "turtle_channel",
+            &StrategyData::default(),
+            &param_space,
+            &params,
+        )
+        .unwrap();
+        let expect_len = bars.iter().filter(|bar| bar.close.is_some()).count();
+        assert_eq!(result.df_stock.len(), expect_len);
+        assert!(result
+            .df_stock
+            .iter()
+            .all(|day| day.total_assets.is_some_and(|assets| assets > 0.0)));
+    }
+}
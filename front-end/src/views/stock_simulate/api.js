@@ -2,3 +2,11 @@ import request from '@/utils/request';
 
 export const test_api = (data) => request({ method: 'POST', url: '/stock/simulate', data });
 
+
+export const backtest_list_api = (params) => request({ method: 'GET', url: '/stock/backtests', params });
+
+export const backtest_detail_api = (id) => request({ method: 'GET', url: `/stock/backtests/${id}` });
+
+export const backtest_delete_api = (data) => request({ method: 'POST', url: '/stock/backtests/delete', data });
+
+export const backtest_compare_api = (data) => request({ method: 'POST', url: '/stock/backtests/compare', data });